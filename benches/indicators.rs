@@ -0,0 +1,97 @@
+use chrono::{DateTime, Duration, Utc};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+use whiplash::config::layers::{Layer, Layers};
+use whiplash::config::Config;
+use whiplash::stream_monitor::atr::MovingAverage;
+use whiplash::stream_monitor::buffer::BufferNode;
+use whiplash::stream_monitor::indicator::IndicatorState;
+use whiplash::stream_monitor::regime::MarketRegime;
+use whiplash::stream_monitor::SymbolData;
+
+const WINDOW_SECONDS: usize = 10;
+// binance pushes kline updates every 250ms
+const MESSAGE_INTERVAL_MS: i64 = 250;
+
+fn node(ts: DateTime<Utc>, tick: i64) -> BufferNode {
+    BufferNode {
+        value: (tick % 240) as f64 * 1000.,
+        ts,
+        recv_ts: ts,
+        confirmed: tick % 240 == 239,
+        close_price: 100. + ((tick * 7) % 13) as f64 * 0.01,
+    }
+}
+
+// one message per symbol followed by an evaluation, for hundreds of symbols
+fn bench_ingest_and_evaluate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ingest_and_evaluate");
+    for symbols in [100, 300, 500] {
+        group.throughput(Throughput::Elements(symbols as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(symbols), &symbols, |b, &symbols| {
+            let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+            let mut states: Vec<IndicatorState> = (0..symbols)
+                .map(|_| IndicatorState::new(WINDOW_SECONDS, MovingAverage::Ema, 60))
+                .collect();
+            // fill the windows first so that the steady state is measured
+            let mut tick = 0;
+            while tick < 4 * 60 {
+                let ts = start + Duration::milliseconds(tick * MESSAGE_INTERVAL_MS);
+                for state in states.iter_mut() {
+                    state.update(&node(ts, tick));
+                }
+                tick += 1;
+            }
+            b.iter(|| {
+                let ts = start + Duration::milliseconds(tick * MESSAGE_INTERVAL_MS);
+                let n = node(ts, tick);
+                for state in states.iter_mut() {
+                    state.update(&n);
+                    black_box(state.atr.check(0.35, 0.8));
+                    black_box(state.volume.sum());
+                }
+                tick += 1;
+            });
+        });
+    }
+    group.finish();
+}
+
+// the same through the symbol's own evaluation, everything a tick of the event mode does but the logging
+fn bench_symbol_evaluate(c: &mut Criterion) {
+    let mut layers = Layers::new(Config::defaults());
+    layers.merge(serde_yaml::from_str("atr_window_seconds: 10\nmin_vol_usdt: 50000\nsymbols: [ETHUSDT]").unwrap(), Layer::File);
+    let config = Config::check_value(layers.value).0.expect("valid bench config");
+    let settings = config.symbol_settings(&config.symbols[0]);
+    let regime = MarketRegime::default();
+
+    let mut group = c.benchmark_group("symbol_evaluate");
+    for symbols in [100, 300, 500] {
+        group.throughput(Throughput::Elements(symbols as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(symbols), &symbols, |b, &symbols| {
+            let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+            let mut handlers: Vec<SymbolData> = (0..symbols).map(|_| SymbolData::new("ETHUSDT", settings.clone())).collect();
+            // a full buffer, the evaluation mustn't depend on its size
+            let mut tick = 0;
+            while tick < 4 * 60 {
+                let ts = start + Duration::milliseconds(tick * MESSAGE_INTERVAL_MS);
+                for handler in handlers.iter_mut() {
+                    handler.ingest(node(ts, tick));
+                }
+                tick += 1;
+            }
+            b.iter(|| {
+                let ts = start + Duration::milliseconds(tick * MESSAGE_INTERVAL_MS);
+                for handler in handlers.iter_mut() {
+                    handler.ingest(node(ts, tick));
+                    black_box(handler.evaluate(Some(ts), Some(&regime), None, false));
+                }
+                tick += 1;
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_ingest_and_evaluate, bench_symbol_evaluate);
+criterion_main!(benches);
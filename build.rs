@@ -0,0 +1,6 @@
+// compiles the grpc api, with the vendored protoc so that no system install is needed
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    tonic_prost_build::compile_protos("proto/whiplash.proto")?;
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use log::LevelFilter;
+use std::path::PathBuf;
+use whiplash::config;
+use whiplash::logging::{LogFormat, LoggingConfig};
+
+/// Volatility impulse monitor for crypto futures markets
+#[derive(Debug, Parser)]
+#[command(name = "whiplash", version, about)]
+pub struct Cli {
+    /// Path to the config file [default: ./config.yaml, which may be missing if the env carries the config]
+    #[arg(short, long, global = true, env = "WHIPLASH_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Log level (error, warn, info, debug, trace), overrides the config and RUST_LOG
+    #[arg(long, global = true)]
+    pub log_level: Option<LevelFilter>,
+
+    /// Log output format, overrides the config
+    #[arg(long, global = true, value_enum)]
+    pub log_format: Option<LogFormat>,
+
+    /// Comma-separated symbols to monitor instead of the ones in the config file
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub symbols: Option<Vec<String>>,
+
+    /// Override a config value, e.g. `--set atr_threshold=0.3`, beats the file and the env
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    pub set: Vec<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    // defaults, file, WHIPLASH_* env and the flags above, in that order
+    pub fn config_sources(&self) -> config::Sources {
+        let mut set = self.set.clone();
+        if let Some(level) = self.log_level {
+            set.push(format!("logging.level={}", level.as_str().to_lowercase()));
+        }
+        if let Some(format) = self.log_format {
+            set.push(format!("logging.format={}", format.to_possible_value().unwrap().get_name()));
+        }
+        config::Sources {
+            path: self.config.clone().unwrap_or_else(|| PathBuf::from(config::DEFAULT_CONFIG_PATH)),
+            path_required: self.config.is_some(),
+            symbols: self.symbols.clone(),
+            set,
+        }
+    }
+
+    // used when the config can't be loaded, so that the errors are logged the way they were asked for
+    pub fn logging(&self) -> LoggingConfig {
+        LoggingConfig {
+            level: self.log_level.map(|level| level.as_str().to_lowercase()),
+            format: self.log_format.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Monitor the configured symbols (default)
+    Run,
+    /// Validate the config and exit
+    CheckConfig {
+        /// Print the effective config along with the source of every value
+        #[arg(long)]
+        effective: bool,
+    },
+    /// Monitor the configured symbols and record every raw frame to a file
+    Record {
+        /// File to write the recorded frames to
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Replay a recording through the monitor at the recorded pace
+    Replay {
+        /// Recording written by `record`
+        #[arg(short, long)]
+        input: PathBuf,
+        /// Replay speed multiplier
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+    },
+    /// Run a recording through the monitor as fast as possible and report the signals
+    Backtest {
+        /// Recording written by `record`
+        #[arg(short, long)]
+        input: PathBuf,
+    },
+    /// List the symbols that would be monitored
+    Symbols,
+    /// Monitor the configured symbols on a live dashboard, logging only to `logging.file`
+    Tui,
+}
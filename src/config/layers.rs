@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use serde_yaml::{Mapping, Value};
+use super::validate::{Issue, Severity};
+use crate::error::ConfigError;
+
+pub const ENV_PREFIX: &str = "WHIPLASH_";
+// env vars with the prefix that aren't config values
+const ENV_RESERVED: [&str; 1] = ["WHIPLASH_CONFIG"];
+
+// where a config value came from, later layers win
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Default,
+    File,
+    Env,
+    Cli,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layer::Default => write!(f, "default"),
+            Layer::File => write!(f, "file"),
+            Layer::Env => write!(f, "env"),
+            Layer::Cli => write!(f, "cli"),
+        }
+    }
+}
+
+// the merged raw config, along with the layer every value came from
+// values are merged per key, nested mappings are merged recursively, lists are replaced as a whole
+#[derive(Debug, Clone)]
+pub struct Layers {
+    pub value: Value,
+    pub sources: BTreeMap<String, Layer>,
+    pub issues: Vec<Issue>,
+}
+
+impl Layers {
+    pub fn new(defaults: Value) -> Self {
+        let mut layers = Layers {
+            value: Value::Mapping(Mapping::new()),
+            sources: BTreeMap::new(),
+            issues: vec![],
+        };
+        layers.merge(defaults, Layer::Default);
+        layers
+    }
+
+    pub fn merge(&mut self, value: Value, layer: Layer) {
+        merge_into(&mut self.value, value, "", layer, &mut self.sources);
+    }
+
+    fn set(&mut self, path: &[String], value: Value, layer: Layer) {
+        let nested = path.iter().rev().fold(value, |value, key| {
+            let mut mapping = Mapping::new();
+            mapping.insert(Value::String(key.clone()), value);
+            Value::Mapping(mapping)
+        });
+        self.merge(nested, layer);
+    }
+
+    fn issue(&mut self, severity: Severity, path: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(Issue { severity, path: path.into(), message: message.into() });
+    }
+
+    // a missing file is fine unless it was asked for explicitly, the env can carry the whole config
+    pub fn file(&mut self, path: &Path, required: bool) -> Result<(), ConfigError> {
+        if !required && !path.exists() {
+            return Ok(());
+        }
+        let config_str = fs::read_to_string(path).map_err(|source| ConfigError::Read { path: path.to_path_buf(), source })?;
+        let raw: Value = serde_yaml::from_str(&config_str).map_err(|source| ConfigError::Parse { path: path.to_path_buf(), source })?;
+        match raw {
+            // an empty file
+            Value::Null => {}
+            Value::Mapping(_) => self.merge(raw, Layer::File),
+            _ => self.issue(Severity::Error, "<root>", "expected a mapping of config keys"),
+        }
+        Ok(())
+    }
+
+    // WHIPLASH_ATR_THRESHOLD=0.3 sets `atr_threshold`, a double underscore separates nested keys
+    // symbols are either comma-separated in WHIPLASH_SYMBOLS, or indexed as WHIPLASH_SYMBOLS_0, WHIPLASH_SYMBOLS_1...
+    pub fn env(&mut self, vars: impl IntoIterator<Item = (String, String)>, known_keys: &[&str]) {
+        let mut vars: Vec<(String, String)> = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX) && !ENV_RESERVED.contains(&name.as_str()))
+            .collect();
+        vars.sort();
+
+        let mut indexed_symbols: BTreeMap<usize, (String, String)> = BTreeMap::new();
+        let mut listed_symbols = None;
+        for (name, raw) in vars {
+            let key = name[ENV_PREFIX.len()..].to_lowercase();
+            if key == "symbols" {
+                listed_symbols = Some(split_list(&raw));
+                continue;
+            }
+            if let Some(index) = key.strip_prefix("symbols_") {
+                match index.parse::<usize>() {
+                    Ok(index) => {
+                        indexed_symbols.insert(index, (name.clone(), raw));
+                    }
+                    Err(_) => self.issue(Severity::Error, format!("env {}", name), "expected WHIPLASH_SYMBOLS_<index>"),
+                }
+                continue;
+            }
+            let path: Vec<String> = key.split("__").map(str::to_string).collect();
+            if !known_keys.contains(&path[0].as_str()) {
+                self.issue(Severity::Warning, format!("env {}", name), "does not match any config key, ignored");
+                continue;
+            }
+            self.set(&path, parse_scalar(&raw), Layer::Env);
+        }
+
+        if !indexed_symbols.is_empty() {
+            if listed_symbols.is_some() {
+                self.issue(Severity::Warning, "env WHIPLASH_SYMBOLS", "ignored in favor of the indexed WHIPLASH_SYMBOLS_<index> variables");
+            }
+            let symbols = indexed_symbols.into_values().map(|(_, symbol)| Value::String(symbol.trim().to_string())).collect();
+            self.set(&["symbols".to_string()], Value::Sequence(symbols), Layer::Env);
+        } else if let Some(symbols) = listed_symbols {
+            self.set(&["symbols".to_string()], symbols, Layer::Env);
+        }
+    }
+
+    // the process environment may hold anything, what isn't unicode can't be one of ours and is skipped
+    pub fn env_os(&mut self, vars: impl IntoIterator<Item = (OsString, OsString)>, known_keys: &[&str]) {
+        let mut converted = vec![];
+        for (name, raw) in vars {
+            match (name.into_string(), raw.into_string()) {
+                (Ok(name), Ok(raw)) => converted.push((name, raw)),
+                (Ok(name), Err(_)) if name.starts_with(ENV_PREFIX) => {
+                    self.issue(Severity::Warning, format!("env {}", name), "not valid unicode, ignored")
+                }
+                _ => {}
+            }
+        }
+        self.env(converted, known_keys);
+    }
+
+    // `--symbols` and `--set key=value` flags, dots separate nested keys
+    pub fn cli(&mut self, symbols: Option<&[String]>, set: &[String]) {
+        for assignment in set {
+            match assignment.split_once('=') {
+                Some((key, raw)) if !key.trim().is_empty() => {
+                    let path: Vec<String> = key.trim().split('.').map(str::to_string).collect();
+                    self.set(&path, parse_scalar(raw), Layer::Cli);
+                }
+                _ => self.issue(Severity::Error, format!("--set {}", assignment), "expected KEY=VALUE"),
+            }
+        }
+        if let Some(symbols) = symbols {
+            let symbols = symbols.iter().map(|symbol| Value::String(symbol.clone())).collect();
+            self.set(&["symbols".to_string()], Value::Sequence(symbols), Layer::Cli);
+        }
+    }
+
+    // every leaf of the merged config as `path = value (layer)`
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![];
+        describe_into(&self.value, "", &self.sources, &mut lines);
+        lines
+    }
+}
+
+fn merge_into(target: &mut Value, value: Value, prefix: &str, layer: Layer, sources: &mut BTreeMap<String, Layer>) {
+    match (target, value) {
+        (Value::Mapping(target), Value::Mapping(mapping)) => {
+            for (key, value) in mapping {
+                let path = join(prefix, &key_name(&key));
+                match target.get_mut(&key) {
+                    Some(existing) if existing.is_mapping() && value.is_mapping() => {
+                        merge_into(existing, value, &path, layer, sources);
+                    }
+                    _ => {
+                        mark(&value, &path, layer, sources);
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, value) => {
+            mark(&value, prefix, layer, sources);
+            *target = value;
+        }
+    }
+}
+
+// records the layer of every leaf under `path`, replacing whatever was there before
+fn mark(value: &Value, path: &str, layer: Layer, sources: &mut BTreeMap<String, Layer>) {
+    let nested = format!("{}.", path);
+    sources.retain(|key, _| key != path && !key.starts_with(&nested));
+    match value {
+        Value::Mapping(mapping) if !mapping.is_empty() => {
+            for (key, value) in mapping {
+                mark(value, &join(path, &key_name(key)), layer, sources);
+            }
+        }
+        _ => {
+            sources.insert(path.to_string(), layer);
+        }
+    }
+}
+
+fn describe_into(value: &Value, path: &str, sources: &BTreeMap<String, Layer>, lines: &mut Vec<String>) {
+    match value {
+        Value::Mapping(mapping) if !mapping.is_empty() => {
+            for (key, value) in mapping {
+                describe_into(value, &join(path, &key_name(key)), sources, lines);
+            }
+        }
+        _ => {
+            let rendered = serde_yaml::to_string(value).unwrap_or_default();
+            let rendered = match value {
+                Value::Sequence(_) => serde_json::to_string(value).unwrap_or(rendered),
+                _ => rendered.trim_end().to_string(),
+            };
+            let layer = sources.get(path).map(|layer| layer.to_string()).unwrap_or_else(|| "?".to_string());
+            lines.push(format!("{} = {} ({})", path, rendered, layer));
+        }
+    }
+}
+
+fn key_name(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        other => serde_yaml::to_string(other).unwrap_or_default().trim_end().to_string(),
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+// env and cli values are typed the way yaml would type them, `0.3` is a number, `ETHUSDT` a string
+fn parse_scalar(raw: &str) -> Value {
+    serde_yaml::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+fn split_list(raw: &str) -> Value {
+    Value::Sequence(
+        raw.split(',')
+            .map(str::trim)
+            .filter(|symbol| !symbol.is_empty())
+            .map(|symbol| Value::String(symbol.to_string()))
+            .collect(),
+    )
+}
+
+// TESTS
+#[test]
+fn test_layers_precedence() {
+    let defaults: Value = serde_yaml::from_str("atr_threshold: 0.35\natr_min_candles_percent: 0.8").unwrap();
+    let mut layers = Layers::new(defaults);
+    layers.merge(serde_yaml::from_str("atr_threshold: 0.2\nmin_vol_usdt: 50000\nsymbols: [ETHUSDT]").unwrap(), Layer::File);
+    layers.env(
+        vec![
+            ("WHIPLASH_MIN_VOL_USDT".to_string(), "100000".to_string()),
+            ("WHIPLASH_SYMBOLS".to_string(), "BTCUSDT, SOLUSDT".to_string()),
+            ("WHIPLASH_SYMBOLS_1".to_string(), "XRPUSDT".to_string()),
+            ("WHIPLASH_SYMBOLS_0".to_string(), "ADAUSDT".to_string()),
+            ("WHIPLASH_ATR_TRESHOLD".to_string(), "1".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ],
+        &["atr_threshold", "atr_min_candles_percent", "min_vol_usdt", "symbols"],
+    );
+    layers.cli(None, &["atr_min_candles_percent=0.5".to_string()]);
+
+    assert_eq!(layers.value["atr_threshold"], Value::from(0.2));
+    assert_eq!(layers.value["atr_min_candles_percent"], Value::from(0.5));
+    assert_eq!(layers.value["min_vol_usdt"], Value::from(100000));
+    assert_eq!(layers.value["symbols"], serde_yaml::from_str::<Value>("[ADAUSDT, XRPUSDT]").unwrap());
+    assert_eq!(layers.describe(), vec![
+        "atr_threshold = 0.2 (file)",
+        "atr_min_candles_percent = 0.5 (cli)",
+        "min_vol_usdt = 100000 (env)",
+        "symbols = [\"ADAUSDT\",\"XRPUSDT\"] (env)",
+    ]);
+    // the typo and the ignored comma-separated list
+    let issues: Vec<&str> = layers.issues.iter().map(|issue| issue.path.as_str()).collect();
+    assert_eq!(issues, vec!["env WHIPLASH_ATR_TRESHOLD", "env WHIPLASH_SYMBOLS"]);
+
+    // symbols from the command line beat everything
+    layers.cli(Some(&["DOGEUSDT".to_string()]), &[]);
+    assert_eq!(layers.sources["symbols"], Layer::Cli);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_env_skips_non_unicode() {
+    use std::os::unix::ffi::OsStringExt;
+    let invalid = || OsString::from_vec(vec![0x66, 0x80]);
+    let mut layers = Layers::new(Value::Mapping(Mapping::new()));
+    layers.env_os(
+        vec![
+            (OsString::from("WHIPLASH_MIN_VOL_USDT"), OsString::from("100000")),
+            (OsString::from("WHIPLASH_ATR_THRESHOLD"), invalid()),
+            (OsString::from("LANG"), invalid()),
+            (invalid(), OsString::from("1")),
+        ],
+        &["atr_threshold", "min_vol_usdt"],
+    );
+
+    assert_eq!(layers.value["min_vol_usdt"], Value::from(100000));
+    assert_eq!(layers.value.get("atr_threshold"), None);
+    let issues: Vec<&str> = layers.issues.iter().map(|issue| issue.path.as_str()).collect();
+    assert_eq!(issues, vec!["env WHIPLASH_ATR_THRESHOLD"]);
+}
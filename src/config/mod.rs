@@ -1,49 +1,469 @@
-
+use std::env;
 use std::fs;
-use std::error::Error;
+use std::path::{Path, PathBuf};
 use log::warn;
-use serde_yaml;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use crate::error::ConfigError;
+use crate::grpc::GrpcConfig;
+use crate::http::HttpConfig;
+use crate::logging::LoggingConfig;
+use crate::stream_monitor::atr::MovingAverage;
+use crate::stream_monitor::buffer::IngestionConfig;
+use crate::stream_monitor::clock::EvaluationConfig;
+use crate::stream_monitor::latency::LatencyConfig;
+use crate::stream_monitor::liquidation::LiquidationConfig;
+use crate::stream_monitor::mark_price::MarkPriceConfig;
+use crate::stream_monitor::market::Market;
+use crate::stream_monitor::open_interest::OpenInterestConfig;
+use crate::stream_monitor::persist::PersistenceConfig;
+use crate::stream_monitor::reference::ReferenceConfig;
+use crate::stream_monitor::regime::RegimeConfig;
+use crate::stream_monitor::orderbook::OrderBookConfig;
+use crate::stream_monitor::SymbolSettings;
+use crate::websocket::WebSocketConfig;
+use layers::Layers;
+
+pub mod layers;
+pub mod validate;
 
 pub static DEFAULT_CONFIG_PATH: &str = "./config.yaml";
-// const ATR_MAT: [&str; 3] = ["EMA", "RMA", "SMA"];
 static DEFAULT_ATR_MAT: &str = "EMA";
 const DEFAULT_ATR_CANDLES_PERCENT: f64 = 0.8;
 const DEFAULT_ATR_THRESHOLD: f64 = 0.35;
+const DEFAULT_ATR_WINDOW_SECONDS: usize = 10;
 
-// TODO: atr setting should be symbol-specific, not global
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
     pub atr_moving_average_type: String,
     pub atr_threshold: f64,
     pub atr_min_candles_percent: f64,
+    pub atr_window_seconds: usize,
     pub min_vol_usdt: f64,
-    pub symbols: Vec<String>,
+    pub symbols: Vec<SymbolConfig>,
+    // market of the symbols that don't set their own
+    #[serde(default)]
+    pub market: Market,
+    // overrides the endpoint of `market`, e.g. to point at a local mock exchange
+    #[serde(default)]
+    pub stream_url: Option<String>,
+    #[serde(default)]
+    pub evaluation: EvaluationConfig,
+    #[serde(default)]
+    pub latency: LatencyConfig,
+    #[serde(default)]
+    pub ingestion: IngestionConfig,
+    #[serde(default)]
+    pub orderbook: OrderBookConfig,
+    #[serde(default)]
+    pub liquidations: LiquidationConfig,
+    #[serde(default)]
+    pub mark_price: MarkPriceConfig,
+    #[serde(default)]
+    pub open_interest: OpenInterestConfig,
+    #[serde(default)]
+    pub regime: RegimeConfig,
+    #[serde(default)]
+    pub reference: ReferenceConfig,
+    #[serde(default)]
+    pub persistence: PersistenceConfig,
+    #[serde(default)]
+    pub grpc: GrpcConfig,
+    #[serde(default)]
+    pub websocket: WebSocketConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+}
+
+// a symbol is either just its name or a mapping with its own market settings and thresholds
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(from = "SymbolEntry", into = "SymbolEntry")]
+pub struct SymbolConfig {
+    pub symbol: String,
+    pub market: Option<Market>,
+    pub stream_url: Option<String>,
+    // usd value of a coin-m contract
+    pub contract_size: Option<f64>,
+    // override the top-level ones
+    pub atr_threshold: Option<f64>,
+    pub min_vol_usdt: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum SymbolEntry {
+    Name(String),
+    Detailed {
+        symbol: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        market: Option<Market>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stream_url: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        contract_size: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        atr_threshold: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_vol_usdt: Option<f64>,
+    },
+}
+
+impl From<SymbolEntry> for SymbolConfig {
+    fn from(entry: SymbolEntry) -> Self {
+        match entry {
+            SymbolEntry::Name(symbol) => SymbolConfig { symbol, ..Default::default() },
+            SymbolEntry::Detailed { symbol, market, stream_url, contract_size, atr_threshold, min_vol_usdt } => {
+                SymbolConfig { symbol, market, stream_url, contract_size, atr_threshold, min_vol_usdt }
+            }
+        }
+    }
+}
+
+// written back as just the name when there's nothing else to it
+impl From<SymbolConfig> for SymbolEntry {
+    fn from(config: SymbolConfig) -> Self {
+        match config {
+            SymbolConfig { symbol, market: None, stream_url: None, contract_size: None, atr_threshold: None, min_vol_usdt: None } => {
+                SymbolEntry::Name(symbol)
+            }
+            SymbolConfig { symbol, market, stream_url, contract_size, atr_threshold, min_vol_usdt } => {
+                SymbolEntry::Detailed { symbol, market, stream_url, contract_size, atr_threshold, min_vol_usdt }
+            }
+        }
+    }
+}
+
+// where the config values come from, in the order of precedence:
+// built-in defaults, the yaml file, WHIPLASH_* env vars and the command line
+#[derive(Debug, Clone, Default)]
+pub struct Sources {
+    pub path: PathBuf,
+    // whether a missing file is an error, it's not when the default path is used
+    pub path_required: bool,
+    pub symbols: Option<Vec<String>>,
+    // `key=value` overrides from the command line
+    pub set: Vec<String>,
+}
+
+// result of loading the config: the config itself unless there were errors,
+// every issue found, and the merged layers with the origin of each value
+pub struct Loaded {
+    pub config: Option<Config>,
+    pub report: validate::Report,
+    pub layers: Layers,
+}
+
+impl Loaded {
+    // logs the warnings, any error fails the load
+    pub fn into_config(self) -> Result<Config, ConfigError> {
+        for issue in self.report.warnings() {
+            warn!("config {}", issue);
+        }
+        self.config.ok_or(ConfigError::Invalid(self.report))
+    }
 }
 
 impl Config {
-    pub fn from_file(path: &str) -> Result<Config, Box<dyn Error>> {
-        let config_str = fs::read_to_string(path)?;
-        let mut config: Config = serde_yaml::from_str(&config_str)?;
-        if config.symbols.len() == 0 {
-            Err("symbols are empty")?
+    // only unreadable files and broken yaml fail right away, everything else ends up in the report
+    pub fn load(sources: &Sources) -> Result<Loaded, ConfigError> {
+        let mut layers = Layers::new(Config::defaults());
+        layers.file(&sources.path, sources.path_required)?;
+        layers.env_os(env::vars_os(), &validate::KNOWN_KEYS);
+        layers.cli(sources.symbols.as_deref(), &sources.set);
+
+        let (mut config, mut report) = Config::check_value(layers.value.clone());
+        report.issues.splice(0..0, layers.issues.iter().cloned());
+        if report.has_errors() {
+            config = None;
+        }
+        Ok(Loaded { config, report, layers })
+    }
+
+    pub fn defaults() -> Value {
+        let mut defaults = Mapping::new();
+        defaults.insert("atr_moving_average_type".into(), DEFAULT_ATR_MAT.into());
+        defaults.insert("atr_threshold".into(), DEFAULT_ATR_THRESHOLD.into());
+        defaults.insert("atr_min_candles_percent".into(), DEFAULT_ATR_CANDLES_PERCENT.into());
+        defaults.insert("atr_window_seconds".into(), (DEFAULT_ATR_WINDOW_SECONDS as u64).into());
+        Value::Mapping(defaults)
+    }
+
+    pub fn check_value(raw: Value) -> (Option<Config>, validate::Report) {
+        let mut report = validate::validate(&raw);
+        if report.has_errors() {
+            return (None, report);
+        }
+        match serde_yaml::from_value::<Config>(raw) {
+            Ok(mut config) => {
+                if config.atr_moving_average_type.is_empty() {
+                    config.atr_moving_average_type = DEFAULT_ATR_MAT.to_string();
+                }
+                config.atr_moving_average_type = config.atr_moving_average_type.to_uppercase();
+                for symbol in config.symbols.iter_mut() {
+                    symbol.symbol = validate::normalize_symbol(&symbol.symbol);
+                }
+                config.reference.symbol = validate::normalize_symbol(&config.reference.symbol);
+                (Some(config), report)
+            }
+            Err(e) => {
+                // validation should catch everything serde would complain about
+                report.issues.push(validate::Issue {
+                    severity: validate::Severity::Error,
+                    path: "<root>".to_string(),
+                    message: e.to_string(),
+                });
+                (None, report)
+            }
+        }
+    }
+
+    // the config as it would be read back, the unset options are left out
+    pub fn to_value(&self) -> Value {
+        fn prune(value: &mut Value) {
+            match value {
+                Value::Mapping(mapping) => {
+                    mapping.retain(|_, value| !value.is_null());
+                    mapping.values_mut().for_each(prune);
+                }
+                Value::Sequence(values) => values.iter_mut().for_each(prune),
+                _ => {}
+            }
         }
-        if config.min_vol_usdt == 0. {
-            Err("minimal volume value is empty")?
+        // every field is a plain value or a string keyed map
+        let mut value = serde_yaml::to_value(self).expect("unserializable config");
+        prune(&mut value);
+        value
+    }
+
+    // writes the changes made to the symbols since `loaded` into the config file, the rest of it is kept as it is
+    // nothing from the defaults, the env or the command line ends up in it, e.g. a token or a symbol set in the env
+    // replaces the file at once, a reader never sees half of it
+    pub fn save_symbols(&self, path: &Path, loaded: &[SymbolConfig]) -> Result<(), ConfigError> {
+        let mut file = match fs::read_to_string(path) {
+            Ok(config_str) => serde_yaml::from_str(&config_str).map_err(|source| ConfigError::Parse { path: path.to_path_buf(), source })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Value::Null,
+            Err(source) => return Err(ConfigError::Read { path: path.to_path_buf(), source }),
+        };
+        if !file.is_mapping() {
+            file = Value::Mapping(Mapping::new());
         }
-        if config.atr_moving_average_type.to_uppercase() != DEFAULT_ATR_MAT {
-            warn!("using default: {} as ATR moving average", DEFAULT_ATR_MAT);
-            config.atr_moving_average_type = DEFAULT_ATR_MAT.to_string();
+        let listed = file.get("symbols").and_then(Value::as_sequence);
+        let mut entries = listed.cloned().unwrap_or_default();
+        let name = |entry: &Value| match entry {
+            Value::String(name) => Some(validate::normalize_symbol(name)),
+            Value::Mapping(entry) => entry.get("symbol").and_then(Value::as_str).map(validate::normalize_symbol),
+            _ => None,
+        };
+        let was_loaded = |symbol: &str| loaded.iter().any(|loaded| loaded.symbol == symbol);
+        let is_kept = |symbol: &str| self.symbols.iter().any(|kept| kept.symbol == symbol);
+        // removed since
+        entries.retain(|entry| name(entry).is_none_or(|symbol| !was_loaded(&symbol) || is_kept(&symbol)));
+        // added or changed since, the untouched entries stay the way they're written
+        for symbol in &self.symbols {
+            let before = loaded.iter().find(|loaded| loaded.symbol == symbol.symbol);
+            if before == Some(symbol) {
+                continue;
+            }
+            let value = serde_yaml::to_value(symbol).expect("unserializable symbol");
+            match entries.iter_mut().find(|entry| name(entry).as_deref() == Some(symbol.symbol.as_str())) {
+                Some(entry) => *entry = value,
+                None if before.is_none() => entries.push(value),
+                // changed, but it's not the file's
+                None => {}
+            }
         }
-        if config.atr_min_candles_percent <= 0. {
-            warn!("using default: {:?} as atr min candles percent value", DEFAULT_ATR_CANDLES_PERCENT);
-            config.atr_min_candles_percent = DEFAULT_ATR_CANDLES_PERCENT;
+        if listed.is_some() || !entries.is_empty() {
+            file["symbols"] = Value::Sequence(entries);
         }
-        if config.atr_threshold <= 0. {
-            warn!("using default: {:?} as atr threshold value", DEFAULT_ATR_CANDLES_PERCENT);
-            config.atr_threshold = DEFAULT_ATR_THRESHOLD;
+        let yaml = serde_yaml::to_string(&file).expect("unserializable config");
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        fs::write(&tmp, yaml)
+            .and_then(|()| fs::rename(&tmp, path))
+            .map_err(|source| ConfigError::Write { path: path.to_path_buf(), source })
+    }
+
+    pub fn atr_moving_average(&self) -> MovingAverage {
+        self.atr_moving_average_type.parse().unwrap_or(MovingAverage::Ema)
+    }
+
+    pub fn symbol_names(&self) -> Vec<String> {
+        self.symbols.iter().map(|symbol| symbol.symbol.clone()).collect()
+    }
+
+    // the configured symbols, along with the reference symbol on the top-level market when it's not one of them
+    pub fn monitored_symbols(&self) -> Vec<SymbolConfig> {
+        let mut symbols = self.symbols.clone();
+        if self.reference.enabled && !symbols.iter().any(|symbol| symbol.symbol == self.reference.symbol) {
+            symbols.push(SymbolConfig { symbol: self.reference.symbol.clone(), ..Default::default() });
         }
-        Ok(config)
+        symbols
     }
+
+    // the top-level `stream_url` only applies to the symbols on the top-level market
+    pub fn symbol_settings(&self, symbol: &SymbolConfig) -> SymbolSettings {
+        let market = symbol.market.unwrap_or(self.market);
+        let stream_url = match (&symbol.stream_url, &self.stream_url) {
+            (Some(url), _) => url.clone(),
+            (None, Some(url)) if market == self.market => url.clone(),
+            _ => market.stream_url().to_string(),
+        };
+        SymbolSettings {
+            market,
+            contract_size: market.contract_size(&symbol.symbol, symbol.contract_size),
+            atr_moving_average: self.atr_moving_average(),
+            atr_window_seconds: self.atr_window_seconds,
+            atr_threshold: symbol.atr_threshold.unwrap_or(self.atr_threshold),
+            atr_min_candles_percent: self.atr_min_candles_percent,
+            min_vol_usdt: symbol.min_vol_usdt.unwrap_or(self.min_vol_usdt),
+            evaluation: self.evaluation,
+            latency: self.latency,
+            ingestion: self.ingestion,
+            orderbook: self.orderbook,
+            liquidations: self.liquidations,
+            mark_price: self.mark_price,
+            open_interest: self.open_interest.clone(),
+            regime: self.regime,
+            reference: self.reference.clone(),
+            reference_only: self.reference.enabled
+                && self.reference.symbol == symbol.symbol
+                && !self.symbols.iter().any(|configured| configured.symbol == symbol.symbol),
+            persistence: self.persistence.clone(),
+            rest_url: market.rest_url().map(|url| self.open_interest.rest_url.clone().unwrap_or(url.to_string())),
+            stream_url,
+        }
+    }
+}
+
+// TESTS
+#[test]
+fn test_symbol_settings_markets() {
+    let raw: Value = serde_yaml::from_str(r#"
+atr_threshold: 0.2
+min_vol_usdt: 50000
+stream_url: ws://localhost:9000/ws
+symbols:
+  - ethusdt
+  - symbol: BTCUSD_PERP
+    market: coinm
+  - symbol: ETHUSD_PERP
+    market: coinm
+    contract_size: 20
+  - symbol: SOLUSDT
+    market: spot
+    stream_url: ws://localhost:9001/ws
+"#).unwrap();
+    let mut layers = Layers::new(Config::defaults());
+    layers.merge(raw, layers::Layer::File);
+    let (config, report) = Config::check_value(layers.value);
+    let config = config.unwrap_or_else(|| panic!("{}", report));
+
+    let settings: Vec<_> = config.symbols.iter().map(|symbol| config.symbol_settings(symbol)).collect();
+    assert_eq!(config.symbol_names(), vec!["ETHUSDT", "BTCUSD_PERP", "ETHUSD_PERP", "SOLUSDT"]);
+    // the top-level url only replaces the endpoint of the top-level market
+    assert_eq!(settings[0].stream_url, "ws://localhost:9000/ws");
+    assert_eq!(settings[1].stream_url, "wss://dstream.binance.com/ws");
+    assert_eq!(settings[3].stream_url, "ws://localhost:9001/ws");
+    assert_eq!(settings.iter().map(|s| s.contract_size).collect::<Vec<_>>(), vec![None, Some(100.), Some(20.), None]);
+    assert_eq!(settings[3].market, Market::Spot);
+}
+
+#[test]
+fn test_reference_only_symbol() {
+    let raw: Value = serde_yaml::from_str(r#"
+min_vol_usdt: 50000
+symbols:
+  - ETHUSDT
+reference:
+  enabled: true
+  symbol: BTCUSDT
+"#).unwrap();
+    let mut layers = Layers::new(Config::defaults());
+    layers.merge(raw, layers::Layer::File);
+    let (config, report) = Config::check_value(layers.value);
+    let config = config.unwrap_or_else(|| panic!("{}", report));
+
+    let settings: Vec<_> = config.monitored_symbols().iter().map(|symbol| config.symbol_settings(symbol)).collect();
+    assert_eq!(settings.iter().map(|s| s.reference_only).collect::<Vec<_>>(), vec![false, true]);
+
+    // a configured reference signals like the rest
+    let mut config = config;
+    config.symbols.push(SymbolConfig { symbol: "BTCUSDT".into(), ..Default::default() });
+    assert!(config.monitored_symbols().iter().all(|symbol| !config.symbol_settings(symbol).reference_only));
+}
+
+#[test]
+fn test_config_round_trip() {
+    let raw: Value = serde_yaml::from_str(r#"
+min_vol_usdt: 50000
+symbols:
+  - ETHUSDT
+  - symbol: SOLUSDT
+    atr_threshold: 0.5
+"#).unwrap();
+    let mut layers = Layers::new(Config::defaults());
+    layers.merge(raw, layers::Layer::File);
+    let (config, report) = Config::check_value(layers.value);
+    let config = config.unwrap_or_else(|| panic!("{}", report));
+
+    let value = config.to_value();
+    // a symbol without overrides goes back to being just a name
+    assert_eq!(value["symbols"][0], Value::from("ETHUSDT"));
+    assert_eq!(value["symbols"][1]["atr_threshold"], Value::from(0.5));
+    let (saved, report) = Config::check_value(value);
+    assert_eq!(report.issues, vec![]);
+    let saved = saved.unwrap();
+    assert_eq!(saved.symbols, config.symbols);
+    assert_eq!(saved.symbol_settings(&saved.symbols[1]).atr_threshold, 0.5);
+    assert_eq!(saved.symbol_settings(&saved.symbols[0]).atr_threshold, DEFAULT_ATR_THRESHOLD);
+}
+
+#[test]
+fn test_save_symbols_keeps_the_file() {
+    let path = env::temp_dir().join(format!("whiplash-save-{}.yaml", std::process::id()));
+    fs::write(&path, "min_vol_usdt: 50000\nsymbols: [ETHUSDT]\nhttp:\n  enabled: true\n").unwrap();
+    let mut layers = Layers::new(Config::defaults());
+    layers.file(&path, true).unwrap();
+    layers.env(vec![("WHIPLASH_HTTP__TOKEN".to_string(), "secret".to_string())], &validate::KNOWN_KEYS);
+    layers.cli(None, &["atr_threshold=0.5".to_string()]);
+    let (config, report) = Config::check_value(layers.value);
+    let mut config = config.unwrap_or_else(|| panic!("{}", report));
+    assert_eq!(config.http.token.as_deref(), Some("secret"));
+
+    let loaded = config.symbols.clone();
+    config.symbols.push(SymbolConfig { symbol: "SOLUSDT".into(), min_vol_usdt: Some(5000.), ..Default::default() });
+    config.save_symbols(&path, &loaded).unwrap();
+    let saved: Value = serde_yaml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+    // the env token and the cli threshold stay where they came from
+    let expected: Value = serde_yaml::from_str("min_vol_usdt: 50000\nsymbols: [ETHUSDT, {symbol: SOLUSDT, min_vol_usdt: 5000.0}]\nhttp:\n  enabled: true\n").unwrap();
+    assert_eq!(saved, expected);
+}
+
+#[test]
+fn test_save_symbols_with_cli_symbols() {
+    let path = env::temp_dir().join(format!("whiplash-save-cli-{}.yaml", std::process::id()));
+    fs::write(&path, "min_vol_usdt: 50000\nsymbols:\n  - ethusdt\n  - symbol: BTCUSDT\n    atr_threshold: 0.3\n  - XRPUSDT\n").unwrap();
+    let mut layers = Layers::new(Config::defaults());
+    layers.file(&path, true).unwrap();
+    layers.cli(Some(&["DOGEUSDT".to_string(), "ETHUSDT".to_string(), "BTCUSDT".to_string()]), &[]);
+    let (config, report) = Config::check_value(layers.value);
+    let mut config = config.unwrap_or_else(|| panic!("{}", report));
+
+    // what the api would do: add one, remove one and change the thresholds of two
+    let loaded = config.symbols.clone();
+    config.symbols.push(SymbolConfig { symbol: "SOLUSDT".into(), ..Default::default() });
+    config.symbols.retain(|symbol| symbol.symbol != "ETHUSDT");
+    config.symbols[0].atr_threshold = Some(0.5);
+    config.symbols[1].atr_threshold = Some(0.4);
+    config.save_symbols(&path, &loaded).unwrap();
+    let saved: Value = serde_yaml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    // the cli symbol isn't written, not even with its new thresholds, the untouched entry stays as it is
+    let expected: Value = serde_yaml::from_str("min_vol_usdt: 50000\nsymbols: [{symbol: BTCUSDT, atr_threshold: 0.4}, XRPUSDT, SOLUSDT]").unwrap();
+    assert_eq!(saved, expected);
 }
-// TODO: impl config validation
\ No newline at end of file
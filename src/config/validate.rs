@@ -0,0 +1,804 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+use crate::grpc::GrpcConfig;
+use crate::http::HttpConfig;
+use crate::logging::LoggingConfig;
+use crate::stream_monitor::atr::MovingAverage;
+use crate::stream_monitor::buffer::{IngestionConfig, BUFFER_SECONDS};
+use crate::stream_monitor::clock::EvaluationConfig;
+use crate::stream_monitor::latency::LatencyConfig;
+use crate::stream_monitor::liquidation::LiquidationConfig;
+use crate::stream_monitor::mark_price::MarkPriceConfig;
+use crate::stream_monitor::open_interest::OpenInterestConfig;
+use crate::stream_monitor::persist::PersistenceConfig;
+use crate::stream_monitor::reference::ReferenceConfig;
+use crate::stream_monitor::regime::RegimeConfig;
+use crate::stream_monitor::market::MARKETS;
+use crate::stream_monitor::orderbook::{OrderBookConfig, DEPTH_LEVELS};
+use crate::websocket::WebSocketConfig;
+
+pub const KNOWN_KEYS: [&str; 22] = [
+    "atr_moving_average_type",
+    "atr_threshold",
+    "atr_min_candles_percent",
+    "atr_window_seconds",
+    "min_vol_usdt",
+    "symbols",
+    "market",
+    "stream_url",
+    "evaluation",
+    "latency",
+    "ingestion",
+    "orderbook",
+    "liquidations",
+    "mark_price",
+    "open_interest",
+    "regime",
+    "reference",
+    "persistence",
+    "grpc",
+    "websocket",
+    "http",
+    "logging",
+];
+const SYMBOL_KEYS: [&str; 6] = ["symbol", "market", "stream_url", "contract_size", "atr_threshold", "min_vol_usdt"];
+const LOGGING_FILE_KEYS: [&str; 3] = ["path", "max_bytes", "keep"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+// a single problem found in the config, `path` points at the offending field, e.g. `symbols[2]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.path, self.message)
+    }
+}
+
+// every issue found in the config, in the order of the fields
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(Issue { severity: Severity::Error, path: path.into(), message: message.into() });
+    }
+
+    fn warning(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(Issue { severity: Severity::Warning, path: path.into(), message: message.into() });
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|issue| issue.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|issue| issue.severity == Severity::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self.errors().map(|issue| issue.to_string()).collect();
+        write!(f, "{} config error(s): {}", errors.len(), errors.join("; "))
+    }
+}
+
+impl Error for Report {}
+
+// validates the raw yaml before it's deserialized, so that every problem is reported at once
+pub fn validate(raw: &Value) -> Report {
+    let mut report = Report::default();
+    let Some(root) = raw.as_mapping() else {
+        report.error("<root>", "expected a mapping of config keys");
+        return report;
+    };
+
+    for key in root.keys() {
+        match key.as_str() {
+            Some(key) if KNOWN_KEYS.contains(&key) => {}
+            Some(key) => report.error(key, "unknown key"),
+            None => report.error(format!("{:?}", key), "config keys must be strings"),
+        }
+    }
+
+    match root.get("atr_moving_average_type") {
+        None | Some(Value::Null) => report.warning("atr_moving_average_type", format!("not set, using default {}", super::DEFAULT_ATR_MAT)),
+        Some(Value::String(s)) if s.is_empty() => report.warning("atr_moving_average_type", format!("not set, using default {}", super::DEFAULT_ATR_MAT)),
+        Some(Value::String(s)) => {
+            if let Err(e) = s.parse::<MovingAverage>() {
+                report.error("atr_moving_average_type", format!("{}, expected one of EMA, RMA, SMA", e));
+            }
+        }
+        Some(_) => report.error("atr_moving_average_type", "expected a string"),
+    }
+
+    check_number(&mut report, root.get("atr_threshold"), "atr_threshold", Some(super::DEFAULT_ATR_THRESHOLD), |v| {
+        (v > 0. && v < 100.).then_some(()).ok_or("must be a percentage between 0 and 100 (exclusive)")
+    });
+    check_number(&mut report, root.get("atr_min_candles_percent"), "atr_min_candles_percent", Some(super::DEFAULT_ATR_CANDLES_PERCENT), |v| {
+        (v > 0. && v <= 1.).then_some(()).ok_or("must be a fraction between 0 (exclusive) and 1")
+    });
+    check_number(&mut report, root.get("min_vol_usdt"), "min_vol_usdt", None, |v| {
+        (v > 0.).then_some(()).ok_or("must be positive")
+    });
+
+    match root.get("atr_window_seconds") {
+        None | Some(Value::Null) => {}
+        Some(value) => match value.as_u64() {
+            Some(0) => report.error("atr_window_seconds", "must be at least 1 second"),
+            Some(seconds) if seconds as usize > BUFFER_SECONDS => report.error(
+                "atr_window_seconds",
+                format!("{} seconds is longer than the {} seconds kept in the buffer", seconds, BUFFER_SECONDS),
+            ),
+            Some(_) => {}
+            None => report.error("atr_window_seconds", "expected a whole number of seconds"),
+        },
+    }
+
+    match root.get("symbols") {
+        None | Some(Value::Null) => report.error("symbols", "no symbols configured"),
+        Some(Value::Sequence(symbols)) if symbols.is_empty() => report.error("symbols", "no symbols configured"),
+        Some(Value::Sequence(symbols)) => {
+            let mut names = vec![];
+            let market = root.get("market").and_then(Value::as_str).unwrap_or("usdm");
+            for (i, symbol) in symbols.iter().enumerate() {
+                let path = format!("symbols[{}]", i);
+                match symbol {
+                    Value::String(name) => names.push((path, name.to_string())),
+                    Value::Mapping(entry) => {
+                        check_unknown_keys(&mut report, entry, &path, &SYMBOL_KEYS);
+                        match entry.get("symbol").and_then(Value::as_str) {
+                            Some(name) => names.push((format!("{}.symbol", path), name.to_string())),
+                            None => report.error(format!("{}.symbol", path), "expected a symbol name"),
+                        }
+                        check_choice(&mut report, entry.get("market"), &format!("{}.market", path), &MARKETS);
+                        if let Some(Value::String(url)) = entry.get("stream_url") {
+                            check_stream_url(&mut report, url, &format!("{}.stream_url", path));
+                        } else if entry.get("stream_url").is_some_and(|url| !url.is_null()) {
+                            report.error(format!("{}.stream_url", path), "expected a websocket url");
+                        }
+                        if let Some(size) = entry.get("contract_size").filter(|size| !size.is_null()) {
+                            let size_path = format!("{}.contract_size", path);
+                            check_number(&mut report, Some(size), &size_path, None, |v| {
+                                (v > 0.).then_some(()).ok_or("must be positive")
+                            });
+                            let symbol_market = entry.get("market").and_then(Value::as_str).unwrap_or(market);
+                            if symbol_market != "coinm" {
+                                report.warning(size_path, format!("ignored, only coinm volumes are in contracts, not {}", symbol_market));
+                            }
+                        }
+                        if let Some(threshold) = entry.get("atr_threshold").filter(|threshold| !threshold.is_null()) {
+                            check_number(&mut report, Some(threshold), &format!("{}.atr_threshold", path), None, |v| {
+                                (v > 0. && v < 100.).then_some(()).ok_or("must be a percentage between 0 and 100 (exclusive)")
+                            });
+                        }
+                        if let Some(volume) = entry.get("min_vol_usdt").filter(|volume| !volume.is_null()) {
+                            check_number(&mut report, Some(volume), &format!("{}.min_vol_usdt", path), None, |v| {
+                                (v > 0.).then_some(()).ok_or("must be positive")
+                            });
+                        }
+                    }
+                    _ => report.error(path, "expected a symbol name or a mapping with a `symbol`"),
+                }
+            }
+            report.issues.extend(check_symbols(&names));
+        }
+        Some(_) => report.error("symbols", "expected a list of symbols"),
+    }
+
+    check_choice(&mut report, root.get("market"), "market", &MARKETS);
+
+    match root.get("stream_url") {
+        None | Some(Value::Null) => {}
+        Some(Value::String(url)) => check_stream_url(&mut report, url, "stream_url"),
+        Some(_) => report.error("stream_url", "expected a websocket url"),
+    }
+
+    if let Some(evaluation) = root.get("evaluation") {
+        check_evaluation(&mut report, evaluation);
+    }
+
+    if let Some(latency) = root.get("latency") {
+        check_latency(&mut report, latency);
+    }
+
+    if let Some(ingestion) = root.get("ingestion") {
+        check_ingestion(&mut report, ingestion);
+    }
+
+    if let Some(orderbook) = root.get("orderbook") {
+        check_orderbook(&mut report, orderbook);
+    }
+
+    if let Some(liquidations) = root.get("liquidations") {
+        let spot = root.get("market").and_then(Value::as_str) == Some("spot");
+        check_liquidations(&mut report, liquidations, spot);
+    }
+
+    if let Some(mark_price) = root.get("mark_price") {
+        let spot = root.get("market").and_then(Value::as_str) == Some("spot");
+        check_mark_price(&mut report, mark_price, spot);
+    }
+
+    if let Some(open_interest) = root.get("open_interest") {
+        let spot = root.get("market").and_then(Value::as_str) == Some("spot");
+        check_open_interest(&mut report, open_interest, spot);
+    }
+
+    if let Some(regime) = root.get("regime") {
+        check_regime(&mut report, regime);
+    }
+
+    if let Some(reference) = root.get("reference") {
+        check_reference(&mut report, reference);
+    }
+
+    if let Some(persistence) = root.get("persistence") {
+        check_persistence(&mut report, persistence);
+    }
+
+    if let Some(grpc) = root.get("grpc") {
+        check_grpc(&mut report, grpc);
+    }
+
+    if let Some(websocket) = root.get("websocket") {
+        check_websocket(&mut report, websocket);
+    }
+
+    if let Some(http) = root.get("http") {
+        check_http(&mut report, http);
+    }
+
+    if let Some(logging) = root.get("logging") {
+        check_logging(&mut report, logging);
+    }
+
+    report
+}
+
+fn check_unknown_keys(report: &mut Report, mapping: &Mapping, prefix: &str, known: &[impl AsRef<str>]) {
+    for key in mapping.keys() {
+        match key.as_str() {
+            Some(key) if known.iter().any(|known| known.as_ref() == key) => {}
+            Some(key) => report.error(format!("{}.{}", prefix, key), "unknown key"),
+            None => report.error(format!("{}.{:?}", prefix, key), "config keys must be strings"),
+        }
+    }
+}
+
+// the keys of a config section, as its struct serializes them
+fn keys<T: Default + Serialize>() -> Vec<String> {
+    match serde_yaml::to_value(T::default()) {
+        Ok(Value::Mapping(mapping)) => mapping.keys().filter_map(Value::as_str).map(str::to_string).collect(),
+        _ => vec![],
+    }
+}
+
+// a config section, its fields are checked by key and reported under `prefix`
+// unset and null fields are left to the defaults, so every check only sees the set ones
+struct Section<'a> {
+    prefix: &'a str,
+    fields: &'a Mapping,
+}
+
+// None when the section isn't a mapping, which is reported unless it's null
+fn section<'a>(report: &mut Report, value: &'a Value, prefix: &'a str, keys: &[impl AsRef<str>]) -> Option<Section<'a>> {
+    let Some(fields) = value.as_mapping() else {
+        if !value.is_null() {
+            report.error(prefix, "expected a mapping");
+        }
+        return None;
+    };
+    check_unknown_keys(report, fields, prefix, keys);
+    Some(Section { prefix, fields })
+}
+
+impl Section<'_> {
+    fn path(&self, key: &str) -> String {
+        format!("{}.{}", self.prefix, key)
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        self.fields.get(key).filter(|value| !value.is_null())
+    }
+
+    // false when unset or invalid
+    fn bool(&self, report: &mut Report, key: &str) -> bool {
+        match self.get(key) {
+            None => false,
+            Some(Value::Bool(value)) => *value,
+            Some(_) => {
+                report.error(self.path(key), "expected true or false");
+                false
+            }
+        }
+    }
+
+    fn number(&self, report: &mut Report, key: &str, check: impl Fn(f64) -> Result<(), &'static str>) {
+        if let Some(value) = self.get(key) {
+            check_number(report, Some(value), &self.path(key), None, check);
+        }
+    }
+
+    fn positive(&self, report: &mut Report, key: &str, unit: &str) {
+        if self.get(key).is_some_and(|value| value.as_u64().is_none_or(|n| n == 0)) {
+            report.error(self.path(key), format!("expected a positive whole number{}", of(unit)));
+        }
+    }
+
+    fn whole(&self, report: &mut Report, key: &str, unit: &str, range: RangeInclusive<u64>) {
+        if self.get(key).is_some_and(|value| value.as_u64().is_none_or(|n| !range.contains(&n))) {
+            let message = match *range.end() {
+                u64::MAX => format!("expected a whole number{}", of(unit)),
+                end => format!("expected a whole number{} from {} to {}", of(unit), range.start(), end),
+            };
+            report.error(self.path(key), message);
+        }
+    }
+
+    fn choice(&self, report: &mut Report, key: &str, choices: &[&str]) {
+        check_choice(report, self.get(key), &self.path(key), choices);
+    }
+
+    // a limit of a feature that's off would never be checked
+    fn needs(&self, report: &mut Report, key: &str, enabled: bool) {
+        if !enabled && self.get(key).is_some() {
+            report.error(self.path(key), format!("needs {}", self.path("enabled")));
+        }
+    }
+
+    fn listen(&self, report: &mut Report, key: &str) {
+        check_listen(report, self.get(key), &self.path(key));
+    }
+}
+
+fn of(unit: &str) -> String {
+    if unit.is_empty() {
+        String::new()
+    } else {
+        format!(" of {}", unit)
+    }
+}
+
+fn check_level(report: &mut Report, value: &Value, path: &str) {
+    match value.as_str() {
+        Some(level) if level.parse::<log::LevelFilter>().is_ok() => {}
+        _ => report.error(path, "expected one of off, error, warn, info, debug, trace"),
+    }
+}
+
+fn check_choice(report: &mut Report, value: Option<&Value>, path: &str, choices: &[&str]) {
+    match value {
+        None | Some(Value::Null) => {}
+        Some(Value::String(s)) if choices.contains(&s.as_str()) => {}
+        Some(_) => report.error(path, format!("expected one of {}", choices.join(", "))),
+    }
+}
+
+fn check_evaluation(report: &mut Report, evaluation: &Value) {
+    let Some(evaluation) = section(report, evaluation, "evaluation", &keys::<EvaluationConfig>()) else {
+        return;
+    };
+    evaluation.choice(report, "mode", &["aligned", "event"]);
+    evaluation.choice(report, "missed_ticks", &["skip", "burst", "delay"]);
+    evaluation.whole(report, "warmup_seconds", "seconds", 0..=u64::MAX);
+}
+
+fn check_stream_url(report: &mut Report, url: &str, path: &str) {
+    match url::Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "ws" | "wss") => {}
+        Ok(url) => report.error(path, format!("expected a ws:// or wss:// url, got {}://", url.scheme())),
+        Err(e) => report.error(path, format!("invalid url: {}", e)),
+    }
+}
+
+fn check_latency(report: &mut Report, latency: &Value) {
+    let Some(latency) = section(report, latency, "latency", &keys::<LatencyConfig>()) else {
+        return;
+    };
+    for key in ["max_latency_ms", "max_clock_skew_ms"] {
+        latency.number(report, key, |v| (v > 0.).then_some(()).ok_or("must be positive"));
+    }
+    latency.bool(report, "suppress_signals");
+}
+
+fn check_ingestion(report: &mut Report, ingestion: &Value) {
+    let Some(ingestion) = section(report, ingestion, "ingestion", &keys::<IngestionConfig>()) else {
+        return;
+    };
+    ingestion.whole(report, "late_tolerance_ms", "milliseconds", 0..=BUFFER_SECONDS as u64 * 1000);
+}
+
+fn check_logging(report: &mut Report, logging: &Value) {
+    let Some(logging) = section(report, logging, "logging", &keys::<LoggingConfig>()) else {
+        return;
+    };
+    if let Some(level) = logging.get("level") {
+        check_level(report, level, "logging.level");
+    }
+    logging.choice(report, "format", &["text", "json"]);
+    match logging.get("modules") {
+        None => {}
+        Some(Value::Mapping(modules)) => {
+            for (module, level) in modules {
+                let path = format!("logging.modules.{}", module.as_str().unwrap_or("?"));
+                check_level(report, level, &path);
+            }
+        }
+        Some(_) => report.error("logging.modules", "expected a mapping of module to level"),
+    }
+    if let Some(file) = logging.get("file").and_then(|file| section(report, file, "logging.file", &LOGGING_FILE_KEYS)) {
+        if !file.get("path").is_some_and(Value::is_string) {
+            report.error("logging.file.path", "required");
+        }
+        file.positive(report, "max_bytes", "bytes");
+        file.whole(report, "keep", "files", 0..=u64::MAX);
+    }
+}
+
+fn check_orderbook(report: &mut Report, orderbook: &Value) {
+    let Some(orderbook) = section(report, orderbook, "orderbook", &keys::<OrderBookConfig>()) else {
+        return;
+    };
+    let depth = orderbook.bool(report, "depth");
+    let book_ticker = orderbook.bool(report, "book_ticker");
+    orderbook.whole(report, "levels", "", 1..=DEPTH_LEVELS as u64);
+    orderbook.positive(report, "depletion_window_seconds", "seconds");
+
+    let ranges = [
+        ("max_spread_bps", 0. ..=f64::MAX, "must not be negative"),
+        ("min_imbalance", 0. ..=1., "must be within 0 and 1"),
+        ("min_depletion_pct", f64::MIN..=100., "can't be over 100"),
+    ];
+    for (key, range, message) in ranges {
+        orderbook.number(report, key, |v| range.contains(&v).then_some(()).ok_or(message));
+    }
+
+    // a condition on a stream that isn't subscribed to would never pass
+    let conditions = [
+        ("max_spread_bps", depth || book_ticker, "orderbook.depth or orderbook.book_ticker"),
+        ("min_imbalance", depth, "orderbook.depth"),
+        ("min_depletion_pct", depth, "orderbook.depth"),
+    ];
+    for (key, streamed, needed) in conditions {
+        if !streamed && orderbook.get(key).is_some() {
+            report.error(orderbook.path(key), format!("needs {} enabled", needed));
+        }
+    }
+}
+
+fn check_liquidations(report: &mut Report, liquidations: &Value, spot: bool) {
+    let Some(liquidations) = section(report, liquidations, "liquidations", &keys::<LiquidationConfig>()) else {
+        return;
+    };
+    let enabled = liquidations.bool(report, "enabled");
+    liquidations.bool(report, "all_market");
+    if enabled && spot {
+        report.warning("liquidations.enabled", "spot has no liquidations, only the futures symbols get the stream");
+    }
+    for key in ["min_notional_usdt", "max_notional_usdt"] {
+        liquidations.number(report, key, |v| (v >= 0.).then_some(()).ok_or("must not be negative"));
+        liquidations.needs(report, key, enabled);
+    }
+    let limit = |key| liquidations.get(key).and_then(Value::as_f64);
+    if let (Some(min), Some(max)) = (limit("min_notional_usdt"), limit("max_notional_usdt")) {
+        if min > max {
+            report.error("liquidations.min_notional_usdt", format!("{} is over the maximum of {}", min, max));
+        }
+    }
+}
+
+fn check_mark_price(report: &mut Report, mark_price: &Value, spot: bool) {
+    let Some(mark_price) = section(report, mark_price, "mark_price", &keys::<MarkPriceConfig>()) else {
+        return;
+    };
+    let enabled = mark_price.bool(report, "enabled");
+    if enabled && spot {
+        report.warning("mark_price.enabled", "spot has no mark price, only the futures symbols get the stream");
+    }
+    for key in ["max_basis_bps", "max_index_deviation_bps", "max_funding_rate"] {
+        mark_price.number(report, key, |v| (v >= 0.).then_some(()).ok_or("must not be negative"));
+        mark_price.needs(report, key, enabled);
+    }
+}
+
+fn check_open_interest(report: &mut Report, open_interest: &Value, spot: bool) {
+    let Some(open_interest) = section(report, open_interest, "open_interest", &keys::<OpenInterestConfig>()) else {
+        return;
+    };
+    let enabled = open_interest.bool(report, "enabled");
+    if enabled && spot {
+        report.warning("open_interest.enabled", "spot has no open interest, only the futures symbols are polled");
+    }
+    match open_interest.get("rest_url") {
+        None => {}
+        Some(Value::String(url)) => match url::Url::parse(url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            Ok(url) => report.error("open_interest.rest_url", format!("expected a http:// or https:// url, got {}://", url.scheme())),
+            Err(e) => report.error("open_interest.rest_url", format!("invalid url: {}", e)),
+        },
+        Some(_) => report.error("open_interest.rest_url", "expected a url"),
+    }
+    open_interest.positive(report, "poll_interval_ms", "milliseconds");
+    match open_interest.get("windows_seconds") {
+        None => {}
+        Some(Value::Sequence(windows)) if windows.is_empty() => report.error("open_interest.windows_seconds", "expected at least one window"),
+        Some(Value::Sequence(windows)) => {
+            for (i, window) in windows.iter().enumerate() {
+                if window.as_u64().is_none_or(|seconds| seconds == 0) {
+                    report.error(format!("open_interest.windows_seconds[{}]", i), "expected a positive whole number of seconds");
+                }
+            }
+        }
+        Some(_) => report.error("open_interest.windows_seconds", "expected a list of seconds"),
+    }
+    for key in ["min_change_pct", "max_change_pct"] {
+        open_interest.number(report, key, |_| Ok(()));
+        open_interest.needs(report, key, enabled);
+    }
+    let limit = |key| open_interest.get(key).and_then(Value::as_f64);
+    if let (Some(min), Some(max)) = (limit("min_change_pct"), limit("max_change_pct")) {
+        if min > max {
+            report.error("open_interest.min_change_pct", format!("{} is over the maximum of {}", min, max));
+        }
+    }
+}
+
+fn check_regime(report: &mut Report, regime: &Value) {
+    let Some(regime) = section(report, regime, "regime", &keys::<RegimeConfig>()) else {
+        return;
+    };
+    regime.number(report, "breadth_threshold", |v| (v > 0. && v <= 1.).then_some(()).ok_or("must be over 0 and at most 1"));
+    regime.positive(report, "min_symbols", "");
+    regime.choice(report, "suppress", &["market_wide", "idiosyncratic"]);
+}
+
+fn check_reference(report: &mut Report, reference: &Value) {
+    let Some(reference) = section(report, reference, "reference", &keys::<ReferenceConfig>()) else {
+        return;
+    };
+    let enabled = reference.bool(report, "enabled");
+    if reference.bool(report, "use_residual") && !enabled {
+        report.error("reference.use_residual", "needs reference.enabled");
+    }
+    match reference.get("symbol") {
+        None => {}
+        Some(Value::String(name)) if is_valid_symbol(&normalize_symbol(name)) => {}
+        Some(Value::String(name)) => report.error("reference.symbol", format!("malformed symbol name {:?}", name)),
+        Some(_) => report.error("reference.symbol", "expected a symbol name"),
+    }
+    reference.whole(report, "window_seconds", "seconds", 2..=BUFFER_SECONDS as u64);
+    if reference.get("min_samples").is_some_and(|value| value.as_u64().is_none_or(|n| n < 2)) {
+        report.error("reference.min_samples", "expected a whole number of at least 2");
+    }
+    // there are at most window_seconds returns to relate
+    let window = reference.get("window_seconds").and_then(Value::as_u64).unwrap_or(BUFFER_SECONDS as u64);
+    if let Some(samples) = reference.get("min_samples").and_then(Value::as_u64).filter(|samples| *samples > window) {
+        report.error("reference.min_samples", format!("{} is over the {} seconds of reference.window_seconds", samples, window));
+    }
+}
+
+fn check_grpc(report: &mut Report, grpc: &Value) {
+    let Some(grpc) = section(report, grpc, "grpc", &keys::<GrpcConfig>()) else {
+        return;
+    };
+    grpc.bool(report, "enabled");
+    grpc.listen(report, "listen");
+}
+
+fn check_websocket(report: &mut Report, websocket: &Value) {
+    let Some(websocket) = section(report, websocket, "websocket", &keys::<WebSocketConfig>()) else {
+        return;
+    };
+    websocket.bool(report, "enabled");
+    websocket.listen(report, "listen");
+    websocket.positive(report, "interval_ms", "milliseconds");
+    websocket.positive(report, "min_interval_ms", "milliseconds");
+}
+
+fn check_persistence(report: &mut Report, persistence: &Value) {
+    let Some(persistence) = section(report, persistence, "persistence", &keys::<PersistenceConfig>()) else {
+        return;
+    };
+    persistence.bool(report, "enabled");
+    match persistence.get("dir") {
+        None => {}
+        Some(Value::String(dir)) if !dir.is_empty() => {}
+        Some(_) => report.error("persistence.dir", "expected a directory path"),
+    }
+    // past the buffer nothing saved would still be in it
+    persistence.whole(report, "max_age_seconds", "seconds", 1..=BUFFER_SECONDS as u64);
+}
+
+fn check_http(report: &mut Report, http: &Value) {
+    let Some(http) = section(report, http, "http", &keys::<HttpConfig>()) else {
+        return;
+    };
+    let enabled = http.bool(report, "enabled");
+    http.listen(report, "listen");
+    match http.get("token") {
+        None => {
+            // the api changes what's monitored, anyone who can reach it is trusted
+            let listen = http.get("listen").and_then(Value::as_str).and_then(|listen| listen.parse::<SocketAddr>().ok());
+            if enabled && listen.is_some_and(|listen| !listen.ip().is_loopback()) {
+                report.warning("http.token", "not set, the api is open to anyone who can reach http.listen");
+            }
+        }
+        Some(Value::String(token)) if !token.is_empty() => {}
+        Some(_) => report.error("http.token", "expected a non-empty string"),
+    }
+}
+
+fn check_listen(report: &mut Report, value: Option<&Value>, path: &str) {
+    match value {
+        None | Some(Value::Null) => {}
+        Some(Value::String(listen)) if listen.parse::<SocketAddr>().is_ok() => {}
+        Some(_) => report.error(path, "expected an ip address and a port, e.g. 127.0.0.1:50051"),
+    }
+}
+
+fn check_number(
+    report: &mut Report,
+    value: Option<&Value>,
+    path: &str,
+    default: Option<f64>,
+    check: impl Fn(f64) -> Result<(), &'static str>,
+) {
+    match (value, default) {
+        (None | Some(Value::Null), Some(default)) => report.warning(path, format!("not set, using default {}", default)),
+        (None | Some(Value::Null), None) => report.error(path, "required"),
+        (Some(value), _) => match value.as_f64() {
+            Some(v) => {
+                if let Err(e) = check(v) {
+                    report.error(path, format!("{} is out of range, {}", v, e));
+                }
+            }
+            None => report.error(path, "expected a number"),
+        },
+    }
+}
+
+// checks (path, name) pairs of symbols, used for the config file as well as for overrides
+pub fn check_symbols(symbols: &[(String, String)]) -> Vec<Issue> {
+    let mut report = Report::default();
+    let mut seen = HashSet::new();
+    for (path, name) in symbols {
+        let normalized = normalize_symbol(name);
+        if !is_valid_symbol(&normalized) {
+            report.error(path, format!("malformed symbol name {:?}", name));
+            continue;
+        }
+        if normalized != *name {
+            report.warning(path, format!("{:?} will be used as {:?}", name, normalized));
+        }
+        if !seen.insert(normalized.clone()) {
+            report.error(path, format!("duplicate symbol {}", normalized));
+        }
+    }
+    report.issues
+}
+
+pub fn normalize_symbol(name: &str) -> String {
+    name.trim().to_uppercase()
+}
+
+// binance symbols are upper case alphanumerics, e.g. ETHUSDT or 1000SHIBUSDT,
+// coin-m ones add the contract after an underscore, e.g. BTCUSD_PERP
+fn is_valid_symbol(name: &str) -> bool {
+    (5..=20).contains(&name.len()) && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+// TESTS
+#[test]
+fn test_validate_reports_every_issue() {
+    let raw: Value = serde_yaml::from_str(r#"
+atr_moving_average_type: WMA
+atr_treshold: 0.2
+atr_min_candles_percent: 1.5
+atr_window_seconds: 120
+min_vol_usdt: -1
+symbols:
+  - ETHUSDT
+  - ethusdt
+  - ETH-USDT
+  - 42
+"#).unwrap();
+
+    let report = validate(&raw);
+    let errors: Vec<&str> = report.errors().map(|issue| issue.path.as_str()).collect();
+    let warnings: Vec<&str> = report.warnings().map(|issue| issue.path.as_str()).collect();
+
+    assert_eq!(errors, vec![
+        "atr_treshold",
+        "atr_moving_average_type",
+        "atr_min_candles_percent",
+        "min_vol_usdt",
+        "atr_window_seconds",
+        "symbols[3]",
+        "symbols[1]",
+        "symbols[2]",
+    ]);
+    assert_eq!(warnings, vec!["atr_threshold", "symbols[1]"]);
+}
+
+#[test]
+fn test_validate_valid_config() {
+    let raw: Value = serde_yaml::from_str(r#"
+atr_moving_average_type: rma
+atr_threshold: 0.2
+atr_min_candles_percent: 0.8
+atr_window_seconds: 10
+min_vol_usdt: 50000
+symbols:
+  - ETHUSDT
+  - 1000SHIBUSDT
+  - symbol: BTCUSD_PERP
+    market: coinm
+    contract_size: 100
+"#).unwrap();
+
+    let report = validate(&raw);
+    assert!(report.issues.is_empty(), "{:?}", report.issues);
+}
+
+#[test]
+fn test_known_keys_match_config() {
+    let raw: Value = serde_yaml::from_str(r#"
+atr_moving_average_type: EMA
+atr_threshold: 0.2
+atr_min_candles_percent: 0.8
+atr_window_seconds: 10
+min_vol_usdt: 50000
+symbols:
+  - symbol: ETHUSD_PERP
+    market: coinm
+    stream_url: ws://localhost:9000/ws
+    contract_size: 10
+    atr_threshold: 0.3
+    min_vol_usdt: 1000
+logging:
+  file:
+    path: ./whiplash.log
+"#).unwrap();
+    let (config, report) = super::Config::check_value(raw);
+    let config = config.unwrap_or_else(|| panic!("{}", report));
+
+    // serialized as is, the unset options are still there as nulls
+    let value = serde_yaml::to_value(&config).unwrap();
+    let keys = |value: &Value| -> Vec<String> {
+        value.as_mapping().unwrap().keys().map(|key| key.as_str().unwrap().to_string()).collect()
+    };
+    assert_eq!(keys(&value), KNOWN_KEYS);
+    assert_eq!(keys(&value["symbols"][0]), SYMBOL_KEYS);
+    assert_eq!(keys(&value["logging"]["file"]), LOGGING_FILE_KEYS);
+}
@@ -0,0 +1,189 @@
+use std::io;
+use std::net::SocketAddr;
+use std::num::ParseFloatError;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use thiserror::Error;
+use tokio_tungstenite::tungstenite;
+use crate::config::validate::Report;
+
+// exit codes, following sysexits.h where there's a match
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_DATA: u8 = 65;
+pub const EXIT_UNAVAILABLE: u8 = 69;
+pub const EXIT_SOFTWARE: u8 = 70;
+pub const EXIT_IO: u8 = 74;
+pub const EXIT_CONFIG: u8 = 78;
+// a second shutdown signal, 128 + SIGINT
+pub const EXIT_FORCED: u8 = 130;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read {path:?}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("failed to parse {path:?}: {source}")]
+    Parse { path: PathBuf, source: serde_yaml::Error },
+    #[error("failed to write {path:?}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+    #[error("{0}")]
+    Invalid(Report),
+}
+
+// everything between us and the exchange, or a recording
+#[derive(Debug, Error)]
+pub enum TransportError {
+    #[error("failed to connect to {url}: {source}")]
+    Connect { url: String, source: Box<tungstenite::Error> },
+    #[error("stream error: {0}")]
+    Stream(Box<tungstenite::Error>),
+    #[error("stream closed by the exchange")]
+    Closed,
+    #[error("request to {url} failed: {source}")]
+    Request { url: String, source: Box<reqwest::Error> },
+    #[error("failed to read the recording {path:?}: {source}")]
+    Recording { path: PathBuf, source: io::Error },
+    #[error("failed to write the recording: {0}")]
+    Recorder(io::Error),
+}
+
+// a single frame that can't be turned into a node, only that frame is lost
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("malformed frame: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("malformed number in {field}: {source}")]
+    Number { field: &'static str, source: ParseFloatError },
+    #[error("invalid timestamp {0}")]
+    Timestamp(i64),
+    #[error("unexpected {field} {value:?}")]
+    Value { field: &'static str, value: String },
+    #[error("unexpected stream {0:?}")]
+    Stream(String),
+}
+
+// the apis served to the downstream services
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error("failed to listen on {addr}: {source}")]
+    Bind { addr: SocketAddr, source: io::Error },
+    #[error("grpc server failed: {0}")]
+    Grpc(#[from] tonic::transport::Error),
+    #[error("http server failed: {0}")]
+    Http(io::Error),
+}
+
+// the state saved over a restart, a rejected one only costs the warmup
+#[derive(Debug, Error)]
+pub enum PersistError {
+    #[error("failed to read {path:?}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("failed to write {path:?}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+    #[error("malformed state in {path:?}: {source}")]
+    Parse { path: PathBuf, source: serde_json::Error },
+    #[error("{path:?} is in format version {found}, expected {expected}")]
+    Version { path: PathBuf, found: u32, expected: u32 },
+    #[error("{path:?} was saved {age_seconds} seconds ago, over the max age of {max_age_seconds}")]
+    Expired { path: PathBuf, age_seconds: i64, max_age_seconds: u64 },
+    #[error("{path:?} was saved under different settings, {saved} instead of {current}")]
+    Incompatible { path: PathBuf, saved: String, current: String },
+}
+
+#[derive(Debug, Error)]
+pub enum IndicatorError {
+    #[error("unknown moving average type: {0:?}")]
+    UnknownMovingAverage(String),
+    // a node that would poison the running sums
+    #[error("invalid {field} {value}")]
+    InvalidInput { field: &'static str, value: f64 },
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("config: {0}")]
+    Config(#[from] ConfigError),
+    #[error("transport: {0}")]
+    Transport(#[from] TransportError),
+    #[error("decode: {0}")]
+    Decode(#[from] DecodeError),
+    #[error("indicator: {0}")]
+    Indicator(#[from] IndicatorError),
+    #[error("server: {0}")]
+    Server(#[from] ServerError),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl TransportError {
+    // the exchange turning the request down, e.g. an unknown symbol, won't change with a retry
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TransportError::Connect { source, .. } => match source.as_ref() {
+                tungstenite::Error::Http(response) => !response.status().is_client_error(),
+                tungstenite::Error::Url(_) => false,
+                _ => true,
+            },
+            TransportError::Stream(_) | TransportError::Closed => true,
+            TransportError::Request { source, .. } => !source.status().is_some_and(|status| status.is_client_error()),
+            TransportError::Recording { .. } | TransportError::Recorder(_) => false,
+        }
+    }
+}
+
+impl Error {
+    // retryable errors are handled where they happen, a reconnect or a skipped frame,
+    // fatal ones end the task of the symbol
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Config(_) => false,
+            Error::Transport(e) => e.is_retryable(),
+            Error::Decode(_) => true,
+            Error::Indicator(IndicatorError::UnknownMovingAverage(_)) => false,
+            Error::Indicator(IndicatorError::InvalidInput { .. }) => true,
+            Error::Server(_) => false,
+        }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Config(_) => EXIT_CONFIG,
+            Error::Transport(TransportError::Recording { .. } | TransportError::Recorder(_)) => EXIT_IO,
+            Error::Transport(_) => EXIT_UNAVAILABLE,
+            Error::Decode(_) => EXIT_DATA,
+            Error::Indicator(_) => EXIT_SOFTWARE,
+            Error::Server(_) => EXIT_UNAVAILABLE,
+        }
+    }
+}
+
+impl From<&Error> for ExitCode {
+    fn from(e: &Error) -> Self {
+        ExitCode::from(e.exit_code())
+    }
+}
+
+// TESTS
+#[test]
+fn test_error_classification() {
+    let closed = Error::from(TransportError::Closed);
+    assert!(closed.is_retryable());
+    assert_eq!(closed.exit_code(), EXIT_UNAVAILABLE);
+
+    let response = tungstenite::http::Response::builder().status(400).body(None).unwrap();
+    let rejected = Error::from(TransportError::Connect {
+        url: "wss://fstream.binance.com/ws/nosuchusdt@kline_1m".to_string(),
+        source: Box::new(tungstenite::Error::Http(response)),
+    });
+    assert!(!rejected.is_retryable());
+
+    let recording = Error::from(TransportError::Recording {
+        path: PathBuf::from("missing.jsonl"),
+        source: io::Error::from(io::ErrorKind::NotFound),
+    });
+    assert!(!recording.is_retryable());
+    assert_eq!(recording.exit_code(), EXIT_IO);
+
+    let invalid = Error::from(ConfigError::Invalid(Report::default()));
+    assert!(!invalid.is_retryable());
+    assert_eq!(invalid.exit_code(), EXIT_CONFIG);
+}
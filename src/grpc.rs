@@ -0,0 +1,183 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use futures_util::Stream;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+use crate::config::validate::normalize_symbol;
+use crate::error::ServerError;
+use crate::stream_monitor::evaluation::Evaluation;
+use crate::stream_monitor::hub::Hub;
+use crate::stream_monitor::snapshot::{Signal, SignalState, SymbolSnapshot};
+use proto::whiplash_server::{Whiplash, WhiplashServer};
+
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("whiplash.v1");
+}
+
+// the `grpc` config section, the server is off unless enabled
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct GrpcConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+}
+
+fn default_listen() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 50051))
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        GrpcConfig { enabled: false, listen: default_listen() }
+    }
+}
+
+// answers from the hub, the symbol tasks never wait for a client
+pub struct WhiplashService {
+    hub: Hub,
+    // ends the signal streams, so that the server can shut down
+    cancel: CancellationToken,
+}
+
+type SignalStream = Pin<Box<dyn Stream<Item = Result<proto::Signal, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Whiplash for WhiplashService {
+    type SubscribeSignalsStream = SignalStream;
+
+    async fn subscribe_signals(
+        &self,
+        request: Request<proto::SubscribeSignalsRequest>,
+    ) -> Result<Response<Self::SubscribeSignalsStream>, Status> {
+        let symbols: HashSet<String> = request.into_inner().symbols.iter().map(|symbol| normalize_symbol(symbol)).collect();
+        let state = (self.hub.subscribe_signals(), self.cancel.clone(), symbols);
+        let stream = futures_util::stream::unfold(state, |(mut signals, cancel, symbols)| async move {
+            loop {
+                let received = tokio::select! {
+                    received = signals.recv() => received,
+                    _ = cancel.cancelled() => return None,
+                };
+                match received {
+                    Ok(signal) if symbols.is_empty() || symbols.contains(&signal.symbol) => {
+                        return Some((Ok(proto::Signal::from(&signal)), (signals, cancel, symbols)));
+                    }
+                    Ok(_) => {}
+                    // a slow client misses signals rather than holding up the others
+                    Err(RecvError::Lagged(skipped)) => warn!("grpc subscriber fell behind, skipped {} signal(s)", skipped),
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_symbol_state(
+        &self,
+        request: Request<proto::GetSymbolStateRequest>,
+    ) -> Result<Response<proto::SymbolState>, Status> {
+        let symbol = normalize_symbol(&request.into_inner().symbol);
+        match self.hub.snapshot(&symbol) {
+            Some(snapshot) => Ok(Response::new(proto::SymbolState::from(&snapshot))),
+            None => Err(Status::not_found(format!("{} is not monitored", symbol))),
+        }
+    }
+}
+
+// serves until cancelled, the open signal streams end along with it
+pub async fn serve(listener: TcpListener, hub: Hub, cancel: CancellationToken) -> Result<(), ServerError> {
+    if let Ok(addr) = listener.local_addr() {
+        info!("grpc server listening on {}", addr);
+    }
+    let service = WhiplashService { hub, cancel: cancel.clone() };
+    tonic::transport::Server::builder()
+        .add_service(WhiplashServer::new(service))
+        .serve_with_incoming_shutdown(TcpIncoming::from(listener), cancel.cancelled())
+        .await?;
+    Ok(())
+}
+
+impl From<&Evaluation> for proto::Evaluation {
+    fn from(e: &Evaluation) -> Self {
+        proto::Evaluation {
+            reason: e.reason.as_str().to_string(),
+            description: e.to_string(),
+            atr: e.atr,
+            atr_pct: e.atr_pct,
+            atr_threshold: e.atr_threshold,
+            candles: e.candles as u32,
+            min_candles: e.min_candles as u32,
+            vol_usdt: e.vol_usdt,
+            min_vol_usdt: e.min_vol_usdt,
+            return_pct: e.return_pct,
+            data_age_ms: e.data_age_ms,
+            regime: e.regime.map(|tag| tag.as_str().to_string()),
+            breadth: e.breadth,
+            beta: e.reference.map(|stats| stats.beta),
+            residual_atr_pct: e.reference.map(|stats| stats.residual_atr_pct),
+        }
+    }
+}
+
+impl From<&Signal> for proto::Signal {
+    fn from(signal: &Signal) -> Self {
+        proto::Signal {
+            symbol: signal.symbol.clone(),
+            market: signal.market.as_str().to_string(),
+            ts_ms: signal.ts.timestamp_millis(),
+            close_price: signal.close_price,
+            evaluation: Some(proto::Evaluation::from(&signal.evaluation)),
+            eval_latency_ms: signal.eval_latency_ms,
+            feed_latency_ms: signal.feed_latency_ms,
+        }
+    }
+}
+
+impl From<SignalState> for proto::SignalState {
+    fn from(state: SignalState) -> Self {
+        match state {
+            SignalState::Idle => proto::SignalState::Idle,
+            SignalState::Triggered => proto::SignalState::Triggered,
+            SignalState::Suppressed => proto::SignalState::Suppressed,
+            SignalState::Paused => proto::SignalState::Paused,
+        }
+    }
+}
+
+impl From<&SymbolSnapshot> for proto::SymbolState {
+    fn from(snapshot: &SymbolSnapshot) -> Self {
+        let latency = snapshot.latency.as_ref();
+        proto::SymbolState {
+            symbol: snapshot.symbol.clone(),
+            market: snapshot.market.as_str().to_string(),
+            ts_ms: snapshot.ts.map(|ts| ts.timestamp_millis()),
+            close_price: snapshot.close_price,
+            evaluation: Some(proto::Evaluation::from(&snapshot.evaluation)),
+            state: proto::SignalState::from(snapshot.state).into(),
+            messages: snapshot.messages,
+            signals: snapshot.signals,
+            buffer: Some(proto::BufferHealth {
+                len: snapshot.buffer.len as u32,
+                capacity: snapshot.buffer.capacity as u32,
+                span_seconds: snapshot.buffer.span_seconds,
+                duplicates: snapshot.anomalies.duplicates,
+                merged: snapshot.anomalies.merged,
+                reordered: snapshot.anomalies.reordered,
+                stale: snapshot.anomalies.stale,
+            }),
+            eval_latency_ms: snapshot.eval_latency_ms,
+            feed_latency_ms: latency.map(|stats| stats.latency_ms),
+            clock_skew_ms: latency.map(|stats| stats.clock_skew_ms),
+            latency_problem: snapshot.latency_problem.clone(),
+            reconnects: snapshot.reconnects,
+            received_at_ms: snapshot.received_at.map(|at| at.timestamp_millis()),
+        }
+    }
+}
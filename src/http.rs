@@ -0,0 +1,339 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Path, Request, State};
+use axum::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, patch, post};
+use axum::{Json, Router};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio_util::sync::CancellationToken;
+use crate::config::validate::normalize_symbol;
+use crate::config::{Config, SymbolConfig};
+use crate::error::ServerError;
+use crate::stream_monitor::hub::{Hub, SymbolControls};
+use crate::stream_monitor::snapshot::SymbolSnapshot;
+use crate::websocket::StateUpdate;
+
+// the `http` config section, the server is off unless enabled
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HttpConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+    // required as `Authorization: Bearer <token>` when set
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+fn default_listen() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 8080))
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig { enabled: false, listen: default_listen(), token: None }
+    }
+}
+
+// the symbol tasks are owned by main, the api asks it to start or stop one
+// the reply is why it couldn't, e.g. the symbol is still stopping
+pub enum SymbolCommand {
+    Add { symbol: SymbolConfig, reply: oneshot::Sender<Result<(), String>> },
+    Remove { symbol: String, reply: oneshot::Sender<Result<(), String>> },
+}
+
+#[derive(Clone)]
+struct Api {
+    hub: Hub,
+    // the config as changed by the api, only its symbols get saved
+    config: Arc<Mutex<Config>>,
+    // the symbols as loaded, the changes to them are what gets saved
+    loaded: Arc<Vec<SymbolConfig>>,
+    path: Arc<PathBuf>,
+    commands: mpsc::Sender<SymbolCommand>,
+    token: Option<Arc<str>>,
+}
+
+impl Api {
+    async fn command(&self, command: impl FnOnce(oneshot::Sender<Result<(), String>>) -> SymbolCommand) -> Result<(), ApiError> {
+        let (reply, replied) = oneshot::channel();
+        let unavailable = || ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "symbols can only be changed while monitoring live");
+        self.commands.send(command(reply)).await.map_err(|_| unavailable())?;
+        match replied.await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(message)) => Err(ApiError::new(StatusCode::CONFLICT, message)),
+            Err(_) => Err(unavailable()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    error: String,
+    // the config errors a change would have caused
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    issues: Vec<String>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, error: impl Into<String>) -> Self {
+        ApiError { status, error: error.into(), issues: vec![] }
+    }
+
+    fn not_monitored(symbol: &str) -> Self {
+        ApiError::new(StatusCode::NOT_FOUND, format!("{} is not monitored", symbol))
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, rejection.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(&self)).into_response()
+    }
+}
+
+// what the symbol list shows, the latest evaluation along with the controls
+#[derive(Debug, Serialize)]
+struct SymbolStatus {
+    #[serde(flatten)]
+    state: StateUpdate,
+    min_vol_usdt: f64,
+    paused: bool,
+}
+
+impl SymbolStatus {
+    fn new(snapshot: &SymbolSnapshot, controls: SymbolControls) -> Self {
+        SymbolStatus {
+            state: StateUpdate::from(snapshot),
+            min_vol_usdt: snapshot.evaluation.min_vol_usdt,
+            // the snapshot only catches up on the next tick
+            paused: controls.paused,
+        }
+    }
+}
+
+// sets the overrides left out of the body are kept
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Thresholds {
+    atr_threshold: Option<f64>,
+    min_vol_usdt: Option<f64>,
+}
+
+// serves until cancelled
+pub async fn serve(
+    listener: TcpListener,
+    hub: Hub,
+    config: Config,
+    path: PathBuf,
+    commands: mpsc::Sender<SymbolCommand>,
+    cancel: CancellationToken,
+) -> Result<(), ServerError> {
+    if let Ok(addr) = listener.local_addr() {
+        info!("http server listening on {}", addr);
+    }
+    let token = config.http.token.as_deref().map(Arc::from);
+    let loaded = Arc::new(config.symbols.clone());
+    let api = Api { hub, config: Arc::new(Mutex::new(config)), loaded, path: Arc::new(path), commands, token };
+    let router = Router::new()
+        .route("/symbols", get(list_symbols).post(add_symbol))
+        .route("/symbols/{symbol}", get(get_symbol).delete(remove_symbol))
+        .route("/symbols/{symbol}/thresholds", patch(update_thresholds))
+        .route("/symbols/{symbol}/pause", post(pause))
+        .route("/symbols/{symbol}/resume", post(resume))
+        .route("/config/save", post(save_config))
+        .layer(middleware::from_fn_with_state(api.clone(), authorize))
+        .with_state(api);
+    axum::serve(listener, router)
+        .with_graceful_shutdown(cancel.cancelled_owned())
+        .await
+        .map_err(ServerError::Http)
+}
+
+async fn authorize(State(api): State<Api>, request: Request, next: Next) -> Response {
+    if let Some(token) = &api.token {
+        let given = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if !given.is_some_and(|given| same_token(given.as_bytes(), token.as_bytes())) {
+            let error = ApiError::new(StatusCode::UNAUTHORIZED, "missing or wrong bearer token");
+            return ([(WWW_AUTHENTICATE, "Bearer")], error).into_response();
+        }
+    }
+    next.run(request).await
+}
+
+// takes as long for any wrong token of the same length
+fn same_token(given: &[u8], token: &[u8]) -> bool {
+    given.len() == token.len() && given.iter().zip(token).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// a change is only made if the config would still load with it
+fn check(config: &Config) -> Result<(), ApiError> {
+    let (_, report) = Config::check_value(config.to_value());
+    if !report.has_errors() {
+        return Ok(());
+    }
+    Err(ApiError {
+        status: StatusCode::BAD_REQUEST,
+        error: "the change makes the config invalid".to_string(),
+        issues: report.errors().map(|issue| issue.to_string()).collect(),
+    })
+}
+
+async fn list_symbols(State(api): State<Api>) -> Json<Vec<SymbolStatus>> {
+    let statuses = api
+        .hub
+        .snapshots()
+        .iter()
+        .map(|snapshot| SymbolStatus::new(snapshot, api.hub.controls(&snapshot.symbol).unwrap_or_default()))
+        .collect();
+    Json(statuses)
+}
+
+async fn get_symbol(State(api): State<Api>, Path(symbol): Path<String>) -> Result<Json<SymbolSnapshot>, ApiError> {
+    let symbol = normalize_symbol(&symbol);
+    api.hub.snapshot(&symbol).map(Json).ok_or_else(|| ApiError::not_monitored(&symbol))
+}
+
+// takes a symbol entry the way the config file does, a name or a mapping with its own settings
+async fn add_symbol(
+    State(api): State<Api>,
+    body: Result<Json<SymbolConfig>, JsonRejection>,
+) -> Result<(StatusCode, Json<SymbolStatus>), ApiError> {
+    let Json(mut symbol) = body?;
+    symbol.symbol = normalize_symbol(&symbol.symbol);
+    let mut config = api.config.lock().await;
+    if api.hub.snapshot(&symbol.symbol).is_some() {
+        return Err(ApiError::new(StatusCode::CONFLICT, format!("{} is already monitored", symbol.symbol)));
+    }
+    let mut changed = config.clone();
+    changed.symbols.push(symbol.clone());
+    check(&changed)?;
+
+    let name = symbol.symbol.clone();
+    api.command(|reply| SymbolCommand::Add { symbol, reply }).await?;
+    *config = changed;
+    info!(symbol = name.as_str(); "added {} over the http api", name);
+    let snapshot = api.hub.snapshot(&name).unwrap_or_else(|| SymbolSnapshot { symbol: name.clone(), ..Default::default() });
+    Ok((StatusCode::CREATED, Json(SymbolStatus::new(&snapshot, SymbolControls::default()))))
+}
+
+async fn remove_symbol(State(api): State<Api>, Path(symbol): Path<String>) -> Result<StatusCode, ApiError> {
+    let symbol = normalize_symbol(&symbol);
+    let mut config = api.config.lock().await;
+    if api.hub.snapshot(&symbol).is_none() {
+        return Err(ApiError::not_monitored(&symbol));
+    }
+    if config.reference.enabled && config.reference.symbol == symbol {
+        return Err(ApiError::new(StatusCode::CONFLICT, format!("{} is the reference of the other symbols", symbol)));
+    }
+
+    api.command(|reply| SymbolCommand::Remove { symbol: symbol.clone(), reply }).await?;
+    config.symbols.retain(|configured| configured.symbol != symbol);
+    info!(symbol = symbol.as_str(); "removed {} over the http api", symbol);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn update_thresholds(
+    State(api): State<Api>,
+    Path(symbol): Path<String>,
+    body: Result<Json<Thresholds>, JsonRejection>,
+) -> Result<Json<SymbolControls>, ApiError> {
+    let Json(thresholds) = body?;
+    if thresholds.atr_threshold.is_none() && thresholds.min_vol_usdt.is_none() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "expected atr_threshold, min_vol_usdt or both"));
+    }
+    let symbol = normalize_symbol(&symbol);
+    let mut config = api.config.lock().await;
+    if api.hub.controls(&symbol).is_none() {
+        return Err(ApiError::not_monitored(&symbol));
+    }
+    // a reference symbol that's only monitored for the others gets the new thresholds, but they aren't saved
+    let mut changed = config.clone();
+    let listed = changed.symbols.iter().position(|configured| configured.symbol == symbol);
+    let index = listed.unwrap_or_else(|| {
+        changed.symbols.push(SymbolConfig { symbol: symbol.clone(), ..Default::default() });
+        changed.symbols.len() - 1
+    });
+    let entry = &mut changed.symbols[index];
+    entry.atr_threshold = thresholds.atr_threshold.or(entry.atr_threshold);
+    entry.min_vol_usdt = thresholds.min_vol_usdt.or(entry.min_vol_usdt);
+    check(&changed)?;
+    if listed.is_some() {
+        *config = changed;
+    }
+
+    let controls = api
+        .hub
+        .update_controls(&symbol, |controls| {
+            controls.atr_threshold = thresholds.atr_threshold.or(controls.atr_threshold);
+            controls.min_vol_usdt = thresholds.min_vol_usdt.or(controls.min_vol_usdt);
+        })
+        .ok_or_else(|| ApiError::not_monitored(&symbol))?;
+    info!(
+        symbol = symbol.as_str(), atr_threshold = controls.atr_threshold, min_vol_usdt = controls.min_vol_usdt;
+        "updated the thresholds of {} over the http api", symbol
+    );
+    Ok(Json(controls))
+}
+
+async fn pause(State(api): State<Api>, Path(symbol): Path<String>) -> Result<Json<SymbolControls>, ApiError> {
+    set_paused(&api, &symbol, true)
+}
+
+async fn resume(State(api): State<Api>, Path(symbol): Path<String>) -> Result<Json<SymbolControls>, ApiError> {
+    set_paused(&api, &symbol, false)
+}
+
+// not part of the config, a restart resumes every symbol
+fn set_paused(api: &Api, symbol: &str, paused: bool) -> Result<Json<SymbolControls>, ApiError> {
+    let symbol = normalize_symbol(symbol);
+    let controls = api
+        .hub
+        .update_controls(&symbol, |controls| controls.paused = paused)
+        .ok_or_else(|| ApiError::not_monitored(&symbol))?;
+    info!(symbol = symbol.as_str(), paused = paused; "signalling of {} {} over the http api", symbol, if paused { "paused" } else { "resumed" });
+    Ok(Json(controls))
+}
+
+#[derive(Debug, Serialize)]
+struct Saved {
+    path: PathBuf,
+}
+
+// writes the symbols added, removed or changed over the api into the config file
+async fn save_config(State(api): State<Api>) -> Result<Json<Saved>, ApiError> {
+    let config = api.config.lock().await;
+    config
+        .save_symbols(&api.path, &api.loaded)
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    info!("saved the config to {:?} over the http api", api.path);
+    Ok(Json(Saved { path: api.path.as_ref().clone() }))
+}
+
+// TESTS
+#[test]
+fn test_same_token() {
+    assert!(same_token(b"secret", b"secret"));
+    assert!(!same_token(b"secreT", b"secret"));
+    assert!(!same_token(b"secret2", b"secret"));
+    assert!(!same_token(b"", b"secret"));
+}
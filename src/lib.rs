@@ -0,0 +1,9 @@
+pub mod config;
+pub mod error;
+pub mod grpc;
+pub mod http;
+pub mod logging;
+pub mod stream_monitor;
+pub mod tui;
+pub mod util;
+pub mod websocket;
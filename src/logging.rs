@@ -0,0 +1,226 @@
+use chrono::{SecondsFormat, Utc};
+use env_logger::{Builder, Target};
+use log::kv::{Key, Value, VisitSource};
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // one json object per line, with the structured fields of the record
+    Json,
+}
+
+// the `logging` config section
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LoggingConfig {
+    // falls back to RUST_LOG, then info
+    #[serde(default)]
+    pub level: Option<String>,
+    #[serde(default)]
+    pub format: LogFormat,
+    // per-module levels, e.g. `whiplash::stream_monitor: debug`
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+    #[serde(default)]
+    pub file: Option<LogFileConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    // rotate once the file grows over this size
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    // number of rotated files to keep next to the current one
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+fn default_max_bytes() -> u64 {
+    50 * 1024 * 1024
+}
+
+fn default_keep() -> usize {
+    5
+}
+
+// `quiet` drops the records unless there's a log file, e.g. while the tui owns the terminal
+pub fn init_logger(config: &LoggingConfig, quiet: bool) -> io::Result<()> {
+    let mut builder = Builder::new();
+    if let Ok(style) = env::var("RUST_LOG_STYLE") {
+        builder.parse_write_style(&style);
+    }
+    filters(&mut builder, config, env::var("RUST_LOG").ok().as_deref());
+
+    if config.format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let mut line = serde_json::Map::new();
+            line.insert("ts".into(), Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into());
+            line.insert("level".into(), record.level().as_str().into());
+            line.insert("target".into(), record.target().into());
+            line.insert("msg".into(), record.args().to_string().into());
+            let _ = record.key_values().visit(&mut JsonFields(&mut line));
+            writeln!(buf, "{}", serde_json::Value::Object(line))
+        });
+    }
+
+    if let Some(file) = &config.file {
+        builder.target(Target::Pipe(Box::new(RotatingFile::open(&file.path, file.max_bytes, file.keep)?)));
+        // no terminal colors in a file
+        builder.write_style(env_logger::WriteStyle::Never);
+    } else if quiet {
+        builder.target(Target::Pipe(Box::new(io::sink())));
+    }
+
+    builder.init();
+    Ok(())
+}
+
+// a level from `--log-level` or the config replaces RUST_LOG as a whole, its per-module directives included
+fn filters(builder: &mut Builder, config: &LoggingConfig, rust_log: Option<&str>) {
+    // validated with the rest of the config
+    match (config.level.as_ref().and_then(|level| level.parse::<LevelFilter>().ok()), rust_log) {
+        (Some(level), _) => builder.filter_level(level),
+        (None, Some(rust_log)) => builder.parse_filters(rust_log),
+        (None, None) => builder.filter_level(LevelFilter::Info),
+    };
+    for (module, level) in &config.modules {
+        if let Ok(level) = level.parse::<LevelFilter>() {
+            builder.filter_module(module, level);
+        }
+    }
+}
+
+// collects the structured fields of a record, e.g. `info!(symbol = s, atr = v; "...")`
+struct JsonFields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let value = serde_json::to_value(&value).unwrap_or_else(|_| value.to_string().into());
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+// size based rotation: whiplash.log -> whiplash.log.1 -> ... -> whiplash.log.<keep>
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            max_bytes,
+            keep,
+            file,
+            written,
+        })
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            for index in (1..self.keep).rev() {
+                let from = self.rotated(index);
+                if from.exists() {
+                    fs::rename(&from, self.rotated(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// TESTS
+#[test]
+fn test_rotating_file() {
+    let dir = env::temp_dir().join(format!("whiplash-rotation-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("whiplash.log");
+
+    let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+    for line in ["aaaaaaaa\n", "bbbbbbbb\n", "cccccccc\n", "dddddddd\n"] {
+        file.write_all(line.as_bytes()).unwrap();
+    }
+    file.flush().unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "dddddddd\n");
+    assert_eq!(fs::read_to_string(dir.join("whiplash.log.1")).unwrap(), "cccccccc\n");
+    assert_eq!(fs::read_to_string(dir.join("whiplash.log.2")).unwrap(), "bbbbbbbb\n");
+    // only `keep` rotated files are kept
+    assert!(!dir.join("whiplash.log.3").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_level_overrides_rust_log() {
+    let enabled = |config: &LoggingConfig, rust_log: Option<&str>, level: log::Level, target: &str| {
+        let mut builder = Builder::new();
+        filters(&mut builder, config, rust_log);
+        builder.build().matches(&log::Record::builder().level(level).target(target).build())
+    };
+    let rust_log = Some("warn,whiplash::stream_monitor=trace");
+
+    // without a level RUST_LOG applies, module directives and all
+    let config = LoggingConfig::default();
+    assert!(enabled(&config, rust_log, log::Level::Debug, "whiplash::stream_monitor"));
+    assert!(!enabled(&config, rust_log, log::Level::Info, "whiplash::http"));
+    assert!(enabled(&config, None, log::Level::Info, "whiplash::http"));
+
+    // `--log-level warn` silences the module RUST_LOG turned up
+    let config = LoggingConfig { level: Some("warn".into()), ..Default::default() };
+    assert!(!enabled(&config, rust_log, log::Level::Debug, "whiplash::stream_monitor"));
+    assert!(!enabled(&config, rust_log, log::Level::Info, "whiplash::stream_monitor"));
+    assert!(enabled(&config, rust_log, log::Level::Warn, "whiplash::stream_monitor"));
+
+    // the config's own module levels still apply on top
+    let config = LoggingConfig {
+        level: Some("warn".into()),
+        modules: BTreeMap::from([("whiplash::http".to_string(), "debug".to_string())]),
+        ..Default::default()
+    };
+    assert!(enabled(&config, rust_log, log::Level::Debug, "whiplash::http"));
+    assert!(!enabled(&config, rust_log, log::Level::Debug, "whiplash::stream_monitor"));
+}
@@ -1,60 +1,464 @@
-use futures;
-use std::env;
+use std::collections::HashMap;
 use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
 
+use clap::Parser;
+use cli::{Cli, Command};
 use log::{error, info, warn};
+use tokio::sync::mpsc;
+use tokio::task::{Id, JoinError, JoinSet};
+use tokio_util::sync::CancellationToken;
+use whiplash::http::SymbolCommand;
+use whiplash::stream_monitor::hub::Hub;
+use whiplash::stream_monitor::recorder::Recorder;
+use whiplash::stream_monitor::snapshot::Signal;
+use whiplash::stream_monitor::source::Source;
+use whiplash::error::{self, ConfigError, EXIT_CONFIG, EXIT_FAILURE, EXIT_FORCED, EXIT_SOFTWARE};
+use whiplash::{config, grpc, http, logging, stream_monitor, tui, util, websocket};
 
-pub mod config;
-pub mod stream_monitor;
-pub mod util;
+mod cli;
+
+// how long the symbol tasks get to close their streams and flush once shutdown is requested
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    util::init_logger();
+async fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let cli = Cli::parse();
+    // defaults, the config file, env and the command line
+    let sources = cli.config_sources();
+    let loaded = config::Config::load(&sources);
+    // the logger is configured by the config, so it can only be set up once that's loaded
+    let logging = match &loaded {
+        Ok(config::Loaded { config: Some(config), .. }) => config.logging.clone(),
+        _ => cli.logging(),
+    };
+    let quiet = matches!(cli.command, Some(Command::Tui));
+    if let Err(e) = logging::init_logger(&logging, quiet) {
+        eprintln!("failed to initialize logging: {}", e);
+        return Ok(ExitCode::FAILURE);
+    }
     info!("initializing whiplash");
-    // get the config path from args
-    let args: Vec<String> = env::args().collect();
-    let mut config_path = config::DEFAULT_CONFIG_PATH;
-    if args.len() != 2 {
-        warn!("config path not specified, using default {:?}", config::DEFAULT_CONFIG_PATH);
+
+    if let Some(Command::CheckConfig { effective }) = cli.command {
+        return Ok(check_config(loaded, effective));
+    }
+
+    let config = match loaded.and_then(|loaded| loaded.into_config()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("invalid config: {}", e);
+            return Ok(ExitCode::from(EXIT_CONFIG));
+        }
+    };
+
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => monitor(config, &sources.path, Source::Live, None).await,
+        Command::Record { output } => {
+            let (recorder, writer) = match Recorder::create(&output).await {
+                Ok(created) => created,
+                Err(e) => {
+                    let e = error::Error::from(e);
+                    error!("{}", e);
+                    return Ok((&e).into());
+                }
+            };
+            info!("recording frames to {:?}", output);
+            let code = monitor(config, &sources.path, Source::Live, Some(recorder)).await?;
+            // every recorder handle is gone by now, so the writer flushes and finishes
+            match writer.await? {
+                Ok(frames) => info!("recorded {} frames to {:?}", frames, output),
+                Err(e) => {
+                    let e = error::Error::from(e);
+                    error!("{:?}: {}", output, e);
+                    return Ok((&e).into());
+                }
+            }
+            Ok(code)
+        }
+        Command::Replay { input, speed } => {
+            if speed <= 0. {
+                error!("replay speed must be positive, got {}", speed);
+                return Ok(ExitCode::FAILURE);
+            }
+            monitor(config, &sources.path, Source::Replay { path: input, speed: Some(speed) }, None).await
+        }
+        Command::Backtest { input } => backtest(config, &sources.path, input).await,
+        Command::CheckConfig { .. } => unreachable!("handled before the config is loaded"),
+        Command::Tui => dashboard(config, &sources.path).await,
+        Command::Symbols => {
+            for symbol in &config.symbols {
+                println!("{}", symbol.symbol);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+// prints every issue of the config, fails if there's at least one error
+fn check_config(loaded: Result<config::Loaded, ConfigError>, effective: bool) -> ExitCode {
+    let loaded = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            println!("error: {}", e);
+            return ExitCode::from(EXIT_CONFIG);
+        }
+    };
+    if effective {
+        for line in loaded.layers.describe() {
+            println!("{}", line);
+        }
+    }
+    for issue in &loaded.report.issues {
+        println!("{}", issue);
+    }
+    let errors = loaded.report.errors().count();
+    let warnings = loaded.report.warnings().count();
+    if loaded.config.is_some() {
+        println!("config is valid ({} warning(s))", warnings);
+        ExitCode::SUCCESS
     } else {
-        config_path = &args[1];
-    }
-    // load the config using the path
-    let config = config::Config::from_file(config_path)?;
-    // Clone config data
-    let symbols = config.symbols.clone();
-    let atr_threshold = config.atr_threshold;
-    let atr_min_candles_percent = config.atr_min_candles_percent;
-    let min_vol_usdt = config.min_vol_usdt;
-    info!("found configuration: {:?}", config);
+        println!("config is invalid: {} error(s), {} warning(s)", errors, warnings);
+        ExitCode::from(EXIT_CONFIG)
+    }
+}
 
-    let mut handles = vec![];
-    // for each configured symbol, run the collect & monitor loop
-    for symbol in symbols {
-        info!("init data for {}", symbol);
-        // unwrap config here
-        let handler = stream_monitor::SymbolData::new(
-            symbol.as_str(),
-            atr_threshold,
-            atr_min_candles_percent,
-            min_vol_usdt,
+// spawns a monitoring task per symbol and runs until they finish or shutdown is requested
+async fn monitor(config: config::Config, path: &Path, source: Source, recorder: Option<Recorder>) -> Result<ExitCode, Box<dyn Error>> {
+    let hub = Hub::new();
+    Ok(spawn_and_drain(&config, path, &hub, source, recorder, CancellationToken::new()).await)
+}
+
+// monitors live with a dashboard on the terminal, quitting it shuts the monitoring down
+async fn dashboard(config: config::Config, path: &Path) -> Result<ExitCode, Box<dyn Error>> {
+    let hub = Hub::new();
+    let shutdown = CancellationToken::new();
+    let dashboard = tokio::spawn(tui::run(hub.clone(), shutdown.clone()));
+    let code = spawn_and_drain(&config, path, &hub, Source::Live, None, shutdown.clone()).await;
+    // the monitoring may have ended on its own, e.g. every symbol failed
+    shutdown.cancel();
+    // the terminal is restored by now, and the logger may be quiet
+    if let Err(e) = dashboard.await? {
+        eprintln!("failed to run the dashboard: {}", e);
+        return Ok(ExitCode::FAILURE);
+    }
+    if code != ExitCode::SUCCESS && config.logging.file.is_none() {
+        eprintln!("monitoring failed, set logging.file to keep the logs of the dashboard");
+    }
+    Ok(code)
+}
+
+// replays a recording on the event time and reports every signal it would have produced
+async fn backtest(config: config::Config, path: &Path, input: PathBuf) -> Result<ExitCode, Box<dyn Error>> {
+    let hub = Hub::new();
+    let mut signals = hub.subscribe_signals();
+    let collected = tokio::spawn(async move {
+        let mut collected: Vec<Signal> = vec![];
+        while let Ok(signal) = signals.recv().await {
+            collected.push(signal);
+        }
+        collected
+    });
+
+    let source = Source::Replay { path: input, speed: None };
+    let code = spawn_and_drain(&config, path, &hub, source, None, CancellationToken::new()).await;
+    // the hub holds the last signal sender, dropping it ends the collection
+    drop(hub);
+    let collected = collected.await?;
+
+    println!("backtest finished with {} signal(s)", collected.len());
+    for signal in &collected {
+        println!(
+            "{} {} close: {:.4}, atr: {:.4} ({:.3}%), volume: {:.3}",
+            signal.ts, signal.symbol, signal.close_price, signal.evaluation.atr, signal.evaluation.atr_pct, signal.evaluation.vol_usdt
         );
-        let outer_handle = tokio::spawn(async move {
-            info!("starting monitoring loop for {}", symbol);
-            if let Err(e) = stream_monitor::run(handler).await {
-                error!("failed to start handler for {}: {:?}", symbol, e)
+    }
+    Ok(code)
+}
+
+// the running symbol tasks, the api can start and stop them on the way
+struct SymbolTasks {
+    tasks: JoinSet<error::Result<()>>,
+    // the symbol of each task along with its own cancel token
+    running: HashMap<Id, (String, CancellationToken)>,
+    // removed symbols whose tasks haven't finished yet
+    stopping: HashMap<Id, String>,
+    hub: Hub,
+    source: Source,
+    recorder: Option<Recorder>,
+    shutdown: CancellationToken,
+}
+
+impl SymbolTasks {
+    // runs the collect & monitor loop of the symbol
+    fn spawn(&mut self, config: &config::Config, symbol_config: &config::SymbolConfig) {
+        let symbol = symbol_config.symbol.clone();
+        let settings = config.symbol_settings(symbol_config);
+        info!(symbol = symbol.as_str(), market = settings.market.as_str(); "init data for {} on {}", symbol, settings.market);
+        let handler = stream_monitor::SymbolData::new(symbol.as_str(), settings);
+        let publisher = self.hub.register(&symbol);
+        let cancel = self.shutdown.child_token();
+        let (source, recorder) = (self.source.clone(), self.recorder.clone());
+        let task = {
+            let (symbol, cancel) = (symbol.clone(), cancel.clone());
+            async move {
+                info!(symbol = symbol.as_str(); "starting monitoring loop for {}", symbol);
+                let result = stream_monitor::run(handler, publisher, cancel, source, recorder).await;
+                // only fatal errors get this far, the retryable ones are handled within the task
+                if let Err(e) = &result {
+                    error!(symbol = symbol.as_str(); "monitoring {} failed: {}", symbol, e)
+                }
+                result
+            }
+        };
+        let id = self.tasks.spawn(task).id();
+        self.running.insert(id, (symbol, cancel));
+    }
+
+    fn handle(&mut self, config: &config::Config, command: SymbolCommand) {
+        match command {
+            SymbolCommand::Add { symbol, reply } => {
+                let result = if self.stopping.values().any(|stopping| *stopping == symbol.symbol) {
+                    Err(format!("{} is still stopping, try again in a moment", symbol.symbol))
+                } else if self.running.values().any(|(running, _)| *running == symbol.symbol) {
+                    Err(format!("{} is already monitored", symbol.symbol))
+                } else {
+                    self.spawn(config, &symbol);
+                    Ok(())
+                };
+                let _ = reply.send(result);
             }
-        });
-        handles.push(outer_handle);
+            SymbolCommand::Remove { symbol, reply } => {
+                let id = self.running.iter().find(|(_, (running, _))| *running == symbol).map(|(id, _)| *id);
+                let result = match id.and_then(|id| self.running.remove(&id).map(|(_, cancel)| (id, cancel))) {
+                    Some((id, cancel)) => {
+                        info!(symbol = symbol.as_str(); "stopping monitoring loop for {}", symbol);
+                        cancel.cancel();
+                        // stays registered until the task is joined, so it can't be added again while it flushes
+                        self.stopping.insert(id, symbol);
+                        Ok(())
+                    }
+                    None if self.stopping.values().any(|stopping| *stopping == symbol) => {
+                        Err(format!("{} is still stopping, try again in a moment", symbol))
+                    }
+                    None => Err(format!("{} is not monitored", symbol)),
+                };
+                let _ = reply.send(result);
+            }
+        }
     }
 
-    // run until interrupted
-    if let Err(e) = futures::future::try_join_all(handles).await {
-        error!("failed to run the orchestra: {:?}", e);
+    // the exit code if the task failed
+    fn finished(&mut self, joined: Result<(Id, error::Result<()>), JoinError>) -> Option<u8> {
+        let id = match &joined {
+            Ok((id, _)) => *id,
+            Err(e) => e.id(),
+        };
+        self.running.remove(&id);
+        if let Some(symbol) = self.stopping.remove(&id) {
+            self.hub.unregister(&symbol);
+            info!(symbol = symbol.as_str(); "stopped monitoring {}", symbol);
+        }
+        failure(joined.map(|(_, result)| result))
     }
-    tokio::signal::ctrl_c().await?;
+}
+
+// every symbol task and server gets a child of `shutdown`
+async fn spawn_and_drain(
+    config: &config::Config,
+    path: &Path,
+    hub: &Hub,
+    source: Source,
+    recorder: Option<Recorder>,
+    shutdown: CancellationToken,
+) -> ExitCode {
+    info!("found configuration: {:?}", config);
+
+    // a taken port fails the startup rather than leaving the downstream services without a feed
+    let grpc_listener = match listen(config.grpc.enabled, config.grpc.listen).await {
+        Ok(listener) => listener,
+        Err(code) => return code,
+    };
+    let websocket_listener = match listen(config.websocket.enabled, config.websocket.listen).await {
+        Ok(listener) => listener,
+        Err(code) => return code,
+    };
+    let http_listener = match listen(config.http.enabled, config.http.listen).await {
+        Ok(listener) => listener,
+        Err(code) => return code,
+    };
+
+    // symbols are only added to and removed from a live feed, a recording has what it has
+    let live = matches!(source, Source::Live);
+    let (commands_tx, commands) = mpsc::channel(16);
+    let mut commands = (live && http_listener.is_some()).then_some(commands);
+
+    let mut tasks = SymbolTasks {
+        tasks: JoinSet::new(),
+        running: HashMap::new(),
+        stopping: HashMap::new(),
+        hub: hub.clone(),
+        source,
+        recorder,
+        shutdown: shutdown.clone(),
+    };
+    // for each configured symbol and the reference, run the collect & monitor loop
+    for symbol_config in &config.monitored_symbols() {
+        tasks.spawn(config, symbol_config);
+    }
+
+    // sees every symbol, tags their signals as market-wide or idiosyncratic
+    let aggregator = tokio::spawn(stream_monitor::regime::aggregate(hub.clone(), config.regime, shutdown.child_token()));
+
+    // the servers outlive the symbols until they're drained, e.g. at the end of a replay
+    let servers = shutdown.child_token();
+    let grpc_server = grpc_listener.map(|listener| {
+        let (hub, servers) = (hub.clone(), servers.clone());
+        tokio::spawn(async move {
+            // the symbols go on without the api
+            if let Err(e) = grpc::serve(listener, hub, servers).await {
+                error!("{}", error::Error::from(e));
+            }
+        })
+    });
+    let websocket_server = websocket_listener.map(|listener| {
+        tokio::spawn(websocket::serve(listener, hub.clone(), config.websocket, servers.clone()))
+    });
+    let http_server = http_listener.map(|listener| {
+        let (hub, config, path, servers) = (hub.clone(), config.clone(), path.to_path_buf(), servers.clone());
+        tokio::spawn(async move {
+            if let Err(e) = http::serve(listener, hub, config, path, commands_tx, servers).await {
+                error!("{}", error::Error::from(e));
+            }
+        })
+    });
+
+    // run until interrupted, a second signal skips the graceful part
+    let signal_token = shutdown.clone();
+    let signal_handle = tokio::spawn(async move {
+        util::shutdown_signal().await;
+        info!("shutting down, waiting up to {:?} for the symbol tasks", SHUTDOWN_TIMEOUT);
+        signal_token.cancel();
+        util::shutdown_signal().await;
+        warn!("forced shutdown");
+        std::process::exit(EXIT_FORCED as i32);
+    });
+
+    let failures = drain(&mut tasks, config, commands.as_mut(), &shutdown).await;
+    // the symbol tasks are done with the recorder
+    drop(tasks);
+    // nothing left to aggregate or to serve
+    aggregator.abort();
+    servers.cancel();
+    for server in [grpc_server, websocket_server, http_server].into_iter().flatten() {
+        match server.await {
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            _ => {}
+        }
+    }
+    if !shutdown.is_cancelled() {
+        // everything finished on its own, e.g. a replay
+        signal_handle.abort();
+    }
+
+    log::logger().flush();
+    summarize(hub);
 
-    Ok(())
+    // the first failure decides the exit code
+    if let Some(code) = failures.first() {
+        error!("{} symbol task(s) failed", failures.len());
+        return ExitCode::from(*code);
+    }
+    info!("whiplash stopped");
+    ExitCode::SUCCESS
+}
+
+// binds the port of an enabled server, the error is logged
+async fn listen(enabled: bool, addr: std::net::SocketAddr) -> Result<Option<tokio::net::TcpListener>, ExitCode> {
+    if !enabled {
+        return Ok(None);
+    }
+    util::bind(addr).await.map(Some).map_err(|e| {
+        let e = error::Error::from(e);
+        error!("{}", e);
+        ExitCode::from(&e)
+    })
+}
+
+fn failure(joined: Result<error::Result<()>, JoinError>) -> Option<u8> {
+    match joined {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.exit_code()),
+        // a panic
+        Err(_) => Some(EXIT_SOFTWARE),
+    }
+}
+
+// waits for every symbol task to finish, returns the exit codes of the failed ones
+// with the api taking commands it runs until shutdown, even with no symbols left
+// after shutdown is requested the tasks only get SHUTDOWN_TIMEOUT, the stragglers are aborted
+async fn drain(
+    tasks: &mut SymbolTasks,
+    config: &config::Config,
+    mut commands: Option<&mut mpsc::Receiver<SymbolCommand>>,
+    shutdown: &CancellationToken,
+) -> Vec<u8> {
+    let mut failures = vec![];
+    loop {
+        tokio::select! {
+            next = tasks.tasks.join_next_with_id(), if commands.is_none() || !tasks.tasks.is_empty() => match next {
+                Some(joined) => failures.extend(tasks.finished(joined)),
+                None => return failures,
+            },
+            Some(command) = async { commands.as_mut()?.recv().await }, if commands.is_some() => tasks.handle(config, command),
+            _ = shutdown.cancelled() => break,
+        }
+    }
+
+    let deadline = tokio::time::Instant::now() + SHUTDOWN_TIMEOUT;
+    loop {
+        match tokio::time::timeout_at(deadline, tasks.tasks.join_next_with_id()).await {
+            Ok(Some(joined)) => failures.extend(tasks.finished(joined)),
+            Ok(None) => return failures,
+            Err(_) => {
+                warn!("{} symbol task(s) did not stop in time, aborting", tasks.tasks.len());
+                failures.extend(std::iter::repeat_n(EXIT_FAILURE, tasks.tasks.len()));
+                tasks.tasks.shutdown().await;
+                return failures;
+            }
+        }
+    }
 }
 
+fn summarize(hub: &Hub) {
+    for snapshot in hub.snapshots() {
+        if let Some(latency) = &snapshot.latency {
+            let bucket = |quantile| match latency.percentile(quantile) {
+                Some(le) => format!("<= {}ms", le),
+                None => "over 5s".to_string(),
+            };
+            info!(
+                symbol = snapshot.symbol.as_str(), latency_ms = latency.latency_ms, clock_skew_ms = latency.clock_skew_ms;
+                "feed latency for {}: p50 {}, p99 {}, clock skew estimate {:.0}ms",
+                snapshot.symbol, bucket(0.5), bucket(0.99), latency.clock_skew_ms
+            );
+        }
+        let e = &snapshot.evaluation;
+        info!(
+            symbol = snapshot.symbol.as_str(), messages = snapshot.messages, signals = snapshot.signals,
+            atr = e.atr, atr_pct = e.atr_pct, vol_usdt = e.vol_usdt, state = snapshot.state.as_str(), reason = e.reason.as_str();
+            "summary for {}: messages: {}, signals: {}, last close: {:.3}, atr: {:.3}, volume: {:.3}, {}",
+            snapshot.symbol, snapshot.messages, snapshot.signals, snapshot.close_price, e.atr, e.vol_usdt, e
+        );
+        let anomalies = snapshot.anomalies;
+        if anomalies != Default::default() {
+            info!(
+                symbol = snapshot.symbol.as_str(), duplicates = anomalies.duplicates, merged = anomalies.merged,
+                reordered = anomalies.reordered, stale = anomalies.stale;
+                "out of order messages for {}: duplicates: {}, merged: {}, reordered: {}, stale: {}",
+                snapshot.symbol, anomalies.duplicates, anomalies.merged, anomalies.reordered, anomalies.stale
+            );
+        }
+    }
+}
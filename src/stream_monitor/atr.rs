@@ -1,351 +1,427 @@
-use std::collections::HashMap;
-use std::error::Error;
-use super::buffer::SymbolBuffer;
-use chrono::{Duration, Timelike, Utc};
-
-#[derive(Debug)]
-pub struct ATRInputData {
-    pub lows: Vec<f64>,
-    pub highs: Vec<f64>,
-    pub closes: Vec<f64>,
+use std::collections::VecDeque;
+use std::str::FromStr;
+use super::buffer::BufferNode;
+use super::evaluation::{Evaluation, Reason};
+use crate::error::IndicatorError;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum MovingAverage {
+    Ema,
+    Rma,
+    Sma,
 }
 
+impl FromStr for MovingAverage {
+    type Err = IndicatorError;
 
-pub fn check_atr_condition(
-    buffer: &SymbolBuffer,
-    seconds: usize,
-    atr_threshold: f64,
-    atr_min_candles_percent: f64
-) -> Result<(bool, f64), Box<dyn Error>> {
-    let atr_input = get_atr_data(buffer, seconds)?;
-
-    let mut seconds_to_fetch = seconds;
-    let actual_atr_seconds = atr_input.closes.len();
-
-    // Not enough candles to calculate accurate ATR
-    if actual_atr_seconds < (seconds as f64 * atr_min_candles_percent).ceil() as usize {
-        return Ok((false, 0.));
-    }
-
-    if seconds > actual_atr_seconds {
-        seconds_to_fetch = actual_atr_seconds;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "EMA" => Ok(MovingAverage::Ema),
+            "RMA" => Ok(MovingAverage::Rma),
+            "SMA" => Ok(MovingAverage::Sma),
+            _ => Err(IndicatorError::UnknownMovingAverage(s.to_string())),
+        }
     }
+}
 
-    // Calculate ATR
-    let calculated_atr = calculate_atr(&atr_input, seconds_to_fetch)?;
-    let close_price = atr_input.closes[actual_atr_seconds - 1];
+// a single per-second candle built from the close prices of the kline updates
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Bar {
+    pub second: i64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    // close of the previous bar, used for the true range
+    pub prev_close: Option<f64>,
+    pub tr: f64,
+    // timestamp of the latest node folded into the bar
+    pub last_ts: DateTime<Utc>,
+}
 
-    if calculated_atr == 0.0 {
-        return Ok((false, 0.));
+impl Bar {
+    fn new(node: &BufferNode, prev_close: Option<f64>) -> Self {
+        let mut bar = Bar {
+            second: node.ts.timestamp(),
+            high: node.close_price,
+            low: node.close_price,
+            close: node.close_price,
+            prev_close,
+            tr: 0.,
+            last_ts: node.ts,
+        };
+        bar.tr = bar.true_range();
+        bar
     }
 
-    let is_atr_limit_passed = (calculated_atr / close_price) > (atr_threshold / 100.0);
-
-    Ok((is_atr_limit_passed, calculated_atr))
-}
-
-fn calculate_atr(input: &ATRInputData, seconds: usize) -> Result<f64, Box<dyn Error>>{
-    let atr_arr = atr_ema(&input.highs, &input.lows, &input.closes, seconds-1);
-    let length  = atr_arr.len();
-    if length != 0 {
-        Ok(atr_arr[length-1])
-    } else {
-        Ok(0.)
+    // the very first bar has nothing to range from
+    fn true_range(&self) -> f64 {
+        match self.prev_close {
+            Some(pc) => f64::max(self.high - self.low, f64::max((pc - self.high).abs(), (pc - self.low).abs())),
+            None => 0.,
+        }
     }
+}
 
+// incremental moving average, seeded with a plain average of the first `period` values
+// the ema takes a single ema step off the seed and wilder's steps from then on
+#[derive(Debug, Clone)]
+struct Smoother {
+    moving_average: MovingAverage,
+    period: usize,
+    count: usize,
+    value: f64,
 }
 
-fn get_atr_data(buffer: &SymbolBuffer, seconds: usize) -> Result<ATRInputData, Box<dyn Error>> {
-    if seconds > 60 {
-        return Err("requested interval exceeds minute buffer length".into());
+impl Smoother {
+    fn new(moving_average: MovingAverage, window_seconds: usize) -> Self {
+        let period = match moving_average {
+            MovingAverage::Ema => window_seconds.saturating_sub(1),
+            _ => window_seconds,
+        };
+        Smoother { moving_average, period: period.max(1), count: 0, value: 0. }
     }
 
-    let latest_timestamp = buffer.back().map(|node| node.ts).unwrap_or(Utc::now());
-    let stop_time = latest_timestamp - Duration::seconds(seconds as i64);
-
-    let mut key_order = Vec::new();
-    let mut lows_map = HashMap::new();
-    let mut highs_map = HashMap::new();
-    let mut closes_map = HashMap::new();
+    fn k(&self) -> f64 {
+        match self.moving_average {
+            MovingAverage::Ema if self.count == self.period => 2.0 / (self.period + 1) as f64,
+            _ => 1.0 / self.period as f64,
+        }
+    }
 
-    for (iter, current_node) in buffer.iter().rev().enumerate() {
-        let current_second = current_node.ts.second() as i32;
+    fn push(&mut self, x: f64) {
+        self.value = self.peek(x);
+        self.count += 1;
+    }
 
-        if key_order.is_empty() || *key_order.last().unwrap() != current_second {
-            key_order.push(current_second);
+    // value the average would have after pushing x, without pushing it
+    fn peek(&self, x: f64) -> f64 {
+        if self.count < self.period {
+            (self.value * self.count as f64 + x) / (self.count + 1) as f64
+        } else {
+            self.value + self.k() * (x - self.value)
         }
+    }
+}
 
-        if closes_map.get(&current_second).is_none() {
-            closes_map.insert(current_second, current_node.close_price);
-        }
+// rolling per-second true range over the check window
+// every update is O(1): the open bar is updated in place and the window sums are adjusted
+// by the difference instead of being recalculated
+#[derive(Debug, Clone)]
+pub struct AtrState {
+    window: Duration,
+    window_seconds: usize,
+    moving_average: MovingAverage,
+    bars: VecDeque<Bar>,
+    tr_sum: f64,
+    smoother: Smoother,
+    last_close: Option<f64>,
+}
 
-        let high_entry = highs_map.entry(current_second).or_insert(current_node.close_price);
-        if current_node.close_price > *high_entry {
-            *high_entry = current_node.close_price;
-        }
+// what the state accumulated, the window and the moving average come from the config
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SavedAtr {
+    pub bars: Vec<Bar>,
+    // completed bars folded into the moving average, and its value
+    pub smoothed: usize,
+    pub smoothed_value: f64,
+    pub last_close: Option<f64>,
+}
 
-        let low_entry = lows_map.entry(current_second).or_insert(current_node.close_price);
-        if current_node.close_price < *low_entry {
-            *low_entry = current_node.close_price;
+impl AtrState {
+    pub fn new(window_seconds: usize, moving_average: MovingAverage) -> Self {
+        AtrState {
+            window: Duration::seconds(window_seconds as i64),
+            window_seconds,
+            moving_average,
+            bars: VecDeque::with_capacity(window_seconds + 2),
+            tr_sum: 0.,
+            smoother: Smoother::new(moving_average, window_seconds),
+            last_close: None,
         }
+    }
 
-        if let Some(previous_node) = buffer.iter().rev().nth(iter + 1) {
-            if previous_node.ts <= stop_time || (previous_node.ts == latest_timestamp && iter != 0) {
-                break;
+    pub fn update(&mut self, node: &BufferNode) {
+        let second = node.ts.timestamp();
+        match self.bars.back().map(|bar| bar.second) {
+            Some(current) if current == second => {
+                let bar = self.bars.back_mut().unwrap();
+                let old_tr = bar.tr;
+                bar.high = bar.high.max(node.close_price);
+                bar.low = bar.low.min(node.close_price);
+                // a late node within the second doesn't move the close
+                if node.ts >= bar.last_ts {
+                    bar.close = node.close_price;
+                    bar.last_ts = node.ts;
+                    self.last_close = Some(node.close_price);
+                }
+                bar.tr = bar.true_range();
+                self.tr_sum += bar.tr - old_tr;
+            }
+            Some(current) if current > second => {
+                // late node, only widen the range of its bar if it's still in the window
+                if let Some(bar) = self.bars.iter_mut().rev().find(|bar| bar.second == second) {
+                    let old_tr = bar.tr;
+                    bar.high = bar.high.max(node.close_price);
+                    bar.low = bar.low.min(node.close_price);
+                    bar.tr = bar.true_range();
+                    self.tr_sum += bar.tr - old_tr;
+                }
+            }
+            _ => {
+                // the open bar is complete, fold it into the moving average
+                if let Some(bar) = self.bars.back() {
+                    self.smoother.push(bar.tr);
+                }
+                let bar = Bar::new(node, self.last_close);
+                self.tr_sum += bar.tr;
+                self.bars.push_back(bar);
+                self.last_close = Some(node.close_price);
             }
         }
+        let latest = self.bars.back().map(|bar| bar.last_ts).unwrap_or(node.ts);
+        self.evict(latest);
     }
 
-    key_order.reverse();
-
-    let mut lows = Vec::new();
-    let mut highs = Vec::new();
-    let mut closes = Vec::new();
-
-    for key in key_order {
-        lows.push(*lows_map.get(&key).unwrap());
-        highs.push(*highs_map.get(&key).unwrap());
-        closes.push(*closes_map.get(&key).unwrap());
+    fn evict(&mut self, latest: DateTime<Utc>) {
+        let stop_time = latest - self.window;
+        while self.bars.len() > 1 && self.bars.front().map(|bar| bar.last_ts <= stop_time).unwrap_or(false) {
+            let bar = self.bars.pop_front().unwrap();
+            self.tr_sum -= bar.tr;
+        }
+        if self.bars.len() == 1 {
+            // avoid accumulating float drift
+            self.tr_sum = self.bars[0].tr;
+        }
     }
 
-    let result = ATRInputData {
-        lows,
-        highs,
-        closes,
-    };
-
-    Ok(result)
-}
-
-// atr calculation rewrites
-// TODO: add SMA and RMA and make it configurable
-
-// calculate atr using ema
-pub fn atr_ema(in_high: &[f64], in_low: &[f64], in_close: &[f64], in_time_period: usize) -> Vec<f64> {
-    let mut out_real = vec![0.0; in_close.len()];
-
-    let in_time_period_f = in_time_period as f64;
-
-    if in_time_period < 1 {
-        return out_real;
+    pub fn save(&self) -> SavedAtr {
+        SavedAtr {
+            bars: self.bars.iter().copied().collect(),
+            smoothed: self.smoother.count,
+            smoothed_value: self.smoother.value,
+            last_close: self.last_close,
+        }
     }
 
-    if in_time_period <= 1 {
-        return true_range(in_high, in_low, in_close);
+    pub fn restore(&mut self, saved: SavedAtr) {
+        self.bars = saved.bars.into();
+        self.tr_sum = self.bars.iter().map(|bar| bar.tr).sum();
+        self.smoother.count = saved.smoothed;
+        self.smoother.value = saved.smoothed_value;
+        self.last_close = saved.last_close;
     }
 
-    let tr = true_range(in_high, in_low, in_close);
-    let prev_atr_temp = calc_ema(&tr, in_time_period);
-    let mut prev_atr = prev_atr_temp[in_time_period];
-    out_real[in_time_period] = prev_atr;
-
-    let mut out_idx = in_time_period+1;
-    let mut today = in_time_period + 1;
-
-    while out_idx < in_close.len() {
-        prev_atr = (prev_atr * (in_time_period_f - 1.0) + tr[today]) / in_time_period_f;
-        out_real[out_idx] = prev_atr;
-        today += 1;
-        out_idx += 1;
+    pub fn bars(&self) -> &VecDeque<Bar> {
+        &self.bars
     }
 
-    out_real
-}
-
-fn calc_ema(in_real: &[f64], in_time_period: usize) -> Vec<f64> {
-    let k = 2.0 / ((in_time_period + 1) as f64);
-    ema(in_real, in_time_period, k)
-}
-
-fn ema(in_real: &[f64], in_time_period: usize, k1: f64) -> Vec<f64> {
-    let mut out_real = vec![0.0; in_real.len()];
-    let lookback_total = in_time_period - 1;
-    let start_idx = lookback_total;
-    let mut today = start_idx - lookback_total;
-
-    // sma first
-    let mut temp_real = 0.0;
-    for _ in 0..in_time_period {
-        temp_real += in_real[today];
-        today += 1;
+    pub fn candles(&self) -> usize {
+        self.bars.len()
     }
-    let mut prev_ma = temp_real / in_time_period as f64;
 
-    // ema for the first element
-    while today <= start_idx {
-        prev_ma = ((in_real[today] - prev_ma) * k1) + prev_ma;
-        today += 1;
-    }
-    out_real[start_idx] = prev_ma;
-    let mut out_idx = start_idx + 1;
-
-    // ema for the rest
-    while today < in_real.len() {
-        prev_ma = ((in_real[today] - prev_ma) * k1) + prev_ma;
-        out_real[out_idx] = prev_ma;
-        today += 1;
-        out_idx += 1;
+    pub fn close(&self) -> Option<f64> {
+        self.bars.back().map(|bar| bar.close)
     }
 
-    out_real
-}
-
-
-pub fn true_range(in_high: &[f64], in_low: &[f64], in_close: &[f64]) -> Vec<f64> {
-    let len = in_close.len();
-    let mut out_real = vec![0.0; len];
-
-    let mut today = 1;
-    while today < len {
-        let temp_lt = in_low[today];
-        let temp_ht = in_high[today];
-        let temp_cy = in_close[today - 1];
-
-        let greatest = f64::max(temp_ht - temp_lt, f64::max((temp_cy - temp_ht).abs(), (temp_cy - temp_lt).abs()));
-
-        out_real[today] = greatest;
-        today += 1;
+    pub fn value(&self) -> f64 {
+        let Some(current) = self.bars.back() else {
+            return 0.;
+        };
+        match self.moving_average {
+            MovingAverage::Sma => self.tr_sum / self.bars.len() as f64,
+            _ => self.smoother.peek(current.tr),
+        }
     }
 
-    out_real
+    // checks the atr conditions, the volume and the freshness of the data are up to the caller
+    pub fn check(&self, atr_threshold: f64, atr_min_candles_percent: f64) -> Evaluation {
+        let atr = self.value();
+        let close_price = self.close().unwrap_or(0.);
+        let mut evaluation = Evaluation {
+            atr,
+            atr_pct: if close_price > 0. { atr / close_price * 100. } else { 0. },
+            atr_threshold,
+            candles: self.candles(),
+            min_candles: (self.window_seconds as f64 * atr_min_candles_percent).ceil() as usize,
+            ..Default::default()
+        };
+
+        evaluation.reason = if evaluation.candles < evaluation.min_candles {
+            // not enough candles to calculate accurate ATR
+            Reason::InsufficientCandles
+        } else if atr == 0.0 {
+            Reason::ZeroRange
+        } else if evaluation.atr_pct <= atr_threshold {
+            Reason::BelowThreshold
+        } else {
+            Reason::Triggered
+        };
+        evaluation
+    }
 }
 
 // TESTS
-#[test]
-fn test_get_atr_data() {
+#[cfg(test)]
+use super::buffer::node;
 
-    use super::BufferNode;
-    use circular_buffer::CircularBuffer;
+#[test]
+fn test_atr_bars() {
     // get current time and round it to full seconds so that we have clean start
     let start_time = Utc::now();
     let nanos_to_deduct = start_time.timestamp_subsec_nanos() as i64;
     let start_time = start_time - Duration::nanoseconds(nanos_to_deduct);
     // we'll try to get the data for the last second
     // and make sure our nodes cross the boundary at some point
-    let node1 = BufferNode {
-        value: 45.,
-        ts: start_time - Duration::milliseconds(50),
-        confirmed: true,
-        close_price: 55.,
-    };
-    let node2 = BufferNode {
-        value: 44.,
-        ts: start_time - Duration::milliseconds(250),
-        confirmed: false,
-        close_price: 59.,
-    };
-    let node3 = BufferNode {
-        value: 43.,
-        ts: start_time - Duration::milliseconds(450),
-        confirmed: false,
-        close_price: 53.,
-    };
-    let node4 = BufferNode {
-        value: 42.,
-        ts: start_time - Duration::milliseconds(650),
-        confirmed: false,
-        close_price: 52.,
-    };
-    let node5 = BufferNode {
-        value: 41.,
-        ts: start_time - Duration::milliseconds(1050),
-        confirmed: true,
-        close_price: 51.,
-    };
-
-    let nodes = vec![node5, node4, node3, node2, node1];
-    let mut buffer = CircularBuffer::<244, BufferNode>::new();
-
+    let nodes = vec![
+        node(start_time - Duration::milliseconds(1050), 0., 51.),
+        node(start_time - Duration::milliseconds(650), 0., 52.),
+        node(start_time - Duration::milliseconds(450), 0., 53.),
+        node(start_time - Duration::milliseconds(250), 0., 59.),
+        node(start_time - Duration::milliseconds(50), 0., 55.),
+    ];
+    let mut state = AtrState::new(1, MovingAverage::Ema);
     for node in nodes {
-        buffer.push_back(node)
+        state.update(&node);
     }
 
-    let recv_atr_data = get_atr_data(&mut buffer, 1).unwrap();
-
-    assert_eq!(recv_atr_data.closes.len(), 1);
-    assert_eq!(recv_atr_data.closes, vec![55.]);
-    assert_eq!(recv_atr_data.highs, vec![59.]);
-    assert_eq!(recv_atr_data.lows, vec![52.]);
-
+    let bars: Vec<&Bar> = state.bars().iter().collect();
+    assert_eq!(bars.len(), 1);
+    assert_eq!(bars[0].close, 55.);
+    assert_eq!(bars[0].high, 59.);
+    assert_eq!(bars[0].low, 52.);
 }
 
 #[test]
-fn test_get_atr_data_cross() {
-
-    use super::BufferNode;
-    use circular_buffer::CircularBuffer;
+fn test_atr_bars_cross() {
     // get current time and round it to full seconds so that we have clean start
     let start_time = Utc::now();
     let nanos_to_deduct = start_time.timestamp_subsec_nanos() as i64;
     let start_time = start_time - Duration::nanoseconds(nanos_to_deduct) + Duration::milliseconds(500);
-    // we'll try to get the data for the last second
-    // and make sure our nodes cross the boundary twice
-    let node1 = BufferNode {
-        value: 45.,
-        ts: start_time - Duration::milliseconds(50),
-        confirmed: false,
-        close_price: 55.,
-    };
-    let node2 = BufferNode {
-        value: 44.,
-        ts: start_time - Duration::milliseconds(250),
-        confirmed: false,
-        close_price: 59.,
-    };
-    // nodes from 2nd second, but still close enough
-    let node3 = BufferNode {
-        value: 43.,
-        ts: start_time - Duration::milliseconds(550),
-        confirmed: true,
-        close_price: 53.,
-    };
-    let node4 = BufferNode {
-        value: 42.,
-        ts: start_time - Duration::milliseconds(650),
-        confirmed: false,
-        close_price: 52.,
-    };
-    // node from 2nd second, but out of interval
-    let node5 = BufferNode {
-        value: 42.,
-        ts: start_time - Duration::milliseconds(1050),
-        confirmed: false,
-        close_price: 52.,
-    };
-    // node from 3rd second, should be omitted
-    let node6 = BufferNode {
-        value: 41.,
-        ts: start_time - Duration::milliseconds(1550),
-        confirmed: true,
-        close_price: 51.,
-    };
-
-    let nodes = vec![node6, node5, node4, node3, node2, node1];
-    let mut buffer = CircularBuffer::<244, BufferNode>::new();
-
+    // the last node of the 2nd second is still within the interval,
+    // the bar of the 3rd second should be evicted
+    let nodes = vec![
+        node(start_time - Duration::milliseconds(1550), 0., 51.),
+        node(start_time - Duration::milliseconds(1050), 0., 52.),
+        node(start_time - Duration::milliseconds(650), 0., 52.),
+        node(start_time - Duration::milliseconds(550), 0., 53.),
+        node(start_time - Duration::milliseconds(250), 0., 59.),
+        node(start_time - Duration::milliseconds(50), 0., 55.),
+    ];
+    let mut state = AtrState::new(1, MovingAverage::Ema);
     for node in nodes {
-        buffer.push_back(node)
+        state.update(&node);
     }
 
-    let recv_atr_data = get_atr_data(&mut buffer, 1).unwrap();
+    let closes: Vec<f64> = state.bars().iter().map(|bar| bar.close).collect();
+    let highs: Vec<f64> = state.bars().iter().map(|bar| bar.high).collect();
+    let lows: Vec<f64> = state.bars().iter().map(|bar| bar.low).collect();
+    assert_eq!(closes, vec![53., 55.]);
+    assert_eq!(highs, vec![53., 59.]);
+    assert_eq!(lows, vec![52., 55.]);
+}
 
-    assert_eq!(recv_atr_data.closes.len(), 2);
-    assert_eq!(recv_atr_data.closes, vec![53., 55.0]);
-    assert_eq!(recv_atr_data.highs, vec![53., 59.]);
-    assert_eq!(recv_atr_data.lows, vec![52., 55.]);
+#[test]
+fn test_atr_ema() {
+    // 10 seconds ranging from 99.5 to 100.5 and closing at 100, the period is a second short of the window
+    let start_time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let mut state = AtrState::new(6, MovingAverage::Ema);
+    for second in 0..10 {
+        let ts = start_time + Duration::seconds(second);
+        for (i, price) in [100.5, 99.5, 100.].into_iter().enumerate() {
+            state.update(&node(ts + Duration::milliseconds(250 * i as i64), 0., price));
+        }
+    }
 
+    assert_eq!(state.candles(), 6);
+    assert_eq!((state.value() * 1000.).round() / 1000., 0.945);
 }
 
 #[test]
-fn test_atr_ema() {
+fn test_atr_matches_rescan() {
+    let start_time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let nodes: Vec<BufferNode> = (0..120)
+        .map(|i| node(start_time + Duration::milliseconds(250 * i), 0., 100. + ((i * 7919) % 13) as f64 * 0.1 - (i % 5) as f64 * 0.05))
+        .collect();
+
+    for moving_average in [MovingAverage::Ema, MovingAverage::Rma, MovingAverage::Sma] {
+        let mut state = AtrState::new(10, moving_average);
+        for node in &nodes {
+            state.update(node);
+        }
+
+        // rescan the buffer: per-second bars, their true ranges, then the average including the open bar
+        let mut bars: Vec<(i64, f64, f64, f64)> = vec![];
+        for node in &nodes {
+            match bars.last_mut() {
+                Some(bar) if bar.0 == node.ts.timestamp() => {
+                    bar.1 = bar.1.max(node.close_price);
+                    bar.2 = bar.2.min(node.close_price);
+                    bar.3 = node.close_price;
+                }
+                _ => bars.push((node.ts.timestamp(), node.close_price, node.close_price, node.close_price)),
+            }
+        }
+        let trs: Vec<f64> = bars
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, high, low, _))| match i {
+                0 => 0.,
+                _ => f64::max(high - low, f64::max((bars[i - 1].3 - high).abs(), (bars[i - 1].3 - low).abs())),
+            })
+            .collect();
+        let expected = match moving_average {
+            MovingAverage::Sma => trs[trs.len() - 10..].iter().sum::<f64>() / 10.,
+            MovingAverage::Rma => {
+                let seed = trs[..10].iter().sum::<f64>() / 10.;
+                trs[10..].iter().fold(seed, |average, tr| average + (tr - average) / 10.)
+            }
+            MovingAverage::Ema => {
+                let seed = trs[..9].iter().sum::<f64>() / 9.;
+                let first = seed + (trs[9] - seed) * 2. / 10.;
+                trs[10..].iter().fold(first, |average, tr| (average * 8. + tr) / 9.)
+            }
+        };
+        assert!((state.value() - expected).abs() < 1e-9, "{:?}: {} != {}", moving_average, state.value(), expected);
+    }
+}
 
-    let highs = vec![100.5, 100.5, 100.5, 100.5, 100.5, 100.5, 100.5, 100.5, 100.5, 100.5];
-    let lows = vec![99.5, 99.5, 99.5, 99.5, 99.5, 99.5, 99.5, 99.5, 99.5, 99.5];
-    let closes = vec![100., 100., 100., 100., 100., 100., 100., 100., 100., 100.];
+#[test]
+fn test_atr_moving_averages() {
+    let start_time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    // every second moves between 99.5 and 100.5, so the true range is 1 from the second one on
+    let mut states = [
+        AtrState::new(5, MovingAverage::Ema),
+        AtrState::new(5, MovingAverage::Rma),
+        AtrState::new(5, MovingAverage::Sma),
+    ];
+    for second in 0..40 {
+        let ts = start_time + Duration::seconds(second);
+        for state in states.iter_mut() {
+            state.update(&node(ts, 0., 100.5));
+            state.update(&node(ts + Duration::milliseconds(500), 0., 99.5));
+        }
+    }
 
-    let result = atr_ema(highs.as_slice(), lows.as_slice(), closes.as_slice(), 5);
-    let rounded_result = (result[result.len()-1] * 1000.).round() / 1000.;
+    for state in states.iter() {
+        assert_eq!(state.candles(), 5);
+        assert_eq!((state.value() * 1000.).round() / 1000., 1.);
+    }
+    // 1% range passes the 0.5% threshold, but not the 2% one
+    assert_eq!(states[0].check(0.5, 0.8).reason, Reason::Triggered);
+    assert_eq!(states[0].check(2., 0.8).reason, Reason::BelowThreshold);
+}
 
-    assert_eq!(result.len(), 10);
-    assert_eq!(rounded_result, 0.945);
+#[test]
+fn test_atr_insufficient_candles() {
+    let start_time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let mut state = AtrState::new(10, MovingAverage::Ema);
+    for second in 0..5 {
+        state.update(&node(start_time + Duration::seconds(second), 0., 100. + second as f64));
+    }
+    let evaluation = state.check(0.1, 0.8);
+    assert_eq!(evaluation.reason, Reason::InsufficientCandles);
+    assert_eq!((evaluation.candles, evaluation.min_candles), (5, 8));
 }
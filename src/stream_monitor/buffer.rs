@@ -1,82 +1,327 @@
-use anyhow::Result;
 use circular_buffer::CircularBuffer;
 use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use super::event::Event;
+use crate::error::DecodeError;
 
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct BufferNode {
     pub value: f64,
+    // exchange event time
     pub ts: DateTime<Utc>,
+    // local receive time
+    pub recv_ts: DateTime<Utc>,
     pub confirmed: bool,
     pub close_price: f64
 }
 
 // we're collecting data for the last minute + some safe zone
-const BUFFER_SIZE: usize = (60 + 1) * 4;
+pub const BUFFER_SECONDS: usize = 60;
+const BUFFER_SIZE: usize = (BUFFER_SECONDS + 1) * 4;
 
 pub type SymbolBuffer = CircularBuffer<BUFFER_SIZE, BufferNode>;
 
 impl BufferNode {
-    pub fn from_kline_event(event: &Event) -> Result<Self> {
-        let (kline_volume, close_price) = parse_kline_event(event)?;
+    // volume is in usd, coin-m klines count contracts of `contract_size` usd each
+    pub fn from_kline_event(event: &Event, recv_ts: DateTime<Utc>, contract_size: Option<f64>) -> Result<Self, DecodeError> {
+        let (kline_volume, close_price) = parse_kline_event(event, contract_size)?;
         let ts = chrono::DateTime::from_timestamp_millis(event.E as i64)
-            .ok_or_else(|| anyhow::anyhow!("invalid timestamp received"))?
+            .ok_or(DecodeError::Timestamp(event.E as i64))?
             .to_utc();
 
         let node: BufferNode = BufferNode {
-            ts: ts,
+            ts,
+            recv_ts,
             value: kline_volume,
             confirmed: event.k.x,
-            close_price: close_price,
+            close_price,
         };
 
         Ok(node)
     }
 }
 
-fn parse_kline_event(event: &Event) -> Result<(f64, f64)> {
-    let price_high: f64 = event.k.h.parse()?;
-    let price_low: f64 = event.k.l.parse()?;
-    let price_close: f64 = event.k.c.parse()?;
-    let volume: f64 = event.k.v.parse()?;
+impl BufferNode {
+    // same message, no matter when it reached us
+    fn same_data(&self, other: &BufferNode) -> bool {
+        self.ts == other.ts
+            && self.value == other.value
+            && self.confirmed == other.confirmed
+            && self.close_price == other.close_price
+    }
+}
 
-    let event_size: f64 = (price_high + price_low) / 2. * volume;
+// the `ingestion` config section
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IngestionConfig {
+    // how far behind the latest node a message may arrive and still be put in its place
+    #[serde(default = "default_late_tolerance_ms")]
+    pub late_tolerance_ms: u64,
+}
 
-    Ok((event_size, price_close))
+fn default_late_tolerance_ms() -> u64 {
+    1000
+}
+
+impl Default for IngestionConfig {
+    fn default() -> Self {
+        IngestionConfig { late_tolerance_ms: default_late_tolerance_ms() }
+    }
 }
 
-pub fn calc_volume_delta(buffer: &SymbolBuffer, needed_seconds: i64) -> f64 {
-    // info!("CVD: {:?}", buffer);
-    // calculate the stop time
-    let latest_timestamp = buffer.back().map(|node| node.ts).unwrap_or(Utc::now());
-    let stop_time = latest_timestamp - Duration::seconds(needed_seconds);
+// what happened to an incoming node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    // newer than everything in the buffer
+    Appended,
+    // same message as a buffered one, dropped
+    Duplicate,
+    // same event time as a buffered node but different data, replaced it
+    Merged,
+    // older than the latest node but within the tolerance, put in its place
+    Reordered,
+    // older than the tolerance allows, dropped
+    Stale,
+}
 
-    let mut total_volume_delta = 0.0;
+impl Admission {
+    // whether the node made it into the buffer
+    pub fn accepted(&self) -> bool {
+        !matches!(self, Admission::Duplicate | Admission::Stale)
+    }
+}
 
-    // go backwards
-    for (iter, current_node) in buffer.iter().rev().enumerate() {
-        // backup and peek ahead
-        let current_node = current_node.clone();
+// every message that didn't arrive in order, since the start
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Anomalies {
+    pub duplicates: u64,
+    pub merged: u64,
+    pub reordered: u64,
+    pub stale: u64,
+}
+
+impl Anomalies {
+    pub fn count(&mut self, admission: Admission) {
+        match admission {
+            Admission::Appended => {}
+            Admission::Duplicate => self.duplicates += 1,
+            Admission::Merged => self.merged += 1,
+            Admission::Reordered => self.reordered += 1,
+            Admission::Stale => self.stale += 1,
+        }
+    }
+}
+
+// how full the buffer is and how much time it covers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct BufferHealth {
+    pub len: usize,
+    pub capacity: usize,
+    // event time between the oldest and the latest node
+    pub span_seconds: f64,
+}
+
+pub fn health(buffer: &SymbolBuffer) -> BufferHealth {
+    let span = buffer.front().zip(buffer.back()).map(|(oldest, latest)| latest.ts - oldest.ts).unwrap_or_default();
+    BufferHealth {
+        len: buffer.len(),
+        capacity: buffer.capacity(),
+        span_seconds: span.num_milliseconds() as f64 / 1000.,
+    }
+}
+
+// a second of closes and the volume traded within it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Bar {
+    // start of the second
+    pub ts: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
 
-        if let Some(previous_node) = buffer.iter().rev().nth(iter + 1) {
-            if previous_node.ts <= stop_time || (previous_node.ts == latest_timestamp && iter != 0) {
-                break;
+// the seconds the buffer covers, oldest first
+// kline volume is cumulative within the kline, each node adds its increment over the previous one to its second
+pub fn bars(buffer: &SymbolBuffer) -> Vec<Bar> {
+    let mut bars: Vec<Bar> = vec![];
+    let mut previous: Option<&BufferNode> = None;
+    for node in buffer.iter() {
+        let increment = match previous {
+            Some(previous) if previous.confirmed => node.value,
+            Some(previous) => (node.value - previous.value).max(0.),
+            None => 0.,
+        };
+        previous = Some(node);
+        let second = node.ts.timestamp();
+        match bars.last_mut() {
+            Some(bar) if bar.ts.timestamp() == second => {
+                bar.high = bar.high.max(node.close_price);
+                bar.low = bar.low.min(node.close_price);
+                bar.close = node.close_price;
+                bar.volume += increment;
             }
+            _ => bars.push(Bar {
+                ts: DateTime::from_timestamp(second, 0).unwrap_or(node.ts),
+                open: node.close_price,
+                high: node.close_price,
+                low: node.close_price,
+                close: node.close_price,
+                volume: increment,
+            }),
+        }
+    }
+    bars
+}
+
+// keeps the buffer ordered by the event time, which is what every scan over it assumes
+// reconnects replay some of the recent messages and the exchange doesn't guarantee the order
+pub fn admit(buffer: &mut SymbolBuffer, node: BufferNode, tolerance: Duration) -> Admission {
+    let Some(latest) = buffer.back() else {
+        buffer.push_back(node);
+        return Admission::Appended;
+    };
+    if node.ts > latest.ts {
+        buffer.push_back(node);
+        return Admission::Appended;
+    }
+    if node.ts < latest.ts - tolerance {
+        return Admission::Stale;
+    }
+
+    if let Some(index) = buffer.iter().rposition(|buffered| buffered.ts == node.ts) {
+        if buffer[index].same_data(&node) {
+            return Admission::Duplicate;
+        }
+        // the exchange sent an update under the same event time, the later one wins
+        buffer[index] = node;
+        return Admission::Merged;
+    }
+
+    // a full buffer drops its oldest node, which is older than this one anyway
+    buffer.push_back(node);
+    let mut index = buffer.len() - 1;
+    while index > 0 && buffer[index - 1].ts > buffer[index].ts {
+        buffer.swap(index - 1, index);
+        index -= 1;
+    }
+    Admission::Reordered
+}
+
+pub(super) fn parse_number(field: &'static str, value: &str) -> Result<f64, DecodeError> {
+    value.parse().map_err(|source| DecodeError::Number { field, source })
+}
+
+fn parse_kline_event(event: &Event, contract_size: Option<f64>) -> Result<(f64, f64), DecodeError> {
+    let price_high = parse_number("k.h", &event.k.h)?;
+    let price_low = parse_number("k.l", &event.k.l)?;
+    let price_close = parse_number("k.c", &event.k.c)?;
+    let volume = parse_number("k.v", &event.k.v)?;
+
+    let event_size: f64 = match contract_size {
+        Some(contract_size) => volume * contract_size,
+        None => (price_high + price_low) / 2. * volume,
+    };
+
+    Ok((event_size, price_close))
+}
 
-            if previous_node.confirmed && iter != 0 {
-                total_volume_delta += current_node.value;
+// rolling sum of the traded volume over the check window
+// kline volume is cumulative within the kline, so every node contributes its increment
+// over the previous node, or its whole value when the previous kline got closed
+#[derive(Debug, Clone)]
+pub struct VolumeWindow {
+    window: Duration,
+    // (timestamp of the previous node, increment)
+    increments: VecDeque<(DateTime<Utc>, f64)>,
+    sum: f64,
+    last: Option<BufferNode>,
+    // `last` came from a restore, its kline may have closed while we were down
+    restored: bool,
+}
+
+// what the window accumulated, its length comes from the config
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SavedVolume {
+    pub increments: Vec<(DateTime<Utc>, f64)>,
+    pub last: Option<BufferNode>,
+}
+
+impl VolumeWindow {
+    pub fn new(window_seconds: i64) -> Self {
+        VolumeWindow {
+            window: Duration::seconds(window_seconds),
+            increments: VecDeque::new(),
+            sum: 0.,
+            last: None,
+            restored: false,
+        }
+    }
+
+    pub fn push(&mut self, node: &BufferNode) {
+        // kline volume is cumulative, so the newer nodes have already accounted for a late one
+        if self.last.as_ref().is_some_and(|last| node.ts < last.ts) {
+            return;
+        }
+        // the volume of a kline missed in between can't be told apart, the new kline starts from its next node
+        if std::mem::take(&mut self.restored) && self.last.as_ref().is_some_and(|last| kline_open(last.ts) != kline_open(node.ts)) {
+            self.last = None;
+        }
+        if let Some(previous_node) = &self.last {
+            let increment = if previous_node.confirmed {
+                node.value
             } else {
-                total_volume_delta += current_node.value - previous_node.value;
-            }
+                node.value - previous_node.value
+            };
+            self.increments.push_back((previous_node.ts, increment));
+            self.sum += increment;
+        }
+        self.last = Some(node.clone());
+
+        // an increment is in the window only if the node it's measured from is
+        let stop_time = node.ts - self.window;
+        while self.increments.front().map(|(ts, _)| *ts <= stop_time).unwrap_or(false) {
+            let (_, increment) = self.increments.pop_front().unwrap();
+            self.sum -= increment;
         }
+        if self.increments.is_empty() {
+            self.sum = 0.;
+        }
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
     }
 
-    total_volume_delta
+    pub fn save(&self) -> SavedVolume {
+        SavedVolume { increments: self.increments.iter().copied().collect(), last: self.last.clone() }
+    }
+
+    // whatever fell out of the window by `now` is left out
+    pub fn restore(&mut self, saved: SavedVolume, now: DateTime<Utc>) {
+        let stop_time = now - self.window;
+        self.increments = saved.increments.into_iter().filter(|(ts, _)| *ts > stop_time).collect();
+        self.sum = self.increments.iter().map(|(_, increment)| increment).sum();
+        self.last = saved.last.filter(|last| last.ts > stop_time);
+        self.restored = self.last.is_some();
+    }
 }
 
+// start of the 1m kline the node belongs to
+fn kline_open(ts: DateTime<Utc>) -> i64 {
+    ts.timestamp().div_euclid(60)
+}
 
 // TESTS
+// shared by the tests of everything fed from the buffer
+#[cfg(test)]
+pub(crate) fn node(ts: DateTime<Utc>, value: f64, close_price: f64) -> BufferNode {
+    BufferNode { value, ts, recv_ts: ts, confirmed: false, close_price }
+}
+
 #[test]
 fn test_calc_volume_data() {
     let latest_timestamp = Utc::now();
@@ -84,67 +329,177 @@ fn test_calc_volume_data() {
     // this node should be excluded
     let node0 = BufferNode {
         ts: latest_timestamp - Duration::milliseconds(2050),
+        recv_ts: latest_timestamp - Duration::milliseconds(2050),
         value: 0.1,
         confirmed: false,
         close_price:42.
     };
     let node1 = BufferNode {
         ts: latest_timestamp - Duration::milliseconds(1550),
+        recv_ts: latest_timestamp - Duration::milliseconds(1550),
         value: 1.0,
         confirmed: false,
         close_price:42.
     };
     let node2 = BufferNode {
         ts: latest_timestamp - Duration::milliseconds(1300),
+        recv_ts: latest_timestamp - Duration::milliseconds(1300),
         value: 2.0,
         confirmed: false,
         close_price:42.
     };
     let node3 = BufferNode {
         ts: latest_timestamp - Duration::milliseconds(1050),
+        recv_ts: latest_timestamp - Duration::milliseconds(1050),
         value: 3.0,
         confirmed: false,
         close_price:42.
     };
     let node4 = BufferNode {
         ts: latest_timestamp - Duration::milliseconds(800),
+        recv_ts: latest_timestamp - Duration::milliseconds(800),
         value: 4.0,
         confirmed: true,
         close_price:42.
     };
     let node5 = BufferNode {
         ts: latest_timestamp - Duration::milliseconds(550),
+        recv_ts: latest_timestamp - Duration::milliseconds(550),
         value: 1.0,
         confirmed: false,
         close_price:42.
     };
     let node6 = BufferNode {
         ts: latest_timestamp - Duration::milliseconds(300),
+        recv_ts: latest_timestamp - Duration::milliseconds(300),
         value: 2.0,
         confirmed: false,
         close_price:42.
     };
     let node7 = BufferNode {
         ts: latest_timestamp - Duration::milliseconds(50),
+        recv_ts: latest_timestamp - Duration::milliseconds(50),
         value: 3.0,
         confirmed: false,
         close_price:42.
     };
 
     let nodes = vec![node0, node1, node2, node3, node4, node5, node6, node7];
-    let mut buffer = CircularBuffer::<244, BufferNode>::new();
+    let mut window_2s = VolumeWindow::new(2);
+    let mut window_3s = VolumeWindow::new(3);
 
     for node in nodes {
-        buffer.push_back(node)
+        window_2s.push(&node);
+        window_3s.push(&node);
     }
-    // copy buffer to make sure the final state is equal to the original one
-    let buffer_backup = buffer.clone();
     // test for 2 seconds
-    let volume_delta_over_2_seconds = calc_volume_delta(&mut buffer, 2);
-    assert_eq!(volume_delta_over_2_seconds, 6.0);
-    assert_eq!(buffer, buffer_backup);
+    assert_eq!(window_2s.sum(), 6.0);
     // test for 3 seconds - now the node at position 0 should be included
-    let volume_delta_over_3_seconds = calc_volume_delta(&mut buffer, 3);
-    assert_eq!(volume_delta_over_3_seconds, 6.9);
-    assert_eq!(buffer, buffer_backup);
+    assert_eq!((window_3s.sum() * 1000.).round() / 1000., 6.9);
+}
+
+#[test]
+fn test_admit_duplicates() {
+    let start = Utc::now();
+    let tolerance = Duration::seconds(1);
+    let mut buffer = SymbolBuffer::new();
+    let mut anomalies = Anomalies::default();
+
+    for admitted in [
+        node(start, 1., 42.),
+        node(start + Duration::milliseconds(250), 2., 42.),
+        // resent after a reconnect, only the receive time differs
+        BufferNode { recv_ts: start + Duration::seconds(5), ..node(start + Duration::milliseconds(250), 2., 42.) },
+        // same event time, newer data
+        node(start + Duration::milliseconds(250), 3., 42.),
+    ] {
+        anomalies.count(admit(&mut buffer, admitted, tolerance));
+    }
+
+    assert_eq!(buffer.len(), 2);
+    assert_eq!(buffer.back().unwrap().value, 3.);
+    assert_eq!(anomalies, Anomalies { duplicates: 1, merged: 1, ..Default::default() });
+}
+
+#[test]
+fn test_admit_late_and_stale() {
+    let start = Utc::now();
+    let tolerance = Duration::seconds(1);
+    let mut buffer = SymbolBuffer::new();
+    let mut anomalies = Anomalies::default();
+
+    for admitted in [
+        node(start, 1., 42.),
+        node(start + Duration::milliseconds(500), 3., 42.),
+        node(start + Duration::milliseconds(2000), 4., 42.),
+        // within the tolerance, goes before the latest node
+        node(start + Duration::milliseconds(1500), 3.5, 42.),
+        // too late
+        node(start + Duration::milliseconds(250), 2., 42.),
+    ] {
+        anomalies.count(admit(&mut buffer, admitted, tolerance));
+    }
+
+    let values: Vec<f64> = buffer.iter().map(|node| node.value).collect();
+    assert_eq!(values, vec![1., 3., 3.5, 4.]);
+    assert_eq!(anomalies, Anomalies { reordered: 1, stale: 1, ..Default::default() });
+
+    // the late node was part of the cumulative volume of the newer one already
+    let mut window = VolumeWindow::new(10);
+    for pushed in [node(start, 1., 42.), node(start + Duration::milliseconds(2000), 4., 42.), node(start + Duration::milliseconds(1500), 3.5, 42.)] {
+        window.push(&pushed);
+    }
+    assert_eq!(window.sum(), 3.);
+}
+
+#[test]
+fn test_bars() {
+    let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let mut buffer = SymbolBuffer::new();
+    // (ms from start, cumulative volume, closed, close)
+    for (ms, value, confirmed, close_price) in [(0, 10., false, 100.), (250, 25., false, 102.), (750, 30., true, 99.), (1000, 5., false, 101.)] {
+        let ts = start + Duration::milliseconds(ms);
+        buffer.push_back(BufferNode { ts, recv_ts: ts, value, confirmed, close_price });
+    }
+    let bars = bars(&buffer);
+    assert_eq!(bars.len(), 2);
+    assert_eq!((bars[0].open, bars[0].high, bars[0].low, bars[0].close), (100., 102., 99., 99.));
+    assert_eq!(bars[0].volume, 20.);
+    // a new kline starts over from the closed one
+    assert_eq!((bars[1].ts, bars[1].close, bars[1].volume), (start + Duration::seconds(1), 101., 5.));
+}
+
+#[test]
+fn test_volume_restore() {
+    // on a kline boundary
+    let start = DateTime::from_timestamp(1_700_000_040, 0).unwrap();
+    let mut live = VolumeWindow::new(10);
+    live.push(&node(start - Duration::seconds(2), 10., 100.));
+    live.push(&node(start - Duration::seconds(1), 40., 100.));
+    let saved = live.save();
+    assert_eq!(live.sum(), 30.);
+
+    // the kline of the saved node closed while we were down, the new one starts from scratch
+    let mut restored = VolumeWindow::new(10);
+    restored.restore(saved.clone(), start + Duration::seconds(1));
+    assert_eq!(restored.sum(), 30.);
+    restored.push(&node(start + Duration::milliseconds(500), 5., 100.));
+    assert_eq!(restored.sum(), 30.);
+    restored.push(&node(start + Duration::milliseconds(750), 8., 100.));
+    assert_eq!(restored.sum(), 33.);
+
+    // within the same kline it carries on
+    let mut restored = VolumeWindow::new(10);
+    restored.restore(saved.clone(), start - Duration::milliseconds(500));
+    restored.push(&node(start - Duration::milliseconds(250), 45., 100.));
+    assert_eq!(restored.sum(), 35.);
+
+    // nothing of it is in the window anymore
+    let mut restored = VolumeWindow::new(10);
+    restored.restore(saved, start + Duration::seconds(20));
+    assert_eq!(restored.sum(), 0.);
+    restored.push(&node(start + Duration::seconds(20), 50., 100.));
+    assert_eq!(restored.sum(), 0.);
+    restored.push(&node(start + Duration::milliseconds(20_250), 60., 100.));
+    assert_eq!(restored.sum(), 10.);
 }
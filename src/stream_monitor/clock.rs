@@ -0,0 +1,74 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use super::buffer::BUFFER_SECONDS;
+use tokio::time::{interval_at, Duration, Instant, Interval, MissedTickBehavior};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvaluationMode {
+    // once a second, on the wall-clock second boundary
+    #[default]
+    Aligned,
+    // after every incoming message
+    Event,
+}
+
+// what to do with the aligned ticks missed while the owner was busy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissedTicks {
+    // drop them and wait for the next boundary
+    #[default]
+    Skip,
+    // fire them all as fast as possible
+    Burst,
+    // fire one right away and shift the schedule
+    Delay,
+}
+
+impl From<MissedTicks> for MissedTickBehavior {
+    fn from(missed_ticks: MissedTicks) -> Self {
+        match missed_ticks {
+            MissedTicks::Skip => MissedTickBehavior::Skip,
+            MissedTicks::Burst => MissedTickBehavior::Burst,
+            MissedTicks::Delay => MissedTickBehavior::Delay,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EvaluationConfig {
+    #[serde(default)]
+    pub mode: EvaluationMode,
+    #[serde(default)]
+    pub missed_ticks: MissedTicks,
+    // time to populate the buffer before the first evaluation
+    #[serde(default = "default_warmup_seconds")]
+    pub warmup_seconds: u64,
+}
+
+// the whole buffer
+fn default_warmup_seconds() -> u64 {
+    BUFFER_SECONDS as u64
+}
+
+impl Default for EvaluationConfig {
+    fn default() -> Self {
+        EvaluationConfig {
+            mode: EvaluationMode::default(),
+            missed_ticks: MissedTicks::default(),
+            warmup_seconds: default_warmup_seconds(),
+        }
+    }
+}
+
+// one second interval starting on the first wall-clock second boundary after `delay`
+pub fn aligned_interval(delay: Duration, missed_ticks: MissedTicks) -> Interval {
+    let now_ms = Utc::now().timestamp_millis();
+    let start_ms = now_ms + delay.as_millis() as i64;
+    let to_boundary = 1000 - start_ms.rem_euclid(1000);
+    let start = Instant::now() + Duration::from_millis((start_ms - now_ms + to_boundary) as u64);
+    let mut interval = interval_at(start, Duration::from_secs(1));
+    interval.set_missed_tick_behavior(missed_ticks.into());
+    interval
+}
@@ -0,0 +1,189 @@
+use serde::Serialize;
+use std::fmt;
+use super::liquidation::LiquidationStats;
+use super::mark_price::MarkStats;
+use super::open_interest::OpenInterestStats;
+use super::reference::ReferenceStats;
+use super::regime::RegimeTag;
+use super::orderbook::BookStats;
+
+// why a symbol is (or isn't) triggered, in the order the conditions are checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    // the buffer isn't populated yet
+    #[default]
+    WarmingUp,
+    // nothing arrived for a while, the numbers would be outdated
+    StaleData,
+    // not enough seconds with data in the atr window
+    InsufficientCandles,
+    // the price didn't move at all
+    ZeroRange,
+    // the residual atr is the threshold metric, but there's no overlap with the reference yet
+    NoReferenceData,
+    BelowThreshold,
+    VolumeTooLow,
+    // order book conditions are configured, but there's no book yet
+    NoBookData,
+    SpreadTooWide,
+    ImbalanceTooLow,
+    DepletionTooLow,
+    LiquidationsTooLow,
+    LiquidationsTooHigh,
+    BasisTooWide,
+    IndexDeviationTooWide,
+    FundingTooHigh,
+    // open interest conditions are configured, but there's no change over the first window yet
+    NoOpenInterestData,
+    OpenInterestChangeTooLow,
+    OpenInterestChangeTooHigh,
+    // held back by the regime suppression
+    MarketWideMove,
+    IdiosyncraticMove,
+    Triggered,
+}
+
+impl Reason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Reason::WarmingUp => "warming_up",
+            Reason::StaleData => "stale_data",
+            Reason::InsufficientCandles => "insufficient_candles",
+            Reason::ZeroRange => "zero_range",
+            Reason::NoReferenceData => "no_reference_data",
+            Reason::BelowThreshold => "below_threshold",
+            Reason::VolumeTooLow => "volume_too_low",
+            Reason::NoBookData => "no_book_data",
+            Reason::SpreadTooWide => "spread_too_wide",
+            Reason::ImbalanceTooLow => "imbalance_too_low",
+            Reason::DepletionTooLow => "depletion_too_low",
+            Reason::LiquidationsTooLow => "liquidations_too_low",
+            Reason::LiquidationsTooHigh => "liquidations_too_high",
+            Reason::BasisTooWide => "basis_too_wide",
+            Reason::IndexDeviationTooWide => "index_deviation_too_wide",
+            Reason::FundingTooHigh => "funding_too_high",
+            Reason::NoOpenInterestData => "no_open_interest_data",
+            Reason::OpenInterestChangeTooLow => "open_interest_change_too_low",
+            Reason::OpenInterestChangeTooHigh => "open_interest_change_too_high",
+            Reason::MarketWideMove => "market_wide_move",
+            Reason::IdiosyncraticMove => "idiosyncratic_move",
+            Reason::Triggered => "triggered",
+        }
+    }
+}
+
+// outcome of a single evaluation along with the numbers behind it
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Evaluation {
+    pub reason: Reason,
+    pub atr: f64,
+    // atr relative to the close price, in percent
+    pub atr_pct: f64,
+    pub atr_threshold: f64,
+    // seconds with data in the atr window, and how many are needed
+    pub candles: usize,
+    pub min_candles: usize,
+    pub vol_usdt: f64,
+    pub min_vol_usdt: f64,
+    // close change over the atr window, in percent
+    pub return_pct: f64,
+    // time since the latest message was received, live only
+    pub data_age_ms: Option<f64>,
+    // spread, imbalance and depletion, when the order book streams are enabled
+    pub book: Option<BookStats>,
+    // liquidations over the atr window, when the liquidation stream is enabled
+    pub liquidations: Option<LiquidationStats>,
+    // mark price, index price and funding, when the mark price stream is enabled
+    pub mark: Option<MarkStats>,
+    // open interest and its change over each window, when open interest is polled
+    pub open_interest: Option<OpenInterestStats>,
+    // beta, correlation and residual atr against the reference symbol, when it's enabled
+    pub reference: Option<ReferenceStats>,
+    // whether the threshold was checked against the residual atr rather than the atr
+    pub residual: bool,
+    // whether the move is shared by the other symbols, and the share of them over their threshold
+    // only set once triggered, as of the latest market regime
+    pub regime: Option<RegimeTag>,
+    pub breadth: Option<f64>,
+    // limit of the failed order book, liquidation, mark price or open interest condition
+    pub limit: Option<f64>,
+}
+
+impl Evaluation {
+    pub fn is_triggered(&self) -> bool {
+        self.reason == Reason::Triggered
+    }
+}
+
+// human readable explanation, e.g. for the idle log
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let book = self.book.as_ref();
+        let limit = self.limit.unwrap_or_default();
+        let liquidated = self.liquidations.map(|stats| stats.total_usdt()).unwrap_or_default();
+        let mark = self.mark.unwrap_or_default();
+        let oi_change = self
+            .open_interest
+            .as_ref()
+            .and_then(|stats| stats.changes.first())
+            .and_then(|change| change.change_pct)
+            .unwrap_or_default();
+        let (metric, volatility_pct) = match self.reference.filter(|_| self.residual) {
+            Some(stats) => ("residual atr", stats.residual_atr_pct),
+            None => ("atr", self.atr_pct),
+        };
+        match self.reason {
+            Reason::WarmingUp => write!(f, "warming up"),
+            Reason::StaleData => write!(f, "no data for {:.0}ms", self.data_age_ms.unwrap_or_default()),
+            Reason::InsufficientCandles => write!(f, "{} of {} candles needed", self.candles, self.min_candles),
+            Reason::ZeroRange => write!(f, "price didn't move over {} candles", self.candles),
+            Reason::NoReferenceData => write!(f, "no returns shared with the reference yet"),
+            Reason::BelowThreshold => write!(f, "{} {:.3}% below the {:.3}% threshold", metric, volatility_pct, self.atr_threshold),
+            Reason::VolumeTooLow => write!(f, "volume {:.0} below the {:.0} minimum", self.vol_usdt, self.min_vol_usdt),
+            Reason::NoBookData => write!(f, "no order book data yet"),
+            Reason::SpreadTooWide => write!(
+                f, "spread {:.1}bps wider than {:.1}bps",
+                book.and_then(|book| book.spread_bps).unwrap_or_default(), limit
+            ),
+            Reason::ImbalanceTooLow => write!(
+                f, "book imbalance {:.2} within ±{:.2}",
+                book.and_then(|book| book.imbalance).unwrap_or_default(), limit
+            ),
+            Reason::DepletionTooLow => write!(
+                f, "book depletion bid {:.1}%, ask {:.1}% below {:.1}%",
+                book.and_then(|book| book.bid_depletion_pct).unwrap_or_default(),
+                book.and_then(|book| book.ask_depletion_pct).unwrap_or_default(),
+                limit
+            ),
+            Reason::LiquidationsTooLow => write!(f, "liquidations {:.0} below the {:.0} minimum", liquidated, limit),
+            Reason::LiquidationsTooHigh => write!(f, "liquidations {:.0} over the {:.0} maximum", liquidated, limit),
+            Reason::BasisTooWide => write!(f, "last price {:.1}bps off the mark, over {:.1}bps", mark.basis_bps, limit),
+            Reason::IndexDeviationTooWide => {
+                write!(f, "last price {:.1}bps off the index, over {:.1}bps", mark.index_deviation_bps, limit)
+            }
+            Reason::FundingTooHigh => write!(f, "predicted funding rate {:.4}% over {:.4}%", mark.predicted_funding_rate * 100., limit * 100.),
+            Reason::NoOpenInterestData => write!(f, "no open interest change yet"),
+            Reason::OpenInterestChangeTooLow => write!(f, "open interest change {:.2}% below {:.2}%", oi_change, limit),
+            Reason::OpenInterestChangeTooHigh => write!(f, "open interest change {:.2}% over {:.2}%", oi_change, limit),
+            Reason::MarketWideMove => write!(
+                f, "market-wide move with {:.0}% of the other symbols over their threshold, suppressed",
+                self.breadth.unwrap_or_default() * 100.
+            ),
+            Reason::IdiosyncraticMove => write!(
+                f, "idiosyncratic move with {:.0}% of the other symbols over their threshold, suppressed",
+                self.breadth.unwrap_or_default() * 100.
+            ),
+            Reason::Triggered => {
+                write!(
+                    f, "{} {:.3}% over the {:.3}% threshold with volume {:.0}",
+                    metric, volatility_pct, self.atr_threshold, self.vol_usdt
+                )?;
+                match self.regime {
+                    Some(tag) => write!(f, ", {} move", tag.as_str().replace('_', "-")),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
@@ -15,3 +15,64 @@ pub struct Event {
     pub E: u64,
     pub k: Kline,
 }
+
+// frames of a combined stream come wrapped, e.g. `{"stream":"ethusdt@bookTicker","data":{...}}`
+#[derive(Debug, Deserialize)]
+pub struct Combined<'a> {
+    pub stream: &'a str,
+    #[serde(borrow)]
+    pub data: &'a serde_json::value::RawValue,
+}
+
+// partial depth, levels as [price, quantity]
+// futures name the sides `b` and `a` and carry the event time, spot sends `bids` and `asks` only
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DepthEvent {
+    #[serde(default)]
+    pub E: Option<u64>,
+    #[serde(alias = "bids")]
+    pub b: Vec<[String; 2]>,
+    #[serde(alias = "asks")]
+    pub a: Vec<[String; 2]>,
+}
+
+// best bid and ask, the event time is futures only
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookTickerEvent {
+    #[serde(default)]
+    pub E: Option<u64>,
+    pub b: String,
+    pub B: String,
+    pub a: String,
+    pub A: String,
+}
+
+// a liquidation order, `S` is BUY or SELL, `ap` the average price and `z` the filled quantity
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForceOrder {
+    pub s: String,
+    pub S: String,
+    pub ap: String,
+    pub z: String,
+    pub T: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForceOrderEvent {
+    pub o: ForceOrder,
+}
+
+// mark and index price along with the predicted funding `r`, due at `T`
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MarkPriceEvent {
+    pub E: u64,
+    pub p: String,
+    pub i: String,
+    #[serde(default)]
+    pub r: String,
+    pub T: u64,
+}
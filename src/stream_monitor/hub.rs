@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::{broadcast, watch, Notify};
+use super::reference::SecondCloses;
+use super::regime::MarketRegime;
+use super::snapshot::{Signal, SymbolSnapshot};
+
+const SIGNAL_CHANNEL_CAPACITY: usize = 1024;
+
+// runtime overrides of a symbol, e.g. from the control api
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct SymbolControls {
+    pub atr_threshold: Option<f64>,
+    pub min_vol_usdt: Option<f64>,
+    // evaluated as usual, but nothing is signalled
+    pub paused: bool,
+}
+
+// the publishing side handed to a symbol's owner task
+// sending never blocks and never waits for the consumers
+pub struct Publisher {
+    pub snapshot: watch::Sender<SymbolSnapshot>,
+    pub signals: broadcast::Sender<Signal>,
+    // market-wide state, as far as the aggregator has seen
+    pub regime: watch::Receiver<MarketRegime>,
+    // per-second closes of the reference symbol, sent by its own task and read by the others
+    pub reference: Arc<watch::Sender<SecondCloses>>,
+    // wakes the aggregator up after a snapshot
+    pub updates: Arc<Notify>,
+    pub controls: watch::Receiver<SymbolControls>,
+    // consumers that want the per-second bars in the snapshots
+    pub bar_watchers: Arc<AtomicUsize>,
+}
+
+impl Publisher {
+    pub fn publish(&self, snapshot: SymbolSnapshot) {
+        self.snapshot.send_replace(snapshot);
+        self.updates.notify_one();
+    }
+
+    // the bars are rebuilt from the whole buffer, so only while someone looks at them
+    pub fn wants_bars(&self) -> bool {
+        self.bar_watchers.load(Ordering::Relaxed) > 0
+    }
+}
+
+// keeps the bars in the snapshots while it's held
+pub struct BarsWatch(Arc<AtomicUsize>);
+
+impl Drop for BarsWatch {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// registry of the published symbol states
+// the map is only locked when a symbol is (un)registered or a consumer reads the list,
+// the ingestion and evaluation path only ever touches its own channels
+#[derive(Clone)]
+pub struct Hub {
+    snapshots: Arc<RwLock<HashMap<String, watch::Receiver<SymbolSnapshot>>>>,
+    controls: Arc<RwLock<HashMap<String, watch::Sender<SymbolControls>>>>,
+    signals: broadcast::Sender<Signal>,
+    regime: Arc<watch::Sender<MarketRegime>>,
+    reference: Arc<watch::Sender<SecondCloses>>,
+    updates: Arc<Notify>,
+    bar_watchers: Arc<AtomicUsize>,
+}
+
+impl Default for Hub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hub {
+    pub fn new() -> Self {
+        let (signals, _) = broadcast::channel(SIGNAL_CHANNEL_CAPACITY);
+        let (regime, _) = watch::channel(MarketRegime::default());
+        let (reference, _) = watch::channel(SecondCloses::new());
+        Hub {
+            snapshots: Arc::new(RwLock::new(HashMap::new())),
+            controls: Arc::new(RwLock::new(HashMap::new())),
+            signals,
+            regime: Arc::new(regime),
+            reference: Arc::new(reference),
+            updates: Arc::new(Notify::new()),
+            bar_watchers: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn register(&self, symbol: &str) -> Publisher {
+        let initial = SymbolSnapshot {
+            symbol: symbol.to_string(),
+            ..Default::default()
+        };
+        let (tx, rx) = watch::channel(initial);
+        self.snapshots.write().unwrap().insert(symbol.to_string(), rx);
+        let (controls, controls_rx) = watch::channel(SymbolControls::default());
+        self.controls.write().unwrap().insert(symbol.to_string(), controls);
+        Publisher {
+            snapshot: tx,
+            signals: self.signals.clone(),
+            regime: self.regime.subscribe(),
+            reference: self.reference.clone(),
+            updates: self.updates.clone(),
+            controls: controls_rx,
+            bar_watchers: self.bar_watchers.clone(),
+        }
+    }
+
+    pub fn unregister(&self, symbol: &str) {
+        self.snapshots.write().unwrap().remove(symbol);
+        self.controls.write().unwrap().remove(symbol);
+    }
+
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self.snapshots.read().unwrap().keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    pub fn snapshot(&self, symbol: &str) -> Option<SymbolSnapshot> {
+        self.snapshots.read().unwrap().get(symbol).map(|rx| rx.borrow().clone())
+    }
+
+    pub fn snapshots(&self) -> Vec<SymbolSnapshot> {
+        let mut snapshots: Vec<SymbolSnapshot> = self.snapshots
+            .read()
+            .unwrap()
+            .values()
+            .map(|rx| rx.borrow().clone())
+            .collect();
+        snapshots.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        snapshots
+    }
+
+    // watch a single symbol, e.g. to react on every published tick
+    pub fn watch(&self, symbol: &str) -> Option<watch::Receiver<SymbolSnapshot>> {
+        self.snapshots.read().unwrap().get(symbol).cloned()
+    }
+
+    // the snapshots carry their per-second bars from the next evaluation on, until it's dropped
+    pub fn watch_bars(&self) -> BarsWatch {
+        self.bar_watchers.fetch_add(1, Ordering::Relaxed);
+        BarsWatch(self.bar_watchers.clone())
+    }
+
+    pub fn subscribe_signals(&self) -> broadcast::Receiver<Signal> {
+        self.signals.subscribe()
+    }
+
+    pub fn controls(&self, symbol: &str) -> Option<SymbolControls> {
+        self.controls.read().unwrap().get(symbol).map(|tx| *tx.borrow())
+    }
+
+    // the symbol picks the change up before its next evaluation, None if it isn't registered
+    pub fn update_controls(&self, symbol: &str, update: impl FnOnce(&mut SymbolControls)) -> Option<SymbolControls> {
+        let controls = self.controls.read().unwrap();
+        let tx = controls.get(symbol)?;
+        tx.send_modify(update);
+        let updated = *tx.borrow();
+        Some(updated)
+    }
+
+    pub fn regime(&self) -> MarketRegime {
+        self.regime.borrow().clone()
+    }
+
+    pub fn publish_regime(&self, regime: MarketRegime) {
+        self.regime.send_replace(regime);
+    }
+
+    // resolves once a symbol published since the last call
+    pub async fn updated(&self) {
+        self.updates.notified().await
+    }
+}
@@ -0,0 +1,64 @@
+use super::atr::{AtrState, MovingAverage};
+use super::buffer::{BufferNode, VolumeWindow};
+use super::reference::CloseSeries;
+use crate::error::IndicatorError;
+
+// streaming indicator state, updated once per incoming node
+// evaluation only reads the accumulated values, so it's O(1) regardless of the buffer size
+#[derive(Debug, Clone)]
+pub struct IndicatorState {
+    pub atr: AtrState,
+    pub volume: VolumeWindow,
+    // over the reference window, what the symbol is related to the reference with
+    pub closes: CloseSeries,
+}
+
+impl IndicatorState {
+    pub fn new(window_seconds: usize, moving_average: MovingAverage, reference_seconds: usize) -> Self {
+        IndicatorState {
+            atr: AtrState::new(window_seconds, moving_average),
+            volume: VolumeWindow::new(window_seconds as i64),
+            closes: CloseSeries::new(reference_seconds),
+        }
+    }
+
+    pub fn update(&mut self, node: &BufferNode) {
+        self.atr.update(node);
+        self.volume.push(node);
+        self.closes.push(node);
+    }
+
+    // close change over the atr window, from the close of its oldest second
+    pub fn return_pct(&self) -> f64 {
+        match (self.atr.bars().front(), self.atr.bars().back()) {
+            (Some(first), Some(last)) if first.close > 0. => (last.close - first.close) / first.close * 100.,
+            _ => 0.,
+        }
+    }
+}
+
+// a single bad price would stay in the running sums for the whole window
+pub fn check_input(node: &BufferNode) -> Result<(), IndicatorError> {
+    if !node.close_price.is_finite() || node.close_price <= 0. {
+        return Err(IndicatorError::InvalidInput { field: "close price", value: node.close_price });
+    }
+    if !node.value.is_finite() || node.value < 0. {
+        return Err(IndicatorError::InvalidInput { field: "volume", value: node.value });
+    }
+    Ok(())
+}
+
+// TESTS
+#[cfg(test)]
+use super::buffer::node;
+
+#[test]
+fn test_return_pct() {
+    let start = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let mut state = IndicatorState::new(5, MovingAverage::Ema, 5);
+    for second in 0..10 {
+        state.update(&node(start + chrono::Duration::seconds(second), 0., 100. + second as f64));
+    }
+    // the seconds 5 to 9 are left in the window
+    assert_eq!(state.return_pct(), 4. / 105. * 100.);
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use super::buffer::BufferNode;
+
+// upper bounds of the histogram buckets, the last bucket takes everything above
+pub const LATENCY_BUCKETS_MS: [f64; 10] = [5., 10., 25., 50., 100., 250., 500., 1000., 2500., 5000.];
+// about a minute of kline updates
+const RECENT_SAMPLES: usize = 240;
+
+// the `latency` config section
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct LatencyConfig {
+    // average feed latency over the recent messages
+    #[serde(default = "default_max_latency_ms")]
+    pub max_latency_ms: f64,
+    // estimated difference between our clock and the exchange one, either way
+    #[serde(default = "default_max_clock_skew_ms")]
+    pub max_clock_skew_ms: f64,
+    // hold the signals back while either limit is exceeded
+    #[serde(default)]
+    pub suppress_signals: bool,
+}
+
+fn default_max_latency_ms() -> f64 {
+    1000.
+}
+
+fn default_max_clock_skew_ms() -> f64 {
+    500.
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        LatencyConfig {
+            max_latency_ms: default_max_latency_ms(),
+            max_clock_skew_ms: default_max_clock_skew_ms(),
+            suppress_signals: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LatencyStats {
+    // average over the recent messages
+    pub latency_ms: f64,
+    // lowest latency over the recent messages, negative when our clock is behind
+    pub clock_skew_ms: f64,
+    // message count per bucket of LATENCY_BUCKETS_MS since the start, plus the overflow bucket
+    pub histogram: Vec<u64>,
+}
+
+impl LatencyStats {
+    // upper bound of the bucket holding the given quantile, None for the overflow bucket
+    pub fn percentile(&self, quantile: f64) -> Option<f64> {
+        let total: u64 = self.histogram.iter().sum();
+        let rank = (total as f64 * quantile).ceil().max(1.) as u64;
+        let mut seen = 0;
+        for (i, count) in self.histogram.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return LATENCY_BUCKETS_MS.get(i).copied();
+            }
+        }
+        None
+    }
+}
+
+// latency of the feed: local receive time minus the exchange event time
+// that's the network transit plus the skew between the clocks, and since the transit
+// can't be negative, the lowest recent latency is the best guess of the skew
+#[derive(Debug, Clone)]
+pub struct LatencyMonitor {
+    histogram: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    recent: VecDeque<f64>,
+    recent_sum: f64,
+}
+
+impl Default for LatencyMonitor {
+    fn default() -> Self {
+        LatencyMonitor::new()
+    }
+}
+
+impl LatencyMonitor {
+    pub fn new() -> Self {
+        LatencyMonitor {
+            histogram: [0; LATENCY_BUCKETS_MS.len() + 1],
+            recent: VecDeque::with_capacity(RECENT_SAMPLES),
+            recent_sum: 0.,
+        }
+    }
+
+    pub fn record(&mut self, node: &BufferNode) {
+        let latency_ms = (node.recv_ts - node.ts).num_microseconds().unwrap_or(i64::MAX) as f64 / 1000.;
+        let bucket = LATENCY_BUCKETS_MS.iter().position(|le| latency_ms <= *le).unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.histogram[bucket] += 1;
+
+        if self.recent.len() == RECENT_SAMPLES {
+            self.recent_sum -= self.recent.pop_front().unwrap();
+        }
+        self.recent.push_back(latency_ms);
+        self.recent_sum += latency_ms;
+    }
+
+    pub fn stats(&self) -> Option<LatencyStats> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        Some(LatencyStats {
+            latency_ms: self.recent_sum / self.recent.len() as f64,
+            clock_skew_ms: self.recent.iter().copied().fold(f64::INFINITY, f64::min),
+            histogram: self.histogram.to_vec(),
+        })
+    }
+}
+
+impl LatencyConfig {
+    // describes what's over the limits, if anything
+    pub fn check(&self, stats: &LatencyStats) -> Option<String> {
+        let mut problems = vec![];
+        if stats.latency_ms > self.max_latency_ms {
+            problems.push(format!("feed latency {:.0}ms over {:.0}ms", stats.latency_ms, self.max_latency_ms));
+        }
+        if stats.clock_skew_ms.abs() > self.max_clock_skew_ms {
+            problems.push(format!("clock skew {:.0}ms over {:.0}ms", stats.clock_skew_ms, self.max_clock_skew_ms));
+        }
+        (!problems.is_empty()).then(|| problems.join(", "))
+    }
+}
+
+// TESTS
+#[test]
+fn test_latency_monitor() {
+    use chrono::{Duration, Utc};
+
+    let start = Utc::now();
+    let mut monitor = LatencyMonitor::new();
+    assert_eq!(monitor.stats(), None);
+
+    // our clock is 300ms behind the exchange, transit takes 20 to 60ms
+    for (i, transit_ms) in [20, 60, 40, 20, 60].iter().enumerate() {
+        let ts = start + Duration::milliseconds(i as i64 * 250);
+        monitor.record(&BufferNode {
+            value: 0.,
+            ts,
+            recv_ts: ts + Duration::milliseconds(transit_ms - 300),
+            confirmed: false,
+            close_price: 42.,
+        });
+    }
+
+    let stats = monitor.stats().unwrap();
+    assert_eq!(stats.latency_ms, -260.);
+    assert_eq!(stats.clock_skew_ms, -280.);
+    // every negative latency lands in the first bucket
+    assert_eq!(stats.histogram[0], 5);
+    assert_eq!(stats.percentile(0.99), Some(5.));
+
+    let config = LatencyConfig { max_clock_skew_ms: 250., ..Default::default() };
+    assert_eq!(config.check(&stats), Some("clock skew -280ms over 250ms".to_string()));
+    assert_eq!(LatencyConfig::default().check(&stats), None);
+}
@@ -0,0 +1,171 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use super::buffer::parse_number;
+use super::evaluation::Reason;
+use super::event::ForceOrderEvent;
+use super::market::Market;
+use crate::error::DecodeError;
+
+pub static FORCE_ORDER_STREAM: &str = "forceOrder";
+// liquidations of every symbol on the market, filtered down to the symbol by the reader
+pub static ALL_FORCE_ORDERS_STREAM: &str = "!forceOrder@arr";
+
+// the `liquidations` config section, the stream is off unless enabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct LiquidationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // read the all-market stream instead of the one of the symbol
+    #[serde(default)]
+    pub all_market: bool,
+    // notional liquidated on both sides over the atr window, checked once atr and volume pass
+    // a minimum only lets liquidation driven moves through, a maximum only organic ones
+    #[serde(default)]
+    pub min_notional_usdt: Option<f64>,
+    #[serde(default)]
+    pub max_notional_usdt: Option<f64>,
+}
+
+impl LiquidationConfig {
+    // spot has no liquidations
+    pub fn streams(&self, market: Market) -> Vec<&'static str> {
+        match (self.enabled, market) {
+            (false, _) | (true, Market::Spot) => vec![],
+            (true, _) if self.all_market => vec![ALL_FORCE_ORDERS_STREAM],
+            (true, _) => vec![FORCE_ORDER_STREAM],
+        }
+    }
+
+    // the failed condition along with its limit
+    pub fn check(&self, stats: &LiquidationStats) -> Option<(Reason, f64)> {
+        let notional = stats.total_usdt();
+        if let Some(min) = self.min_notional_usdt.filter(|min| notional < *min) {
+            return Some((Reason::LiquidationsTooLow, min));
+        }
+        if let Some(max) = self.max_notional_usdt.filter(|max| notional > *max) {
+            return Some((Reason::LiquidationsTooHigh, max));
+        }
+        None
+    }
+}
+
+// the side of the liquidation order, a sell closes a long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Liquidation {
+    pub ts: DateTime<Utc>,
+    pub side: Side,
+    // usd value of the filled quantity
+    pub notional: f64,
+}
+
+impl Liquidation {
+    // coin-m quantities are contracts of `contract_size` usd each
+    pub fn from_event(event: &ForceOrderEvent, contract_size: Option<f64>) -> Result<Self, DecodeError> {
+        let order = &event.o;
+        let ts = DateTime::from_timestamp_millis(order.T as i64).ok_or(DecodeError::Timestamp(order.T as i64))?;
+        let side = match order.S.as_str() {
+            "BUY" => Side::Buy,
+            "SELL" => Side::Sell,
+            other => return Err(DecodeError::Value { field: "o.S", value: other.to_string() }),
+        };
+        let quantity = parse_number("o.z", &order.z)?;
+        let notional = match contract_size {
+            Some(contract_size) => quantity * contract_size,
+            None => quantity * parse_number("o.ap", &order.ap)?,
+        };
+        Ok(Liquidation { ts, side, notional })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct LiquidationStats {
+    // shorts liquidated by buy orders
+    pub buy_usdt: f64,
+    // longs liquidated by sell orders
+    pub sell_usdt: f64,
+    pub count: usize,
+}
+
+impl LiquidationStats {
+    pub fn total_usdt(&self) -> f64 {
+        self.buy_usdt + self.sell_usdt
+    }
+}
+
+// liquidations of the symbol over the atr window
+#[derive(Debug, Clone)]
+pub struct LiquidationWindow {
+    window: Duration,
+    liquidations: VecDeque<Liquidation>,
+}
+
+impl LiquidationWindow {
+    pub fn new(window_seconds: usize) -> Self {
+        LiquidationWindow { window: Duration::seconds(window_seconds as i64), liquidations: VecDeque::new() }
+    }
+
+    pub fn push(&mut self, liquidation: Liquidation) {
+        let position = self.liquidations.iter().rposition(|other| other.ts <= liquidation.ts).map_or(0, |i| i + 1);
+        self.liquidations.insert(position, liquidation);
+        let latest = self.liquidations.back().map(|latest| latest.ts).unwrap_or(liquidation.ts);
+        while self.liquidations.front().is_some_and(|oldest| oldest.ts <= latest - self.window) {
+            self.liquidations.pop_front();
+        }
+    }
+
+    // liquidations are sparse, so the window is cut at `now` rather than at the latest one
+    pub fn stats(&self, now: DateTime<Utc>) -> LiquidationStats {
+        let mut stats = LiquidationStats::default();
+        for liquidation in self.liquidations.iter().filter(|l| l.ts > now - self.window && l.ts <= now) {
+            match liquidation.side {
+                Side::Buy => stats.buy_usdt += liquidation.notional,
+                Side::Sell => stats.sell_usdt += liquidation.notional,
+            }
+            stats.count += 1;
+        }
+        stats
+    }
+}
+
+// TESTS
+#[test]
+fn test_liquidation_window() {
+    let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let liquidation = |second: i64, side: Side, notional: f64| Liquidation {
+        ts: start + Duration::seconds(second),
+        side,
+        notional,
+    };
+    let mut window = LiquidationWindow::new(10);
+    window.push(liquidation(0, Side::Sell, 50_000.));
+    window.push(liquidation(4, Side::Buy, 10_000.));
+    // arrives late, still counted
+    window.push(liquidation(2, Side::Sell, 20_000.));
+
+    let stats = window.stats(start + Duration::seconds(5));
+    assert_eq!(stats, LiquidationStats { buy_usdt: 10_000., sell_usdt: 70_000., count: 3 });
+    // the first one is out of the window by now
+    assert_eq!(window.stats(start + Duration::seconds(10)).sell_usdt, 20_000.);
+
+    let config = LiquidationConfig { enabled: true, min_notional_usdt: Some(100_000.), ..Default::default() };
+    assert_eq!(config.check(&stats), Some((Reason::LiquidationsTooLow, 100_000.)));
+    assert_eq!(config.streams(Market::Spot), Vec::<&str>::new());
+}
+
+#[test]
+fn test_liquidation_side() {
+    let event = |side: &str| -> ForceOrderEvent {
+        serde_json::from_str(&format!(r#"{{"o": {{"s": "ETHUSDT", "S": "{}", "T": 1700000000000, "z": "2", "ap": "100"}}}}"#, side)).unwrap()
+    };
+    assert_eq!(Liquidation::from_event(&event("SELL"), None).unwrap().side, Side::Sell);
+    assert_eq!(Liquidation::from_event(&event("BUY"), Some(10.)).unwrap().notional, 20.);
+    // an unknown side isn't counted as either
+    assert!(matches!(Liquidation::from_event(&event("HOLD"), None), Err(DecodeError::Value { field: "o.S", .. })));
+}
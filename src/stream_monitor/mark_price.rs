@@ -0,0 +1,148 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use super::buffer::parse_number;
+use super::evaluation::Reason;
+use super::event::MarkPriceEvent;
+use super::market::Market;
+use crate::error::DecodeError;
+
+pub static MARK_PRICE_STREAM: &str = "markPrice@1s";
+
+// the `mark_price` config section, the stream is off unless enabled
+// a last price far off the mark or the index is a local dislocation rather than a move
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct MarkPriceConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // absolute limits, checked once atr and volume pass
+    #[serde(default)]
+    pub max_basis_bps: Option<f64>,
+    #[serde(default)]
+    pub max_index_deviation_bps: Option<f64>,
+    // predicted funding rate, e.g. 0.001 for 0.1%
+    #[serde(default)]
+    pub max_funding_rate: Option<f64>,
+}
+
+impl MarkPriceConfig {
+    // spot has no mark price
+    pub fn streams(&self, market: Market) -> Vec<&'static str> {
+        match (self.enabled, market) {
+            (false, _) | (true, Market::Spot) => vec![],
+            (true, _) => vec![MARK_PRICE_STREAM],
+        }
+    }
+
+    // the first failed condition along with its limit
+    pub fn check(&self, stats: &MarkStats) -> Option<(Reason, f64)> {
+        let conditions = [
+            (self.max_basis_bps, stats.basis_bps, Reason::BasisTooWide),
+            (self.max_index_deviation_bps, stats.index_deviation_bps, Reason::IndexDeviationTooWide),
+            (self.max_funding_rate, stats.predicted_funding_rate, Reason::FundingTooHigh),
+        ];
+        conditions
+            .into_iter()
+            .find_map(|(limit, value, reason)| limit.filter(|limit| value.abs() > *limit).map(|limit| (reason, limit)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarkPrice {
+    pub ts: DateTime<Utc>,
+    pub mark: f64,
+    pub index: f64,
+    // predicted rate of the next funding
+    pub predicted_funding_rate: f64,
+    pub next_funding: DateTime<Utc>,
+}
+
+impl MarkPrice {
+    pub fn from_event(event: &MarkPriceEvent) -> Result<Self, DecodeError> {
+        let timestamp = |ms: u64| DateTime::from_timestamp_millis(ms as i64).ok_or(DecodeError::Timestamp(ms as i64));
+        Ok(MarkPrice {
+            ts: timestamp(event.E)?,
+            mark: parse_number("p", &event.p)?,
+            index: parse_number("i", &event.i)?,
+            // delivery contracts don't fund
+            predicted_funding_rate: if event.r.is_empty() { 0. } else { parse_number("r", &event.r)? },
+            next_funding: timestamp(event.T)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct MarkStats {
+    pub mark_price: f64,
+    pub index_price: f64,
+    // last over mark and over index, in basis points
+    pub basis_bps: f64,
+    pub index_deviation_bps: f64,
+    // the estimate of the next funding, not a settled rate
+    pub predicted_funding_rate: f64,
+    // the rate of the latest funding, once one has passed while streaming
+    pub last_funding_rate: Option<f64>,
+    pub next_funding: Option<DateTime<Utc>>,
+}
+
+// latest mark price of the symbol
+#[derive(Debug, Clone, Default)]
+pub struct MarkPriceState {
+    latest: Option<MarkPrice>,
+    last_funding_rate: Option<f64>,
+}
+
+fn bps(price: f64, reference: f64) -> f64 {
+    if reference > 0. {
+        (price - reference) / reference * 10_000.
+    } else {
+        0.
+    }
+}
+
+impl MarkPriceState {
+    pub fn update(&mut self, mark: MarkPrice) {
+        if let Some(latest) = self.latest {
+            if latest.ts > mark.ts {
+                return;
+            }
+            // the funding time moved on, so the previous prediction got settled
+            if mark.next_funding > latest.next_funding {
+                self.last_funding_rate = Some(latest.predicted_funding_rate);
+            }
+        }
+        self.latest = Some(mark);
+    }
+
+    // against the latest close
+    pub fn stats(&self, last_price: f64) -> Option<MarkStats> {
+        let mark = self.latest?;
+        Some(MarkStats {
+            mark_price: mark.mark,
+            index_price: mark.index,
+            basis_bps: bps(last_price, mark.mark),
+            index_deviation_bps: bps(last_price, mark.index),
+            predicted_funding_rate: mark.predicted_funding_rate,
+            last_funding_rate: self.last_funding_rate,
+            next_funding: Some(mark.next_funding),
+        })
+    }
+}
+
+// TESTS
+#[test]
+fn test_mark_price_stats() {
+    let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let funding = DateTime::from_timestamp(1_700_006_400, 0).unwrap();
+    let mut state = MarkPriceState::default();
+    assert_eq!(state.stats(2000.), None);
+
+    state.update(MarkPrice { ts: start, mark: 2000., index: 1999., predicted_funding_rate: 0.0001, next_funding: start });
+    state.update(MarkPrice { ts: start + chrono::Duration::seconds(1), mark: 2000., index: 1999., predicted_funding_rate: 0.0002, next_funding: funding });
+    let stats = state.stats(2010.).unwrap();
+    assert_eq!(stats.basis_bps, 50.);
+    assert_eq!(stats.index_deviation_bps.round(), 55.);
+    assert_eq!((stats.predicted_funding_rate, stats.last_funding_rate), (0.0002, Some(0.0001)));
+
+    let config = MarkPriceConfig { enabled: true, max_basis_bps: Some(60.), max_index_deviation_bps: Some(50.), ..Default::default() };
+    assert_eq!(config.check(&stats), Some((Reason::IndexDeviationTooWide, 50.)));
+}
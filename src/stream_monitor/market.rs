@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// the binance market a symbol is streamed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Market {
+    // usd-m futures
+    #[default]
+    Usdm,
+    // coin-m futures, volumes are in contracts
+    Coinm,
+    Spot,
+    // usd-m futures testnet
+    Testnet,
+}
+
+pub const MARKETS: [&str; 4] = ["usdm", "coinm", "spot", "testnet"];
+
+impl Market {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Market::Usdm => "usdm",
+            Market::Coinm => "coinm",
+            Market::Spot => "spot",
+            Market::Testnet => "testnet",
+        }
+    }
+
+    pub fn stream_url(&self) -> &'static str {
+        match self {
+            Market::Usdm => "wss://fstream.binance.com/ws",
+            Market::Coinm => "wss://dstream.binance.com/ws",
+            Market::Spot => "wss://stream.binance.com:9443/ws",
+            Market::Testnet => "wss://stream.binancefuture.com/ws",
+        }
+    }
+
+    // rest endpoint, only the futures markets are polled
+    pub fn rest_url(&self) -> Option<&'static str> {
+        match self {
+            Market::Usdm => Some("https://fapi.binance.com"),
+            Market::Coinm => Some("https://dapi.binance.com"),
+            Market::Testnet => Some("https://testnet.binancefuture.com"),
+            Market::Spot => None,
+        }
+    }
+
+    // usd value of a single contract, only coin-m volumes are quoted in contracts
+    pub fn contract_size(&self, symbol: &str, configured: Option<f64>) -> Option<f64> {
+        match self {
+            // 100 usd for the btc contracts, 10 usd for everything else
+            Market::Coinm => Some(configured.unwrap_or(if symbol.starts_with("BTC") { 100. } else { 10. })),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Market {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
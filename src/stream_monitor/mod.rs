@@ -1,151 +1,577 @@
-use anyhow::Result;
-use async_std::task;
-use buffer::BufferNode;
+use buffer::{Admission, Anomalies, BufferNode, IngestionConfig};
+use chrono::{DateTime, Utc};
 use circular_buffer::CircularBuffer;
-use event::Event;
-use futures_util::StreamExt;
-use log::{error, info, debug};
-use serde_json;
-use std::sync::Arc;
-use tokio::time::{interval, Duration};
-use tokio::sync::Mutex;
-use tokio_tungstenite::connect_async;
-use tokio_tungstenite::tungstenite::protocol::Message;
+use crate::error::Result;
+use clock::{EvaluationConfig, EvaluationMode};
+use evaluation::Reason;
+use hub::{Publisher, SymbolControls};
+use indicator::IndicatorState;
+use latency::{LatencyConfig, LatencyMonitor};
+use liquidation::{Liquidation, LiquidationConfig, LiquidationWindow};
+use log::{info, debug, warn};
+use mark_price::{MarkPrice, MarkPriceConfig, MarkPriceState};
+use market::Market;
+use open_interest::{OpenInterestConfig, OpenInterestHistory, OpenInterestSample};
+use orderbook::{BookTicker, Depth, OrderBook, OrderBookConfig};
+use persist::{Fingerprint, PersistenceConfig, SavedState};
+use recorder::Recorder;
+use reference::{ReferenceConfig, SecondCloses};
+use regime::{MarketRegime, RegimeConfig};
+use snapshot::{Signal, SignalState, SymbolSnapshot};
+use source::Source;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
 mod event;
-mod buffer;
-mod atr;
+pub mod buffer;
+pub mod atr;
+pub mod clock;
+pub mod evaluation;
+pub mod hub;
+pub mod indicator;
+pub mod latency;
+pub mod liquidation;
+pub mod mark_price;
+pub mod market;
+pub mod open_interest;
+pub mod orderbook;
+pub mod persist;
+pub mod recorder;
+pub mod reference;
+pub mod regime;
+pub mod snapshot;
+pub mod source;
 
-static FUTURES_URL: &str = "wss://fstream.binance.com/ws";
-static STREAM_TYPE: &str = "kline_1m";
-const ATR_CHECK_WINDOW_SECONDS: usize = 10; // TODO: configurable per symbol
-const WARMUP_WINDOW_SECONDS: usize = 60;
+// binance pushes a kline update every 250ms, this is plenty of headroom
+const NODE_CHANNEL_CAPACITY: usize = 1024;
+// kline updates come every 250ms, this long without one means the stream stalled
+const STALE_DATA_MS: f64 = 5000.;
+
+// everything that drives the evaluation of a single symbol
+#[derive(Debug, Clone)]
+pub struct SymbolSettings {
+    pub atr_moving_average: atr::MovingAverage,
+    pub atr_window_seconds: usize,
+    pub atr_threshold: f64,
+    pub atr_min_candles_percent: f64,
+    pub min_vol_usdt: f64,
+    pub evaluation: EvaluationConfig,
+    pub latency: LatencyConfig,
+    pub market: Market,
+    // websocket endpoint the symbol streams are opened under
+    pub stream_url: String,
+    // usd value of a contract, for the markets quoting volume in contracts
+    pub contract_size: Option<f64>,
+    pub ingestion: IngestionConfig,
+    pub orderbook: OrderBookConfig,
+    pub liquidations: LiquidationConfig,
+    pub mark_price: MarkPriceConfig,
+    pub open_interest: OpenInterestConfig,
+    pub regime: RegimeConfig,
+    pub reference: ReferenceConfig,
+    // the reference symbol when it's not among the configured ones, it never signals
+    // and stays out of the market regime
+    pub reference_only: bool,
+    pub persistence: PersistenceConfig,
+    // rest endpoint open interest is polled from, None for spot
+    pub rest_url: Option<String>,
+}
 
 pub struct SymbolData {
     pub symbol:  String,
-    atr_threshold: f64,
-    atr_min_candles_percent: f64,
-    min_vol_usdt: f64,
+    settings: SymbolSettings,
     buffer: buffer::SymbolBuffer,
+    indicators: IndicatorState,
+    book: OrderBook,
+    liquidations: LiquidationWindow,
+    mark_price: MarkPriceState,
+    open_interest: OpenInterestHistory,
+    latency: LatencyMonitor,
+    // what's over the latency limits, as of the latest tick
+    latency_problem: Option<String>,
+    // whether the warmup is over
+    warm: bool,
+    // signalling is paused, the evaluation goes on
+    paused: bool,
+    messages: u64,
+    anomalies: Anomalies,
+    signals: u64,
+    // dropped connections of a live stream
+    reconnects: u64,
+}
+
+// anything decoded from the streams of a symbol
+#[derive(Debug)]
+pub enum Update {
+    Kline(BufferNode),
+    Depth(Depth),
+    BookTicker(BookTicker),
+    Liquidation(Liquidation),
+    MarkPrice(MarkPrice),
+    OpenInterest(OpenInterestSample),
+    // the connection dropped, the reader is about to reconnect
+    Reconnecting,
+}
+
+// an update along with the moment the reader got it, to measure the evaluation latency
+pub struct Received {
+    pub update: Update,
+    pub at: Instant,
 }
 
 impl SymbolData {
-    pub fn new(symbol: &str, atr_threshold: f64, atr_min_candles_percent: f64, min_vol_usdt: f64) -> Arc<Mutex<Self>> {
-        let buffer = CircularBuffer::new();
-        Arc::new(Mutex::new(
-            SymbolData {
-                symbol: symbol.to_string(),
-                buffer: buffer,
-                atr_threshold: atr_threshold,
-                atr_min_candles_percent: atr_min_candles_percent,
-                min_vol_usdt: min_vol_usdt,
+    pub fn new(symbol: &str, settings: SymbolSettings) -> Self {
+        SymbolData {
+            symbol: symbol.to_string(),
+            buffer: CircularBuffer::new(),
+            indicators: IndicatorState::new(settings.atr_window_seconds, settings.atr_moving_average, settings.reference.window_seconds),
+            book: OrderBook::new(settings.orderbook.levels, settings.orderbook.depletion_window_seconds),
+            liquidations: LiquidationWindow::new(settings.atr_window_seconds),
+            mark_price: MarkPriceState::default(),
+            open_interest: OpenInterestHistory::new(&settings.open_interest.windows_seconds),
+            settings,
+            latency: LatencyMonitor::new(),
+            latency_problem: None,
+            warm: false,
+            paused: false,
+            messages: 0,
+            anomalies: Anomalies::default(),
+            signals: 0,
+            reconnects: 0,
+        }
+    }
+
+    pub fn ingest(&mut self, node: BufferNode) {
+        self.messages += 1;
+        if let Err(e) = indicator::check_input(&node) {
+            warn!(symbol = self.symbol.as_str(), ts = node.ts.timestamp_millis(); "dropping message for {}: {}", self.symbol, e);
+            return;
+        }
+        // every message tells about the feed, even the ones we drop
+        self.latency.record(&node);
+        let tolerance = chrono::Duration::milliseconds(self.settings.ingestion.late_tolerance_ms as i64);
+        let admission = buffer::admit(&mut self.buffer, node.clone(), tolerance);
+        self.anomalies.count(admission);
+        if admission != Admission::Appended {
+            debug!(symbol = self.symbol.as_str(), ts = node.ts.timestamp_millis(); "{:?} message for {} at {}", admission, self.symbol, node.ts);
+        }
+        if admission.accepted() {
+            self.indicators.update(&node);
+        }
+    }
+
+    // the overridden thresholds are used from the next evaluation on
+    fn apply(&mut self, controls: SymbolControls) {
+        if let Some(atr_threshold) = controls.atr_threshold {
+            self.settings.atr_threshold = atr_threshold;
+        }
+        if let Some(min_vol_usdt) = controls.min_vol_usdt {
+            self.settings.min_vol_usdt = min_vol_usdt;
+        }
+        self.paused = controls.paused;
+    }
+
+    fn save(&self) -> SavedState {
+        SavedState {
+            version: persist::FORMAT_VERSION,
+            symbol: self.symbol.clone(),
+            saved_at: Utc::now(),
+            fingerprint: Fingerprint::new(&self.settings),
+            buffer: self.buffer.iter().cloned().collect(),
+            atr: self.indicators.atr.save(),
+            volume: self.indicators.volume.save(),
+            open_interest: self.open_interest.samples().copied().collect(),
+        }
+    }
+
+    // picks up the state saved on the last shutdown, returns how much of the warmup it makes up for
+    // the warmup only fills the buffer, so that's the saved data still within it as of `now`
+    fn restore(&mut self, now: DateTime<Utc>, warmup: Duration) -> Duration {
+        let config = &self.settings.persistence;
+        let fingerprint = Fingerprint::new(&self.settings);
+        let state = match persist::load(&config.dir, &self.symbol, fingerprint, config.max_age_seconds, now) {
+            Ok(Some(state)) => state,
+            Ok(None) => return Duration::ZERO,
+            Err(e) => {
+                warn!(symbol = self.symbol.as_str(); "not restoring the state of {}: {}", self.symbol, e);
+                return Duration::ZERO;
             }
-        ))
+        };
+        // the closes aren't saved, the buffer has them all
+        for node in state.buffer {
+            self.indicators.closes.push(&node);
+            self.buffer.push_back(node);
+        }
+        self.indicators.atr.restore(state.atr);
+        self.indicators.volume.restore(state.volume, now);
+        for sample in state.open_interest {
+            self.open_interest.push(sample);
+        }
+        let since = now - chrono::Duration::seconds(warmup.as_secs() as i64);
+        let covered = match (self.buffer.front(), self.buffer.back()) {
+            (Some(first), Some(last)) => (last.ts - first.ts.max(since)).to_std().unwrap_or_default(),
+            _ => Duration::ZERO,
+        };
+        info!(
+            symbol = self.symbol.as_str(), saved_at = state.saved_at.timestamp_millis(), updates = self.buffer.len();
+            "restored {} updates of {} saved at {}", self.buffer.len(), self.symbol, state.saved_at
+        );
+        covered
     }
-}
 
-pub async fn run(handler: Arc<Mutex<SymbolData>>) -> Result<()> {
-    let url = {
-        let handler = handler.lock().await;
-        format!("{}/{}@{}", FUTURES_URL, handler.symbol.to_lowercase(), STREAM_TYPE)
-    };
+    fn is_reference(&self) -> bool {
+        self.settings.reference.enabled && self.settings.reference.symbol == self.symbol
+    }
 
-    info!("connecting to websocket at {}", url);
+    // `now` is the wall-clock time for live streams, recordings can't go stale
+    // `regime` tags a triggered evaluation as market-wide or idiosyncratic
+    // `reference` are the per-second closes of the reference symbol, if it's enabled
+    // `bars` fills in the per-second bars of the buffer, which takes a walk over it
+    pub fn evaluate(
+        &self,
+        now: Option<DateTime<Utc>>,
+        regime: Option<&MarketRegime>,
+        reference: Option<&SecondCloses>,
+        bars: bool,
+    ) -> SymbolSnapshot {
+        let settings = &self.settings;
+        let mut evaluation = self.indicators.atr.check(settings.atr_threshold, settings.atr_min_candles_percent);
+        // the reference itself has nothing to be related to
+        if let Some(reference) = reference.filter(|_| !self.is_reference()) {
+            let closes = self.indicators.closes.closes();
+            evaluation.reference = reference::relate(closes, reference, evaluation.atr_pct, settings.reference.min_samples);
+            // the atr passed its own checks, the residual one decides instead
+            if settings.reference.use_residual && matches!(evaluation.reason, Reason::BelowThreshold | Reason::Triggered) {
+                evaluation.residual = true;
+                evaluation.reason = match evaluation.reference {
+                    None => Reason::NoReferenceData,
+                    Some(stats) if stats.residual_atr_pct > settings.atr_threshold => Reason::Triggered,
+                    Some(_) => Reason::BelowThreshold,
+                };
+            }
+        }
+        evaluation.vol_usdt = self.indicators.volume.sum();
+        evaluation.min_vol_usdt = settings.min_vol_usdt;
+        evaluation.return_pct = self.indicators.return_pct();
+        evaluation.data_age_ms = now
+            .zip(self.buffer.back())
+            .map(|(now, node)| (now - node.recv_ts).num_microseconds().unwrap_or(i64::MAX) as f64 / 1000.);
 
-    // init connection now
-    let (ws_stream, _) = connect_async(url).await?;
-    debug!("connection successful");
+        if !self.warm {
+            evaluation.reason = Reason::WarmingUp;
+        } else if evaluation.data_age_ms.is_some_and(|age| age > STALE_DATA_MS) {
+            evaluation.reason = Reason::StaleData;
+        } else if evaluation.is_triggered() && evaluation.vol_usdt < settings.min_vol_usdt {
+            evaluation.reason = Reason::VolumeTooLow;
+        }
+        evaluation.book = self.book.stats();
+        // over the same window as the atr, up to the latest kline
+        let liquidations_streamed = !settings.liquidations.streams(settings.market).is_empty();
+        evaluation.liquidations = self
+            .buffer
+            .back()
+            .filter(|_| liquidations_streamed)
+            .map(|node| self.liquidations.stats(node.ts));
+        evaluation.mark = self.indicators.atr.close().and_then(|close| self.mark_price.stats(close));
+        evaluation.open_interest = self.open_interest.stats();
+        if evaluation.is_triggered() {
+            let failed = settings
+                .orderbook
+                .check(evaluation.book.as_ref())
+                .or_else(|| evaluation.liquidations.and_then(|stats| settings.liquidations.check(&stats)))
+                .or_else(|| evaluation.mark.and_then(|stats| settings.mark_price.check(&stats)))
+                .or_else(|| settings.open_interest.check(evaluation.open_interest.as_ref()));
+            if let Some((reason, limit)) = failed {
+                evaluation.reason = reason;
+                evaluation.limit = Some(limit);
+            }
+        }
+        if let Some(regime) = regime.filter(|_| evaluation.is_triggered()) {
+            let (tag, breadth) = regime.tag(&self.symbol, &settings.regime);
+            evaluation.regime = Some(tag);
+            evaluation.breadth = Some(breadth);
+            if let Some(reason) = settings.regime.check(tag) {
+                evaluation.reason = reason;
+            }
+        }
 
-    // split the stream into a receiver and a sender, we do not need the latter
-    let (_, mut read) = ws_stream.split();
+        let latency = self.latency.stats();
+        let latency_problem = latency.as_ref().and_then(|stats| settings.latency.check(stats));
+        let state = if !evaluation.is_triggered() || settings.reference_only {
+            SignalState::Idle
+        } else if self.paused {
+            SignalState::Paused
+        } else if latency_problem.is_some() && settings.latency.suppress_signals {
+            SignalState::Suppressed
+        } else {
+            SignalState::Triggered
+        };
 
-    // tokio magic
-    let collection_clone = Arc::clone(&handler);
+        SymbolSnapshot {
+            symbol: self.symbol.clone(),
+            market: settings.market,
+            ts: self.buffer.back().map(|node| node.ts),
+            close_price: self.indicators.atr.close().unwrap_or(0.),
+            evaluation,
+            state,
+            paused: self.paused,
+            reference_only: settings.reference_only,
+            messages: self.messages,
+            anomalies: self.anomalies,
+            buffer: buffer::health(&self.buffer),
+            signals: self.signals,
+            reconnects: self.reconnects,
+            received_at: self.buffer.back().map(|node| node.recv_ts),
+            bars: if bars { buffer::bars(&self.buffer) } else { vec![] },
+            eval_latency_ms: None,
+            latency,
+            latency_problem,
+        }
+    }
+}
+
+// the reader only parses the stream and forwards the nodes, the symbol state
+// is owned by a single task so that nothing on the hot path needs a lock
+// once `cancel` fires the reader stops (closing the websocket properly) and the owner drains what's left
+pub async fn run(
+    handler: SymbolData,
+    publisher: Publisher,
+    cancel: CancellationToken,
+    source: Source,
+    recorder: Option<Recorder>,
+) -> Result<()> {
+    let (tx, rx) = mpsc::channel::<Received>(NODE_CHANNEL_CAPACITY);
+    let feed = source::Feed {
+        symbol: handler.symbol.clone(),
+        stream_url: handler.settings.stream_url.clone(),
+        contract_size: handler.settings.contract_size,
+        streams: [
+            handler.settings.orderbook.streams(),
+            handler.settings.liquidations.streams(handler.settings.market),
+            handler.settings.mark_price.streams(handler.settings.market),
+        ]
+        .concat(),
+    };
+    let live = source.is_live();
+    // open interest isn't recorded, so only live streams poll it
+    let poller = handler.settings.rest_url.clone().filter(|_| live && handler.settings.open_interest.enabled).map(|rest_url| {
+        open_interest::OpenInterestFeed {
+            symbol: handler.symbol.clone(),
+            market: handler.settings.market,
+            rest_url,
+            poll_interval: Duration::from_millis(handler.settings.open_interest.poll_interval_ms),
+            history_seconds: handler.settings.open_interest.windows_seconds.iter().copied().max().unwrap_or_default(),
+        }
+    });
+    // the poller stops along with the reader, so that the owner sees the channel close
+    let poll_cancel = cancel.child_token();
+
+    let polling_handle = poller.map(|poller| tokio::spawn(open_interest::poll(poller, tx.clone(), poll_cancel.clone())));
 
     // collection loop
     let collection_handle = tokio::spawn(async move {
-        while let Some(message) = read.next().await {
-            match message {
-                Ok(Message::Text(text)) => {
-                    debug!("message received: {:?}", text);
-                    match serde_json::from_str::<Event>(&text) {
-                        Ok(parsed_message) => {
-
-                            debug!("Received and parsed message: {:?}", parsed_message);
-                            // append a new node to the buffer
-                            match BufferNode::from_kline_event(&parsed_message) {
-                                Ok(node) => {
-                                    debug!("appending node: {:?}", node);
-                                    let mut handler = collection_clone.lock().await;
-                                    handler.buffer.push_back(node);
-                                    // don't have to explicitly drop the lock because it goes out of the scope
-                                    // and the lock is gone implicitly
-                                }
-                                Err(e) => {
-                                    error!("failed to create BufferNode: {:?}", e);
-                                }
-                            }
+        let result = match source {
+            Source::Live => source::read_websocket(feed, tx, cancel, recorder).await,
+            Source::Replay { path, speed } => source::read_recording(feed, path, speed, tx, cancel).await,
+        };
+        poll_cancel.cancel();
+        result
+    });
+
+    let monitoring_handle = tokio::spawn(monitor(handler, rx, publisher, live));
+
+    let (collection_result, monitoring_result) = tokio::join!(collection_handle, monitoring_handle);
+    let polling_result = match polling_handle {
+        Some(handle) => handle.await,
+        None => Ok(Ok(())),
+    };
+    // a panic in any part is a bug, let it reach the supervisor as such
+    for result in [monitoring_result.map(|_| Ok(())), collection_result, polling_result] {
+        match result {
+            Ok(Err(e)) => return Err(e.into()),
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// owner loop: ingests the forwarded nodes and evaluates the symbol
+// in the aligned mode live streams are evaluated on the wall-clock second boundaries, recordings whenever
+// the event time crosses a second, so that a replay evaluates the same seconds no matter how fast it runs
+// in the event mode every node is evaluated as soon as it's ingested
+// order book updates, liquidations, mark prices and open interest are only kept, the klines alone drive the evaluation
+// it runs until the reader hangs up, so every forwarded node is accounted for
+async fn monitor(mut handler: SymbolData, mut rx: mpsc::Receiver<Received>, publisher: Publisher, live: bool) {
+    let s = handler.symbol.clone();
+    let evaluation = handler.settings.evaluation;
+    // recordings are replayed from their start, only live streams carry on where they left off
+    let persist = live && handler.settings.persistence.enabled;
+    let mut warmup = Duration::from_secs(evaluation.warmup_seconds);
+    if persist {
+        warmup = warmup.saturating_sub(handler.restore(Utc::now(), warmup));
+    }
+    info!(symbol = s.as_str(); "allowing {:?} seconds to populate buffer for {}", warmup.as_secs(), s);
+    let started = Instant::now();
+    let mut interval = clock::aligned_interval(warmup, evaluation.missed_ticks);
+    let on_clock = live && evaluation.mode == EvaluationMode::Aligned;
+    let mut first_ts: Option<DateTime<Utc>> = None;
+    let mut last_received: Option<Instant> = None;
+    let mut controls = publisher.controls.clone();
+    loop {
+        tokio::select! {
+            Ok(()) = controls.changed() => {
+                let controls = *controls.borrow_and_update();
+                info!(
+                    symbol = s.as_str(), atr_threshold = controls.atr_threshold, min_vol_usdt = controls.min_vol_usdt, paused = controls.paused;
+                    "controls of {} changed, paused: {}", s, controls.paused
+                );
+                handler.apply(controls);
+            }
+            received = rx.recv() => match received {
+                Some(Received { update, at }) => {
+                    let node = match update {
+                        Update::Kline(node) => node,
+                        Update::Depth(depth) => {
+                            handler.book.update_depth(depth);
+                            continue;
+                        }
+                        Update::BookTicker(ticker) => {
+                            handler.book.update_ticker(ticker);
+                            continue;
+                        }
+                        Update::Liquidation(liquidation) => {
+                            handler.liquidations.push(liquidation);
+                            continue;
+                        }
+                        Update::MarkPrice(mark) => {
+                            handler.mark_price.update(mark);
+                            continue;
+                        }
+                        Update::OpenInterest(sample) => {
+                            handler.open_interest.push(sample);
+                            continue;
+                        }
+                        Update::Reconnecting => {
+                            handler.reconnects += 1;
+                            continue;
                         }
-                        Err(e) => {
-                            error!("failed to parse message: {:?}", e);
+                    };
+                    let data_started = *first_ts.get_or_insert(node.ts);
+                    handler.warm = if live {
+                        started.elapsed() >= warmup
+                    } else {
+                        (node.ts - data_started).to_std().unwrap_or_default() >= warmup
+                    };
+                    if !live && evaluation.mode == EvaluationMode::Aligned {
+                        let crossed = handler.buffer.back().is_some_and(|last| node.ts.timestamp() > last.ts.timestamp());
+                        if crossed && handler.warm {
+                            tick(&mut handler, &publisher, last_received, live);
                         }
                     }
+                    handler.ingest(node);
+                    last_received = Some(at);
+                    if evaluation.mode == EvaluationMode::Event && handler.warm {
+                        tick(&mut handler, &publisher, last_received, live);
+                    }
                 }
-                Err(e) => {
-                    error!("error while reading from stream: {:?}", e)
-                }
-                _ => {}
+                // the reader is done
+                None => break,
+            },
+            _ = interval.tick(), if on_clock => {
+                // the interval only starts once the warmup is over
+                handler.warm = true;
+                tick(&mut handler, &publisher, last_received, live);
             }
         }
-    });
-
-    let monitoring_clone = Arc::clone(&handler);
-    let handler = handler.lock().await;
-    let s = handler.symbol.clone();
-    let at = handler.atr_threshold.clone();
-    let am = handler.atr_min_candles_percent.clone();
-    let mv = handler.min_vol_usdt.clone();
-    // explicitly drop the lock becasue it needs to be
-    drop(handler);
-
-    let monitoring_handle = tokio::spawn(async move {
-        info!("allowing {:?} seconds to populate buffer for {}", WARMUP_WINDOW_SECONDS, s);
-        task::sleep(Duration::from_secs(WARMUP_WINDOW_SECONDS as u64)).await;
-        let mut interval = interval(Duration::from_secs(1));
-        loop {
-            interval.tick().await; // IMPORTANT NOTE: if ticks are missed, they ACCUMULATE!!!
-            let handler = monitoring_clone.lock().await;
-            let buffer_copy = handler.buffer.clone();
-            drop(handler);
-            // calculate atr
-            let atr_result = atr::check_atr_condition(
-                &buffer_copy,
-                ATR_CHECK_WINDOW_SECONDS,
-                at,
-                am
-            );
-            // get volume delta for the period
-            let vol_usdt = buffer::calc_volume_delta(&buffer_copy, ATR_CHECK_WINDOW_SECONDS as i64);
-
-            // Handle the ATR result as needed
-            match atr_result {
-                Ok((limit_passed, val)) => {
-                   // TODO: this is to be changed based on the action we want to take
-                   if limit_passed && vol_usdt >= mv {
-                    info!("SYMBOL {} READY FOR TRADE RUN, ATR: {:.3}, VOLUME: {:.3}", &s, val, vol_usdt)
-                   } else {
-                    info!("symbol {} idle, atr: {:.3}, volume: {:.3}", &s, val, vol_usdt)
-                   }
-                }
-                Err(e) => {
-                    error!("an error occurred while calculating atr for {}: {:?}", &s, e);
-                }
-            }
+    }
+    // publish the final state for the shutdown summary
+    let reference = handler.settings.reference.enabled.then(|| publisher.reference.borrow().clone());
+    publisher.publish(handler.evaluate(None, None, reference.as_ref(), publisher.wants_bars()));
+    if persist {
+        match persist::save(&handler.settings.persistence.dir, &handler.save()) {
+            Ok(path) => info!(symbol = s.as_str(); "saved the state of {} to {:?}", s, path),
+            Err(e) => warn!(symbol = s.as_str(); "failed to save the state of {}: {}", s, e),
         }
-    });
+    }
+    debug!(symbol = s.as_str(); "monitoring loop for {} finished", s);
+}
 
-    let _ = tokio::try_join!(collection_handle, monitoring_handle);
+// evaluates the symbol, `received` is when the latest ingested node arrived
+fn tick(handler: &mut SymbolData, publisher: &Publisher, received: Option<Instant>, live: bool) {
+    let regime = publisher.regime.borrow().clone();
+    let reference = handler.settings.reference.enabled.then(|| publisher.reference.borrow().clone());
+    let mut snapshot = handler.evaluate(live.then(Utc::now), Some(&regime), reference.as_ref(), publisher.wants_bars());
+    if handler.is_reference() {
+        publisher.reference.send_replace(handler.indicators.closes.closes().clone());
+    }
+    // receipt of the latest message to the decision
+    snapshot.eval_latency_ms = received.map(|at| at.elapsed().as_secs_f64() * 1000.);
+    let latency_ms = snapshot.eval_latency_ms.unwrap_or_default();
+    let s = &snapshot.symbol;
+    let e = &snapshot.evaluation;
 
-    Ok(())
-}
+    // only the changes are logged, not every tick
+    if snapshot.latency_problem != handler.latency_problem {
+        match &snapshot.latency_problem {
+            Some(problem) => warn!(symbol = s.as_str(); "feed of {} is degraded: {}", s, problem),
+            None => info!(symbol = s.as_str(); "feed of {} is back within the latency limits", s),
+        }
+        handler.latency_problem = snapshot.latency_problem.clone();
+    }
 
+    // TODO: this is to be changed based on the action we want to take
+    if snapshot.state == SignalState::Triggered {
+        info!(
+            symbol = s.as_str(), market = snapshot.market.as_str(), atr = e.atr, atr_pct = e.atr_pct, vol_usdt = e.vol_usdt,
+            spread_bps = e.book.as_ref().and_then(|book| book.spread_bps),
+            imbalance = e.book.as_ref().and_then(|book| book.imbalance),
+            liquidations_usdt = e.liquidations.map(|stats| stats.total_usdt()),
+            basis_bps = e.mark.map(|mark| mark.basis_bps), predicted_funding_rate = e.mark.map(|mark| mark.predicted_funding_rate),
+            oi_change_pct = e.open_interest.as_ref().and_then(|oi| oi.changes.first()).and_then(|change| change.change_pct),
+            regime = e.regime.map(|tag| tag.as_str()), breadth = e.breadth,
+            beta = e.reference.map(|stats| stats.beta), residual_atr_pct = e.reference.map(|stats| stats.residual_atr_pct),
+            state = snapshot.state.as_str(), reason = e.reason.as_str(), latency_ms = latency_ms;
+            "SYMBOL {} READY FOR TRADE RUN, ATR: {:.3}, VOLUME: {:.3}, LATENCY: {:.1}ms, {}", s, e.atr, e.vol_usdt, latency_ms, e
+        );
+        handler.signals += 1;
+        snapshot.signals = handler.signals;
+        // nobody listening is fine
+        let _ = publisher.signals.send(Signal {
+            symbol: s.clone(),
+            market: snapshot.market,
+            ts: snapshot.ts.unwrap_or_else(Utc::now),
+            close_price: snapshot.close_price,
+            evaluation: e.clone(),
+            eval_latency_ms: snapshot.eval_latency_ms,
+            feed_latency_ms: snapshot.latency.as_ref().map(|stats| stats.latency_ms),
+        });
+    } else if snapshot.state == SignalState::Paused {
+        info!(
+            symbol = s.as_str(), atr = e.atr, atr_pct = e.atr_pct, vol_usdt = e.vol_usdt,
+            state = snapshot.state.as_str(), reason = e.reason.as_str(), latency_ms = latency_ms;
+            "signal for {} held back, signalling is paused, {}", s, e
+        );
+    } else if snapshot.state == SignalState::Suppressed {
+        warn!(
+            symbol = s.as_str(), atr = e.atr, atr_pct = e.atr_pct, vol_usdt = e.vol_usdt,
+            state = snapshot.state.as_str(), reason = e.reason.as_str(), latency_ms = latency_ms;
+            "signal for {} suppressed, {}", s, snapshot.latency_problem.as_deref().unwrap_or_default()
+        );
+    } else if handler.settings.evaluation.mode == EvaluationMode::Event {
+        // every message would be logged otherwise
+        debug!(
+            symbol = s.as_str(), atr = e.atr, atr_pct = e.atr_pct, vol_usdt = e.vol_usdt,
+            state = snapshot.state.as_str(), reason = e.reason.as_str(), latency_ms = latency_ms;
+            "symbol {} idle, {}", s, e
+        )
+    } else {
+        info!(
+            symbol = s.as_str(), atr = e.atr, atr_pct = e.atr_pct, vol_usdt = e.vol_usdt,
+            state = snapshot.state.as_str(), reason = e.reason.as_str(), latency_ms = latency_ms;
+            "symbol {} idle, {}", s, e
+        )
+    }
+    publisher.publish(snapshot);
+}
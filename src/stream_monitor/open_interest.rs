@@ -0,0 +1,304 @@
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use super::buffer::parse_number;
+use super::evaluation::Reason;
+use super::market::Market;
+use super::{Received, Update};
+use crate::error::{DecodeError, TransportError};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// the history endpoint has 5 minute periods at best and returns up to 500 of them
+const HISTORY_PERIOD_SECONDS: u64 = 300;
+const HISTORY_MAX_LIMIT: u64 = 500;
+
+// the `open_interest` config section, polling is off unless enabled
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct OpenInterestConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // overrides the rest endpoint of the market, e.g. to point at a local mock server
+    #[serde(default)]
+    pub rest_url: Option<String>,
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    // the open interest change is reported over each of these
+    #[serde(default = "default_windows_seconds")]
+    pub windows_seconds: Vec<u64>,
+    // limits of the change over the first window in percent, checked once atr and volume pass
+    // a minimum of 0 only lets new positioning through, a maximum of 0 only forced closing
+    #[serde(default)]
+    pub min_change_pct: Option<f64>,
+    #[serde(default)]
+    pub max_change_pct: Option<f64>,
+}
+
+fn default_poll_interval_ms() -> u64 {
+    10_000
+}
+
+fn default_windows_seconds() -> Vec<u64> {
+    vec![60, 300]
+}
+
+impl Default for OpenInterestConfig {
+    fn default() -> Self {
+        OpenInterestConfig {
+            enabled: false,
+            rest_url: None,
+            poll_interval_ms: default_poll_interval_ms(),
+            windows_seconds: default_windows_seconds(),
+            min_change_pct: None,
+            max_change_pct: None,
+        }
+    }
+}
+
+impl OpenInterestConfig {
+    // the failed condition along with its limit
+    pub fn check(&self, stats: Option<&OpenInterestStats>) -> Option<(Reason, f64)> {
+        if self.min_change_pct.is_none() && self.max_change_pct.is_none() {
+            return None;
+        }
+        let limit = self.min_change_pct.or(self.max_change_pct).unwrap_or_default();
+        let Some(change) = stats.and_then(|stats| stats.changes.first()).and_then(|change| change.change_pct) else {
+            return Some((Reason::NoOpenInterestData, limit));
+        };
+        if let Some(min) = self.min_change_pct.filter(|min| change < *min) {
+            return Some((Reason::OpenInterestChangeTooLow, min));
+        }
+        if let Some(max) = self.max_change_pct.filter(|max| change > *max) {
+            return Some((Reason::OpenInterestChangeTooHigh, max));
+        }
+        None
+    }
+}
+
+// (current, history) paths of the market, coin-m history goes by pair rather than symbol so it's not read
+fn paths(market: Market) -> Option<(&'static str, Option<&'static str>)> {
+    match market {
+        Market::Usdm | Market::Testnet => Some(("/fapi/v1/openInterest", Some("/futures/data/openInterestHist"))),
+        Market::Coinm => Some(("/dapi/v1/openInterest", None)),
+        Market::Spot => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct OpenInterestSample {
+    pub ts: DateTime<Utc>,
+    // in contracts, or the base asset for usd-m
+    pub open_interest: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CurrentResponse {
+    #[serde(rename = "openInterest")]
+    open_interest: String,
+    time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryResponse {
+    #[serde(rename = "sumOpenInterest")]
+    open_interest: String,
+    timestamp: i64,
+}
+
+fn sample(open_interest: &str, ts_ms: i64) -> Result<OpenInterestSample, DecodeError> {
+    Ok(OpenInterestSample {
+        ts: DateTime::from_timestamp_millis(ts_ms).ok_or(DecodeError::Timestamp(ts_ms))?,
+        open_interest: parse_number("openInterest", open_interest)?,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct OpenInterestChange {
+    pub window_seconds: u64,
+    // None until there's a sample that old
+    pub change_pct: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpenInterestStats {
+    pub open_interest: f64,
+    pub ts: DateTime<Utc>,
+    // in the order of `windows_seconds`
+    pub changes: Vec<OpenInterestChange>,
+}
+
+// polled samples of the symbol, as far back as the longest window
+#[derive(Debug, Clone)]
+pub struct OpenInterestHistory {
+    windows_seconds: Vec<u64>,
+    samples: VecDeque<OpenInterestSample>,
+}
+
+impl OpenInterestHistory {
+    pub fn new(windows_seconds: &[u64]) -> Self {
+        OpenInterestHistory { windows_seconds: windows_seconds.to_vec(), samples: VecDeque::new() }
+    }
+
+    pub fn push(&mut self, sample: OpenInterestSample) {
+        if self.samples.back().is_some_and(|latest| latest.ts >= sample.ts) {
+            return;
+        }
+        self.samples.push_back(sample);
+        // keep the latest sample from before the longest window as the reference
+        let longest = chrono::Duration::seconds(self.windows_seconds.iter().copied().max().unwrap_or_default() as i64);
+        while self.samples.get(1).is_some_and(|next| next.ts <= sample.ts - longest) {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &OpenInterestSample> {
+        self.samples.iter()
+    }
+
+    // the change against the latest sample at least a window old
+    pub fn stats(&self) -> Option<OpenInterestStats> {
+        let latest = self.samples.back()?;
+        let changes = self
+            .windows_seconds
+            .iter()
+            .map(|&window_seconds| {
+                let since = latest.ts - chrono::Duration::seconds(window_seconds as i64);
+                let change_pct = self
+                    .samples
+                    .iter()
+                    .rev()
+                    .find(|sample| sample.ts <= since)
+                    .filter(|reference| reference.open_interest > 0.)
+                    .map(|reference| (latest.open_interest - reference.open_interest) / reference.open_interest * 100.);
+                OpenInterestChange { window_seconds, change_pct }
+            })
+            .collect();
+        Some(OpenInterestStats { open_interest: latest.open_interest, ts: latest.ts, changes })
+    }
+}
+
+// what the poller needs to know about its symbol
+#[derive(Debug, Clone)]
+pub struct OpenInterestFeed {
+    pub symbol: String,
+    pub market: Market,
+    pub rest_url: String,
+    pub poll_interval: Duration,
+    // longest window, how much history to read at startup
+    pub history_seconds: u64,
+}
+
+async fn get<T: serde::de::DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+    query: &[(&str, String)],
+) -> Result<T, TransportError> {
+    let request_error = |source| TransportError::Request { url: url.to_string(), source: Box::new(source) };
+    client
+        .get(url)
+        .query(query)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(request_error)?
+        .json::<T>()
+        .await
+        .map_err(request_error)
+}
+
+// reads the history once, then polls the current open interest until cancelled
+// failed requests are only logged, the symbol goes on without open interest
+pub async fn poll(feed: OpenInterestFeed, tx: mpsc::Sender<Received>, cancel: CancellationToken) -> Result<(), TransportError> {
+    let symbol = feed.symbol.as_str();
+    let Some((current_path, history_path)) = paths(feed.market) else {
+        return Ok(());
+    };
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|source| TransportError::Request { url: feed.rest_url.clone(), source: Box::new(source) })?;
+    let base = feed.rest_url.trim_end_matches('/');
+    info!(symbol = symbol; "polling open interest of {} from {}", symbol, base);
+
+    if let Some(history_path) = history_path.filter(|_| feed.history_seconds > 0) {
+        let url = format!("{}{}", base, history_path);
+        let limit = (feed.history_seconds / HISTORY_PERIOD_SECONDS + 1).min(HISTORY_MAX_LIMIT);
+        let query = [("symbol", symbol.to_string()), ("period", "5m".to_string()), ("limit", limit.to_string())];
+        let history = tokio::select! {
+            history = get::<Vec<HistoryResponse>>(&client, &url, &query) => history,
+            _ = cancel.cancelled() => return Ok(()),
+        };
+        match history {
+            Ok(history) => {
+                debug!(symbol = symbol; "read {} open interest samples of {}", history.len(), symbol);
+                for entry in history {
+                    if !forward(symbol, sample(&entry.open_interest, entry.timestamp), &tx).await {
+                        return Ok(());
+                    }
+                }
+            }
+            Err(e) => warn!(symbol = symbol; "failed to read the open interest history of {}: {}", symbol, e),
+        }
+    }
+
+    let url = format!("{}{}", base, current_path);
+    let query = [("symbol", symbol.to_string())];
+    loop {
+        let started = Instant::now();
+        let current = tokio::select! {
+            current = get::<CurrentResponse>(&client, &url, &query) => current,
+            _ = cancel.cancelled() => return Ok(()),
+        };
+        match current {
+            Ok(current) => {
+                if !forward(symbol, sample(&current.open_interest, current.time), &tx).await {
+                    return Ok(());
+                }
+            }
+            // e.g. an unknown symbol, asking again won't help
+            Err(e) if !e.is_retryable() => {
+                error!(symbol = symbol; "stopped polling the open interest of {}: {}", symbol, e);
+                return Ok(());
+            }
+            Err(e) => warn!(symbol = symbol; "failed to poll the open interest of {}: {}", symbol, e),
+        }
+        tokio::select! {
+            _ = sleep(feed.poll_interval.saturating_sub(started.elapsed())) => {},
+            _ = cancel.cancelled() => return Ok(()),
+        }
+    }
+}
+
+// returns false once the owner is gone
+async fn forward(symbol: &str, sample: Result<OpenInterestSample, DecodeError>, tx: &mpsc::Sender<Received>) -> bool {
+    match sample {
+        Ok(sample) => tx.send(Received { update: Update::OpenInterest(sample), at: Instant::now() }).await.is_ok(),
+        Err(e) => {
+            warn!(symbol = symbol; "skipping open interest sample of {}: {}", symbol, e);
+            true
+        }
+    }
+}
+
+// TESTS
+#[test]
+fn test_open_interest_changes() {
+    let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let at = |seconds: i64, open_interest: f64| OpenInterestSample { ts: start + chrono::Duration::seconds(seconds), open_interest };
+    let mut history = OpenInterestHistory::new(&[60, 300]);
+    history.push(at(0, 1000.));
+    history.push(at(240, 1100.));
+    history.push(at(310, 1210.));
+
+    let stats = history.stats().unwrap();
+    assert_eq!(stats.open_interest, 1210.);
+    let changes: Vec<_> = stats.changes.iter().map(|change| change.change_pct.map(f64::round)).collect();
+    assert_eq!(changes, [Some(10.), Some(21.)]);
+
+    let config = OpenInterestConfig { enabled: true, min_change_pct: Some(15.), ..Default::default() };
+    assert_eq!(config.check(Some(&stats)), Some((Reason::OpenInterestChangeTooLow, 15.)));
+    assert_eq!(config.check(None), Some((Reason::NoOpenInterestData, 15.)));
+}
@@ -0,0 +1,278 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use super::buffer::parse_number;
+use super::evaluation::Reason;
+use super::event::{BookTickerEvent, DepthEvent};
+use crate::error::DecodeError;
+
+// the partial depth stream carries 20 levels a side
+pub const DEPTH_LEVELS: usize = 20;
+pub static DEPTH_STREAM: &str = "depth20@100ms";
+pub static BOOK_TICKER_STREAM: &str = "bookTicker";
+
+// the `orderbook` config section, the streams are off unless enabled
+// every condition is optional and only checked once atr and volume pass
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct OrderBookConfig {
+    #[serde(default)]
+    pub depth: bool,
+    #[serde(default)]
+    pub book_ticker: bool,
+    // top levels of each side counted for the imbalance and the depletion
+    #[serde(default = "default_levels")]
+    pub levels: usize,
+    #[serde(default = "default_depletion_window_seconds")]
+    pub depletion_window_seconds: u64,
+    #[serde(default)]
+    pub max_spread_bps: Option<f64>,
+    // absolute imbalance, either side will do
+    #[serde(default)]
+    pub min_imbalance: Option<f64>,
+    // drop of the resting notional of either side over the depletion window, in percent
+    #[serde(default)]
+    pub min_depletion_pct: Option<f64>,
+}
+
+fn default_levels() -> usize {
+    10
+}
+
+fn default_depletion_window_seconds() -> u64 {
+    5
+}
+
+impl Default for OrderBookConfig {
+    fn default() -> Self {
+        OrderBookConfig {
+            depth: false,
+            book_ticker: false,
+            levels: default_levels(),
+            depletion_window_seconds: default_depletion_window_seconds(),
+            max_spread_bps: None,
+            min_imbalance: None,
+            min_depletion_pct: None,
+        }
+    }
+}
+
+impl OrderBookConfig {
+    // streams to subscribe to on top of the klines
+    pub fn streams(&self) -> Vec<&'static str> {
+        let mut streams = vec![];
+        if self.depth {
+            streams.push(DEPTH_STREAM);
+        }
+        if self.book_ticker {
+            streams.push(BOOK_TICKER_STREAM);
+        }
+        streams
+    }
+
+    // the first failed condition along with its limit
+    pub fn check(&self, stats: Option<&BookStats>) -> Option<(Reason, f64)> {
+        let conditions = [
+            (self.max_spread_bps, Reason::SpreadTooWide),
+            (self.min_imbalance, Reason::ImbalanceTooLow),
+            (self.min_depletion_pct, Reason::DepletionTooLow),
+        ];
+        for (limit, reason) in conditions {
+            let Some(limit) = limit else {
+                continue;
+            };
+            let value = stats.and_then(|stats| match reason {
+                Reason::SpreadTooWide => stats.spread_bps,
+                Reason::ImbalanceTooLow => stats.imbalance.map(f64::abs),
+                _ => match (stats.bid_depletion_pct, stats.ask_depletion_pct) {
+                    (Some(bid), Some(ask)) => Some(bid.max(ask)),
+                    (depletion, None) | (None, depletion) => depletion,
+                },
+            });
+            let passed = match (reason, value) {
+                (_, None) => return Some((Reason::NoBookData, limit)),
+                (Reason::SpreadTooWide, Some(value)) => value <= limit,
+                (_, Some(value)) => value >= limit,
+            };
+            if !passed {
+                return Some((reason, limit));
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookTicker {
+    pub ts: DateTime<Utc>,
+    pub bid: f64,
+    pub bid_qty: f64,
+    pub ask: f64,
+    pub ask_qty: f64,
+}
+
+// top levels of the book as (price, quantity), best first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Depth {
+    pub ts: DateTime<Utc>,
+    pub bids: Vec<(f64, f64)>,
+    pub asks: Vec<(f64, f64)>,
+}
+
+// spot events carry no event time, the receive time stands in for it
+fn event_ts(event_ms: Option<u64>, recv_ts: DateTime<Utc>) -> Result<DateTime<Utc>, DecodeError> {
+    match event_ms {
+        Some(ms) => DateTime::from_timestamp_millis(ms as i64).ok_or(DecodeError::Timestamp(ms as i64)),
+        None => Ok(recv_ts),
+    }
+}
+
+fn parse_levels(field: &'static str, levels: &[[String; 2]]) -> Result<Vec<(f64, f64)>, DecodeError> {
+    levels
+        .iter()
+        .map(|[price, qty]| Ok((parse_number(field, price)?, parse_number(field, qty)?)))
+        .collect()
+}
+
+impl BookTicker {
+    pub fn from_event(event: &BookTickerEvent, recv_ts: DateTime<Utc>) -> Result<Self, DecodeError> {
+        Ok(BookTicker {
+            ts: event_ts(event.E, recv_ts)?,
+            bid: parse_number("b", &event.b)?,
+            bid_qty: parse_number("B", &event.B)?,
+            ask: parse_number("a", &event.a)?,
+            ask_qty: parse_number("A", &event.A)?,
+        })
+    }
+}
+
+impl Depth {
+    pub fn from_event(event: &DepthEvent, recv_ts: DateTime<Utc>) -> Result<Self, DecodeError> {
+        Ok(Depth {
+            ts: event_ts(event.E, recv_ts)?,
+            bids: parse_levels("b", &event.b)?,
+            asks: parse_levels("a", &event.a)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BookStats {
+    // best ask over best bid, in basis points of the mid price
+    pub spread_bps: Option<f64>,
+    // (bid - ask) / (bid + ask) notional over the top levels, positive when bids dominate
+    pub imbalance: Option<f64>,
+    pub bid_notional: Option<f64>,
+    pub ask_notional: Option<f64>,
+    // drop of the resting notional over the depletion window, in percent
+    pub bid_depletion_pct: Option<f64>,
+    pub ask_depletion_pct: Option<f64>,
+}
+
+// latest state of the book, as far as the partial depth and the book ticker tell
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    levels: usize,
+    window: Duration,
+    ticker: Option<BookTicker>,
+    depth: Option<Depth>,
+    // (ts, bid notional, ask notional) of the depth updates within the window
+    history: VecDeque<(DateTime<Utc>, f64, f64)>,
+}
+
+fn notional(levels: &[(f64, f64)], count: usize) -> f64 {
+    levels.iter().take(count).map(|(price, qty)| price * qty).sum()
+}
+
+fn depletion(before: f64, now: f64) -> Option<f64> {
+    (before > 0.).then(|| (before - now) / before * 100.)
+}
+
+impl OrderBook {
+    pub fn new(levels: usize, window_seconds: u64) -> Self {
+        OrderBook {
+            levels,
+            window: Duration::seconds(window_seconds as i64),
+            ticker: None,
+            depth: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn update_ticker(&mut self, ticker: BookTicker) {
+        if self.ticker.is_some_and(|current| current.ts > ticker.ts) {
+            return;
+        }
+        self.ticker = Some(ticker);
+    }
+
+    pub fn update_depth(&mut self, depth: Depth) {
+        if self.depth.as_ref().is_some_and(|current| current.ts > depth.ts) {
+            return;
+        }
+        let ts = depth.ts;
+        self.history.push_back((ts, notional(&depth.bids, self.levels), notional(&depth.asks, self.levels)));
+        // keep the latest sample from before the window as the reference
+        while self.history.get(1).is_some_and(|(sample_ts, _, _)| *sample_ts <= ts - self.window) {
+            self.history.pop_front();
+        }
+        self.depth = Some(depth);
+    }
+
+    pub fn stats(&self) -> Option<BookStats> {
+        if self.ticker.is_none() && self.depth.is_none() {
+            return None;
+        }
+        let best = match (&self.ticker, &self.depth) {
+            (Some(ticker), _) => Some((ticker.bid, ticker.ask)),
+            (None, Some(depth)) => depth.bids.first().zip(depth.asks.first()).map(|(bid, ask)| (bid.0, ask.0)),
+            (None, None) => None,
+        };
+        let spread_bps = best
+            .filter(|(bid, ask)| *bid > 0. && *ask > 0.)
+            .map(|(bid, ask)| (ask - bid) / ((ask + bid) / 2.) * 10_000.);
+
+        let mut stats = BookStats { spread_bps, ..Default::default() };
+        if let (Some((_, bid_now, ask_now)), Some((_, bid_before, ask_before))) = (self.history.back(), self.history.front()) {
+            let total = bid_now + ask_now;
+            stats.imbalance = (total > 0.).then(|| (bid_now - ask_now) / total);
+            stats.bid_notional = Some(*bid_now);
+            stats.ask_notional = Some(*ask_now);
+            stats.bid_depletion_pct = depletion(*bid_before, *bid_now);
+            stats.ask_depletion_pct = depletion(*ask_before, *ask_now);
+        }
+        Some(stats)
+    }
+}
+
+// TESTS
+#[test]
+fn test_order_book_stats() {
+    let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let mut book = OrderBook::new(2, 5);
+    assert_eq!(book.stats(), None);
+
+    let depth = |second: i64, bid_qty: f64, ask_qty: f64| Depth {
+        ts: start + Duration::seconds(second),
+        bids: vec![(99., bid_qty), (98., bid_qty), (97., 1000.)],
+        asks: vec![(101., ask_qty), (102., ask_qty), (103., 1000.)],
+    };
+    book.update_depth(depth(0, 10., 10.));
+    book.update_depth(depth(3, 10., 5.));
+    // the sample from second 0 falls out of the window, second 3 is the reference now
+    book.update_depth(depth(8, 5., 5.));
+
+    let stats = book.stats().unwrap();
+    // no book ticker, the spread comes from the depth
+    assert_eq!(stats.spread_bps, Some(200.));
+    assert_eq!(stats.bid_notional, Some(99. * 5. + 98. * 5.));
+    assert_eq!(stats.imbalance.map(|imbalance| (imbalance * 1000.).round()), Some(-15.));
+    assert_eq!(stats.bid_depletion_pct, Some(50.));
+    assert_eq!(stats.ask_depletion_pct, Some(0.));
+
+    book.update_ticker(BookTicker { ts: start, bid: 99.95, bid_qty: 1., ask: 100.05, ask_qty: 1. });
+    assert_eq!(book.stats().unwrap().spread_bps.map(|spread| spread.round()), Some(10.));
+
+    let config = OrderBookConfig { max_spread_bps: Some(20.), min_depletion_pct: Some(60.), ..Default::default() };
+    assert_eq!(config.check(book.stats().as_ref()), Some((Reason::DepletionTooLow, 60.)));
+    assert_eq!(config.check(None), Some((Reason::NoBookData, 20.)));
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use super::atr::{MovingAverage, SavedAtr};
+use super::buffer::{BufferNode, SavedVolume};
+use super::market::Market;
+use super::open_interest::OpenInterestSample;
+use super::SymbolSettings;
+use crate::error::PersistError;
+
+// bump on any change to `SavedState`, older files are then rejected instead of misread
+pub const FORMAT_VERSION: u32 = 1;
+
+// the `persistence` config section, off unless enabled
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PersistenceConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // a file per symbol
+    #[serde(default = "default_dir")]
+    pub dir: PathBuf,
+    // older state is left to the warmup, by then most of the buffer is gone anyway
+    #[serde(default = "default_max_age_seconds")]
+    pub max_age_seconds: u64,
+}
+
+fn default_dir() -> PathBuf {
+    PathBuf::from("./state")
+}
+
+fn default_max_age_seconds() -> u64 {
+    30
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        PersistenceConfig { enabled: false, dir: default_dir(), max_age_seconds: default_max_age_seconds() }
+    }
+}
+
+// the settings the state was accumulated under, it means something else under any others
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Fingerprint {
+    pub market: Market,
+    pub contract_size: Option<f64>,
+    pub atr_window_seconds: usize,
+    pub atr_moving_average: MovingAverage,
+}
+
+impl Fingerprint {
+    pub fn new(settings: &SymbolSettings) -> Self {
+        Fingerprint {
+            market: settings.market,
+            contract_size: settings.contract_size,
+            atr_window_seconds: settings.atr_window_seconds,
+            atr_moving_average: settings.atr_moving_average,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SavedState {
+    pub version: u32,
+    pub symbol: String,
+    pub saved_at: DateTime<Utc>,
+    pub fingerprint: Fingerprint,
+    pub buffer: Vec<BufferNode>,
+    pub atr: SavedAtr,
+    pub volume: SavedVolume,
+    pub open_interest: Vec<OpenInterestSample>,
+}
+
+// the version is read on its own first, so that another layout isn't reported as a corrupt file
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+pub fn path(dir: &Path, symbol: &str) -> PathBuf {
+    dir.join(format!("{}.json", symbol))
+}
+
+// written next to the target and renamed, so that a crash mid-write leaves the previous file
+pub fn save(dir: &Path, state: &SavedState) -> Result<PathBuf, PersistError> {
+    let path = path(dir, &state.symbol);
+    let json = serde_json::to_vec(state).map_err(|source| PersistError::Parse { path: path.clone(), source })?;
+    let tmp = path.with_extension("json.tmp");
+    fs::create_dir_all(dir)
+        .and_then(|()| fs::write(&tmp, json))
+        .and_then(|()| fs::rename(&tmp, &path))
+        .map_err(|source| PersistError::Write { path: path.clone(), source })?;
+    Ok(path)
+}
+
+// None when nothing was saved for the symbol
+pub fn load(
+    dir: &Path,
+    symbol: &str,
+    fingerprint: Fingerprint,
+    max_age_seconds: u64,
+    now: DateTime<Utc>,
+) -> Result<Option<SavedState>, PersistError> {
+    let path = path(dir, symbol);
+    let json = match fs::read(&path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => return Err(PersistError::Read { path, source }),
+    };
+    let header: Header = serde_json::from_slice(&json).map_err(|source| PersistError::Parse { path: path.clone(), source })?;
+    if header.version != FORMAT_VERSION {
+        return Err(PersistError::Version { path, found: header.version, expected: FORMAT_VERSION });
+    }
+    let state: SavedState = serde_json::from_slice(&json).map_err(|source| PersistError::Parse { path: path.clone(), source })?;
+    let age_seconds = (now - state.saved_at).num_seconds();
+    if age_seconds > max_age_seconds as i64 {
+        return Err(PersistError::Expired { path, age_seconds, max_age_seconds });
+    }
+    if state.fingerprint != fingerprint || state.symbol != symbol {
+        return Err(PersistError::Incompatible {
+            path,
+            saved: format!("{} {:?}", state.symbol, state.fingerprint),
+            current: format!("{} {:?}", symbol, fingerprint),
+        });
+    }
+    Ok(Some(state))
+}
+
+// TESTS
+#[cfg(test)]
+use super::buffer::node;
+
+#[cfg(test)]
+fn saved_state(saved_at: DateTime<Utc>) -> SavedState {
+    let node = node(saved_at, 1., 42.);
+    SavedState {
+        version: FORMAT_VERSION,
+        symbol: "ETHUSDT".to_string(),
+        saved_at,
+        fingerprint: Fingerprint { market: Market::Usdm, contract_size: None, atr_window_seconds: 10, atr_moving_average: MovingAverage::Ema },
+        buffer: vec![node.clone()],
+        atr: SavedAtr { bars: vec![], smoothed: 3, smoothed_value: 0.5, last_close: Some(42.) },
+        volume: SavedVolume { increments: vec![(saved_at, 1.)], last: Some(node) },
+        open_interest: vec![OpenInterestSample { ts: saved_at, open_interest: 1000. }],
+    }
+}
+
+#[test]
+fn test_save_and_load() {
+    let dir = std::env::temp_dir().join(format!("whiplash-persist-{}", std::process::id()));
+    let now = Utc::now();
+    let state = saved_state(now - chrono::Duration::seconds(5));
+    let fingerprint = state.fingerprint;
+    save(&dir, &state).unwrap();
+
+    assert_eq!(load(&dir, "ETHUSDT", fingerprint, 30, now).unwrap(), Some(state.clone()));
+    assert!(load(&dir, "BTCUSDT", fingerprint, 30, now).unwrap().is_none());
+    assert!(matches!(load(&dir, "ETHUSDT", fingerprint, 2, now), Err(PersistError::Expired { age_seconds: 5, .. })));
+    let changed = Fingerprint { atr_window_seconds: 20, ..fingerprint };
+    assert!(matches!(load(&dir, "ETHUSDT", changed, 30, now), Err(PersistError::Incompatible { .. })));
+
+    // a different layout is rejected by its version alone
+    fs::write(path(&dir, "ETHUSDT"), r#"{"version": 0, "nodes": []}"#).unwrap();
+    assert!(matches!(load(&dir, "ETHUSDT", fingerprint, 30, now), Err(PersistError::Version { found: 0, expected: FORMAT_VERSION, .. })));
+    fs::remove_dir_all(&dir).unwrap();
+}
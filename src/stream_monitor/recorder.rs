@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::error::TransportError;
+
+// a single raw frame as received from the exchange, one json object per line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub symbol: String,
+    // local receive time in unix millis
+    pub recv_ts: i64,
+    pub data: String,
+}
+
+// cheap to clone handle, every symbol task gets its own copy
+// the file is flushed once the last handle is dropped
+#[derive(Clone)]
+pub struct Recorder {
+    tx: mpsc::UnboundedSender<RecordedFrame>,
+}
+
+impl Recorder {
+    // returns the handle and the writer task, the task resolves to the number of recorded frames
+    pub async fn create(path: &Path) -> Result<(Recorder, JoinHandle<Result<u64, TransportError>>), TransportError> {
+        let file = File::create(path).await.map_err(|source| TransportError::Recording { path: path.to_path_buf(), source })?;
+        let (tx, rx) = mpsc::unbounded_channel::<RecordedFrame>();
+        let writer = tokio::spawn(async move { write_frames(file, rx).await.map_err(TransportError::Recorder) });
+        Ok((Recorder { tx }, writer))
+    }
+
+    pub fn record(&self, symbol: &str, recv_ts: DateTime<Utc>, data: &str) {
+        // the writer only goes away once every handle is dropped
+        let _ = self.tx.send(RecordedFrame {
+            symbol: symbol.to_string(),
+            recv_ts: recv_ts.timestamp_millis(),
+            data: data.to_string(),
+        });
+    }
+}
+
+async fn write_frames(file: File, mut rx: mpsc::UnboundedReceiver<RecordedFrame>) -> io::Result<u64> {
+    let mut writer = BufWriter::new(file);
+    let mut frames = 0;
+    while let Some(frame) = rx.recv().await {
+        let mut line = serde_json::to_vec(&frame)?;
+        line.push(b'\n');
+        writer.write_all(&line).await?;
+        frames += 1;
+    }
+    writer.flush().await?;
+    Ok(frames)
+}
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use super::buffer::{BufferNode, BUFFER_SECONDS};
+
+// the `reference` config section, off unless enabled
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ReferenceConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // ingested even when it's not among the symbols
+    #[serde(default = "default_symbol")]
+    pub symbol: String,
+    // per-second returns the beta and the correlation are computed over, at most the buffer
+    #[serde(default = "default_window_seconds")]
+    pub window_seconds: usize,
+    // seconds with a return of both the symbol and the reference needed
+    #[serde(default = "default_min_samples")]
+    pub min_samples: usize,
+    // check the residual atr against `atr_threshold` instead of the atr
+    #[serde(default)]
+    pub use_residual: bool,
+}
+
+fn default_symbol() -> String {
+    "BTCUSDT".to_string()
+}
+
+fn default_window_seconds() -> usize {
+    BUFFER_SECONDS
+}
+
+fn default_min_samples() -> usize {
+    20
+}
+
+impl Default for ReferenceConfig {
+    fn default() -> Self {
+        ReferenceConfig {
+            enabled: false,
+            symbol: default_symbol(),
+            window_seconds: default_window_seconds(),
+            min_samples: default_min_samples(),
+            use_residual: false,
+        }
+    }
+}
+
+// (second, close) of the latest update within each second, oldest first
+pub type SecondCloses = VecDeque<(i64, f64)>;
+
+// rolling per-second closes over the reference window, updated once per node
+#[derive(Debug, Clone)]
+pub struct CloseSeries {
+    window_seconds: i64,
+    closes: SecondCloses,
+    // event time of the latest close
+    last_ts: Option<DateTime<Utc>>,
+}
+
+impl CloseSeries {
+    pub fn new(window_seconds: usize) -> Self {
+        CloseSeries { window_seconds: window_seconds as i64, closes: SecondCloses::new(), last_ts: None }
+    }
+
+    pub fn push(&mut self, node: &BufferNode) {
+        // a late node doesn't move the close of its second, a later one has set it already
+        if self.last_ts.is_some_and(|last_ts| node.ts < last_ts) {
+            return;
+        }
+        self.last_ts = Some(node.ts);
+        let second = node.ts.timestamp();
+        match self.closes.back_mut() {
+            Some((last_second, close)) if *last_second == second => *close = node.close_price,
+            _ => self.closes.push_back((second, node.close_price)),
+        }
+        let since = second - self.window_seconds;
+        while self.closes.front().is_some_and(|(first_second, _)| *first_second < since) {
+            self.closes.pop_front();
+        }
+    }
+
+    pub fn closes(&self) -> &SecondCloses {
+        &self.closes
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ReferenceStats {
+    pub beta: f64,
+    pub correlation: f64,
+    // the atr scaled down to the part of the moves the reference doesn't explain
+    pub residual_atr_pct: f64,
+    pub samples: usize,
+}
+
+// returns between the seconds both have a close for
+fn returns(symbol: &SecondCloses, reference: &SecondCloses) -> Vec<(f64, f64)> {
+    let mut pairs = vec![];
+    let mut reference = reference.iter().peekable();
+    let mut previous: Option<(f64, f64)> = None;
+    for &(second, close) in symbol {
+        while reference.next_if(|(reference_second, _)| *reference_second < second).is_some() {}
+        let Some(&&(_, reference_close)) = reference.peek().filter(|(reference_second, _)| *reference_second == second) else {
+            continue;
+        };
+        if let Some((previous_close, previous_reference)) = previous {
+            pairs.push((close / previous_close - 1., reference_close / previous_reference - 1.));
+        }
+        previous = Some((close, reference_close));
+    }
+    pairs
+}
+
+pub fn relate(symbol: &SecondCloses, reference: &SecondCloses, atr_pct: f64, min_samples: usize) -> Option<ReferenceStats> {
+    let pairs = returns(symbol, reference);
+    let n = pairs.len();
+    if n < min_samples.max(2) {
+        return None;
+    }
+    let mean = |f: fn(&(f64, f64)) -> f64| pairs.iter().map(f).sum::<f64>() / n as f64;
+    let (mean_s, mean_r) = (mean(|p| p.0), mean(|p| p.1));
+    let covariance = pairs.iter().map(|(s, r)| (s - mean_s) * (r - mean_r)).sum::<f64>();
+    let variance_s = pairs.iter().map(|(s, _)| (s - mean_s).powi(2)).sum::<f64>();
+    let variance_r = pairs.iter().map(|(_, r)| (r - mean_r).powi(2)).sum::<f64>();
+    // a flat reference explains nothing
+    let beta = if variance_r > 0. { covariance / variance_r } else { 0. };
+    let correlation = if variance_s > 0. && variance_r > 0. { covariance / (variance_s * variance_r).sqrt() } else { 0. };
+
+    let moved = pairs.iter().map(|(s, _)| s.abs()).sum::<f64>();
+    let residual = pairs.iter().map(|(s, r)| (s - beta * r).abs()).sum::<f64>();
+    let residual_atr_pct = if moved > 0. { atr_pct * residual / moved } else { 0. };
+    Some(ReferenceStats { beta, correlation, residual_atr_pct, samples: n })
+}
+
+// TESTS
+#[test]
+fn test_relate_to_reference() {
+    let reference: SecondCloses = (0..30).map(|i| (i, 100. * (1. + 0.01 * ((i % 4) as f64 - 1.5)))).collect();
+    // moves twice as much as the reference, and misses a second
+    let follower: SecondCloses = reference
+        .iter()
+        .filter(|(second, _)| *second != 7)
+        .map(|(second, close)| (*second, 50. * (1. + 2. * (close / 100. - 1.))))
+        .collect();
+
+    let stats = relate(&follower, &reference, 0.5, 20).unwrap();
+    assert_eq!(stats.samples, 28);
+    assert!(stats.beta > 1.9 && stats.beta < 2.1, "beta {}", stats.beta);
+    assert!(stats.correlation > 0.99);
+    // hardly anything left once the reference is accounted for
+    assert!(stats.residual_atr_pct < 0.05, "residual {}", stats.residual_atr_pct);
+
+    let short: SecondCloses = follower.iter().take(10).copied().collect();
+    assert_eq!(relate(&short, &reference, 0.5, 20), None);
+}
+
+#[test]
+fn test_close_series() {
+    use super::buffer::node;
+    let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let mut series = CloseSeries::new(2);
+    for (ms, close) in [(0, 10.), (500, 11.), (1000, 12.), (2250, 13.), (2000, 99.), (3500, 14.)] {
+        series.push(&node(start + chrono::Duration::milliseconds(ms), 0., close));
+    }
+    // the late node is left out, the first second fell out of the window
+    let second = start.timestamp();
+    assert_eq!(series.closes(), &SecondCloses::from([(second + 1, 12.), (second + 2, 13.), (second + 3, 14.)]));
+}
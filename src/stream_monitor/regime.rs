@@ -0,0 +1,193 @@
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use super::evaluation::Reason;
+use super::hub::Hub;
+use super::snapshot::SymbolSnapshot;
+
+// whether a move is shared by the market or the symbol's own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegimeTag {
+    MarketWide,
+    Idiosyncratic,
+}
+
+impl RegimeTag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RegimeTag::MarketWide => "market_wide",
+            RegimeTag::Idiosyncratic => "idiosyncratic",
+        }
+    }
+}
+
+// the `regime` config section
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct RegimeConfig {
+    // share of the other symbols over their atr threshold that makes a move market-wide
+    #[serde(default = "default_breadth_threshold")]
+    pub breadth_threshold: f64,
+    // fewer other symbols with data than this and every move is idiosyncratic
+    #[serde(default = "default_min_symbols")]
+    pub min_symbols: usize,
+    // signals of this class are held back
+    #[serde(default)]
+    pub suppress: Option<RegimeTag>,
+}
+
+fn default_breadth_threshold() -> f64 {
+    0.5
+}
+
+fn default_min_symbols() -> usize {
+    3
+}
+
+impl Default for RegimeConfig {
+    fn default() -> Self {
+        RegimeConfig { breadth_threshold: default_breadth_threshold(), min_symbols: default_min_symbols(), suppress: None }
+    }
+}
+
+impl RegimeConfig {
+    pub fn check(&self, tag: RegimeTag) -> Option<Reason> {
+        match (self.suppress, tag) {
+            (Some(RegimeTag::MarketWide), RegimeTag::MarketWide) => Some(Reason::MarketWideMove),
+            (Some(RegimeTag::Idiosyncratic), RegimeTag::Idiosyncratic) => Some(Reason::IdiosyncraticMove),
+            _ => None,
+        }
+    }
+}
+
+// where a symbol with fresh data stands
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Pulse {
+    pub symbol: String,
+    // atr over the atr threshold, over 1 is an impulse
+    pub atr_ratio: f64,
+    pub return_pct: f64,
+}
+
+impl Pulse {
+    pub fn is_impulse(&self) -> bool {
+        self.atr_ratio > 1.
+    }
+}
+
+// market-wide state, as of the latest snapshot of every symbol
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MarketRegime {
+    pub ts: Option<DateTime<Utc>>,
+    // signalling symbols that are warm and not stale
+    pub pulses: Vec<Pulse>,
+    // share of those over their atr threshold
+    pub breadth: f64,
+    // impulses by direction of the return
+    pub up: usize,
+    pub down: usize,
+    pub mean_return_pct: f64,
+}
+
+impl MarketRegime {
+    pub fn from_snapshots(snapshots: &[SymbolSnapshot]) -> Self {
+        let pulses: Vec<Pulse> = snapshots
+            .iter()
+            .filter(|snapshot| !snapshot.reference_only)
+            .filter(|snapshot| !matches!(snapshot.evaluation.reason, Reason::WarmingUp | Reason::StaleData))
+            .filter(|snapshot| snapshot.evaluation.atr_threshold > 0.)
+            .map(|snapshot| Pulse {
+                symbol: snapshot.symbol.clone(),
+                atr_ratio: snapshot.evaluation.atr_pct / snapshot.evaluation.atr_threshold,
+                return_pct: snapshot.evaluation.return_pct,
+            })
+            .collect();
+        let impulses = || pulses.iter().filter(|pulse| pulse.is_impulse());
+        let count = pulses.len().max(1) as f64;
+        MarketRegime {
+            ts: snapshots.iter().filter_map(|snapshot| snapshot.ts).max(),
+            breadth: impulses().count() as f64 / count,
+            up: impulses().filter(|pulse| pulse.return_pct > 0.).count(),
+            down: impulses().filter(|pulse| pulse.return_pct < 0.).count(),
+            mean_return_pct: pulses.iter().map(|pulse| pulse.return_pct).sum::<f64>() / count,
+            pulses,
+        }
+    }
+
+    // breadth among the other symbols, so that a lone move doesn't count itself
+    pub fn tag(&self, symbol: &str, config: &RegimeConfig) -> (RegimeTag, f64) {
+        let others: Vec<&Pulse> = self.pulses.iter().filter(|pulse| pulse.symbol != symbol).collect();
+        if others.is_empty() {
+            return (RegimeTag::Idiosyncratic, 0.);
+        }
+        let breadth = others.iter().filter(|pulse| pulse.is_impulse()).count() as f64 / others.len() as f64;
+        if others.len() >= config.min_symbols && breadth >= config.breadth_threshold {
+            (RegimeTag::MarketWide, breadth)
+        } else {
+            (RegimeTag::Idiosyncratic, breadth)
+        }
+    }
+}
+
+// recomputes the regime whenever a symbol publishes, until cancelled
+// bursts of snapshots are coalesced, the regime is as of the latest ones
+pub async fn aggregate(hub: Hub, config: RegimeConfig, cancel: CancellationToken) {
+    let mut wide = false;
+    loop {
+        tokio::select! {
+            _ = hub.updated() => {},
+            _ = cancel.cancelled() => return,
+        }
+        let regime = MarketRegime::from_snapshots(&hub.snapshots());
+        // a market-wide move is logged once, rather than by every symbol
+        let now_wide = regime.pulses.len() >= config.min_symbols && regime.breadth >= config.breadth_threshold;
+        if now_wide != wide {
+            let impulses = regime.up + regime.down;
+            match now_wide {
+                true => info!(
+                    breadth = regime.breadth, up = regime.up, down = regime.down;
+                    "market-wide impulse, {} of {} symbols over their atr threshold, {} up, {} down",
+                    impulses, regime.pulses.len(), regime.up, regime.down
+                ),
+                false => info!(breadth = regime.breadth; "market-wide impulse is over, breadth {:.0}%", regime.breadth * 100.),
+            }
+            wide = now_wide;
+        }
+        hub.publish_regime(regime);
+    }
+}
+
+// TESTS
+#[cfg(test)]
+fn snapshot(symbol: &str, atr_pct: f64, return_pct: f64) -> SymbolSnapshot {
+    let mut snapshot = SymbolSnapshot { symbol: symbol.to_string(), ..Default::default() };
+    snapshot.evaluation.reason = Reason::BelowThreshold;
+    snapshot.evaluation.atr_pct = atr_pct;
+    snapshot.evaluation.atr_threshold = 0.2;
+    snapshot.evaluation.return_pct = return_pct;
+    snapshot
+}
+
+#[test]
+fn test_market_regime() {
+    let mut snapshots = vec![
+        snapshot("BTCUSDT", 0.5, -1.),
+        snapshot("ETHUSDT", 0.4, -1.5),
+        snapshot("SOLUSDT", 0.3, -2.),
+        snapshot("XRPUSDT", 0.1, 0.1),
+    ];
+    snapshots.push(SymbolSnapshot { symbol: "DOGEUSDT".to_string(), ..Default::default() });
+    let regime = MarketRegime::from_snapshots(&snapshots);
+    // the warming up symbol doesn't count
+    assert_eq!(regime.pulses.len(), 4);
+    assert_eq!((regime.breadth, regime.up, regime.down), (0.75, 0, 3));
+
+    let config = RegimeConfig::default();
+    assert_eq!(regime.tag("ETHUSDT", &config).0, RegimeTag::MarketWide);
+    // a quiet symbol is still tagged by what the others do
+    assert_eq!(regime.tag("XRPUSDT", &config), (RegimeTag::MarketWide, 1.));
+
+    let lone = MarketRegime::from_snapshots(&[snapshot("BTCUSDT", 0.1, 0.), snapshot("ETHUSDT", 0.1, 0.), snapshot("SOLUSDT", 0.1, 0.), snapshot("XRPUSDT", 0.5, 3.)]);
+    assert_eq!(lone.tag("XRPUSDT", &config), (RegimeTag::Idiosyncratic, 0.));
+}
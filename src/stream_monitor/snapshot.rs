@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use super::buffer::{Anomalies, Bar, BufferHealth};
+use super::evaluation::Evaluation;
+use super::latency::LatencyStats;
+use super::market::Market;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignalState {
+    #[default]
+    Idle,
+    Triggered,
+    // would be triggered, but the feed is over the latency limits
+    Suppressed,
+    // would be triggered, but signalling is paused
+    Paused,
+}
+
+impl SignalState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignalState::Idle => "idle",
+            SignalState::Triggered => "triggered",
+            SignalState::Suppressed => "suppressed",
+            SignalState::Paused => "paused",
+        }
+    }
+}
+
+// latest evaluated state of a symbol, published by the owner task on every tick
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SymbolSnapshot {
+    pub symbol: String,
+    pub market: Market,
+    // event time of the latest node
+    pub ts: Option<DateTime<Utc>>,
+    pub close_price: f64,
+    // the outcome of the latest evaluation and the numbers behind it
+    pub evaluation: Evaluation,
+    pub state: SignalState,
+    // signalling is paused, e.g. over the control api
+    pub paused: bool,
+    // only ingested as the reference of the other symbols, never signals
+    pub reference_only: bool,
+    pub messages: u64,
+    // messages that arrived out of order, twice or too late
+    pub anomalies: Anomalies,
+    pub buffer: BufferHealth,
+    pub signals: u64,
+    pub reconnects: u64,
+    // local receive time of the latest kline
+    pub received_at: Option<DateTime<Utc>>,
+    // the seconds in the buffer, oldest first, only while the bars are watched, see `Hub::watch_bars`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bars: Vec<Bar>,
+    // from the receipt of the latest message to the latest decision
+    pub eval_latency_ms: Option<f64>,
+    // feed latency and clock skew, once there's been a message
+    pub latency: Option<LatencyStats>,
+    // what's over the latency limits, if anything
+    pub latency_problem: Option<String>,
+}
+
+// emitted whenever a symbol passes both the atr and the volume condition
+#[derive(Debug, Clone, Serialize)]
+pub struct Signal {
+    pub symbol: String,
+    pub market: Market,
+    pub ts: DateTime<Utc>,
+    pub close_price: f64,
+    pub evaluation: Evaluation,
+    pub eval_latency_ms: Option<f64>,
+    // average exchange to receipt latency of the recent messages
+    pub feed_latency_ms: Option<f64>,
+}
@@ -0,0 +1,283 @@
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use std::path::PathBuf;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message};
+use tokio_util::sync::CancellationToken;
+use super::buffer::BufferNode;
+use super::{Received, Update};
+use super::event::{BookTickerEvent, Combined, DepthEvent, Event, ForceOrderEvent, MarkPriceEvent};
+use super::liquidation::{Liquidation, ALL_FORCE_ORDERS_STREAM, FORCE_ORDER_STREAM};
+use super::mark_price::{MarkPrice, MARK_PRICE_STREAM};
+use super::orderbook::{BookTicker, Depth, BOOK_TICKER_STREAM, DEPTH_STREAM};
+use super::recorder::{RecordedFrame, Recorder};
+use crate::error::{DecodeError, TransportError};
+
+static STREAM_TYPE: &str = "kline_1m";
+// how long to wait for the exchange to acknowledge our close frame
+const CLOSE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+// reconnect backoff, doubled after every failed attempt
+const RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+// a connection that lasted this long starts the backoff over
+const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+
+// where the raw frames of a symbol come from
+#[derive(Debug, Clone)]
+pub enum Source {
+    // binance websocket, optionally recording every frame
+    Live,
+    // a file written by the recorder, replayed at `speed` times the recorded pace,
+    // or as fast as possible when there's no speed
+    Replay { path: PathBuf, speed: Option<f64> },
+}
+
+// what a reader needs to know about its symbol
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub symbol: String,
+    pub stream_url: String,
+    pub contract_size: Option<f64>,
+    // streams on top of the klines, e.g. the order book ones, all-market streams start with `!`
+    pub streams: Vec<&'static str>,
+}
+
+impl Source {
+    // live sources are evaluated on the wall clock, recordings on the event time
+    pub fn is_live(&self) -> bool {
+        matches!(self, Source::Live)
+    }
+}
+
+impl Feed {
+    // a single stream is opened as a raw one, several are combined into one connection
+    fn url(&self) -> String {
+        let base = self.stream_url.trim_end_matches('/');
+        let symbol = self.symbol.to_lowercase();
+        if self.streams.is_empty() {
+            return format!("{}/{}@{}", base, symbol, STREAM_TYPE);
+        }
+        let streams: Vec<String> = std::iter::once(STREAM_TYPE)
+            .chain(self.streams.iter().copied())
+            .map(|stream| if stream.starts_with('!') { stream.to_string() } else { format!("{}@{}", symbol, stream) })
+            .collect();
+        format!("{}/stream?streams={}", base.trim_end_matches("/ws"), streams.join("/"))
+    }
+}
+
+fn decode_kline(text: &str, recv_ts: DateTime<Utc>, contract_size: Option<f64>) -> Result<Option<Update>, DecodeError> {
+    let event = serde_json::from_str::<Event>(text)?;
+    debug!("Received and parsed message: {:?}", event);
+    Ok(Some(Update::Kline(BufferNode::from_kline_event(&event, recv_ts, contract_size)?)))
+}
+
+// combined frames are told apart by the stream name, raw frames are always klines
+// None for the frames of the all-market streams that are about other symbols
+fn decode(feed: &Feed, text: &str, recv_ts: DateTime<Utc>) -> Result<Option<Update>, DecodeError> {
+    if feed.streams.is_empty() {
+        return decode_kline(text, recv_ts, feed.contract_size);
+    }
+    let combined = serde_json::from_str::<Combined>(text)?;
+    let data = combined.data.get();
+    // symbol streams are named `<symbol>@<stream>`, the all-market ones go by their full name
+    let stream = match combined.stream.split_once('@') {
+        Some((_, stream)) if !combined.stream.starts_with('!') => stream,
+        _ => combined.stream,
+    };
+    match stream {
+        stream if stream == STREAM_TYPE => decode_kline(data, recv_ts, feed.contract_size),
+        stream if stream == DEPTH_STREAM => {
+            let event = serde_json::from_str::<DepthEvent>(data)?;
+            Ok(Some(Update::Depth(Depth::from_event(&event, recv_ts)?)))
+        }
+        stream if stream == BOOK_TICKER_STREAM => {
+            let event = serde_json::from_str::<BookTickerEvent>(data)?;
+            Ok(Some(Update::BookTicker(BookTicker::from_event(&event, recv_ts)?)))
+        }
+        stream if stream == FORCE_ORDER_STREAM || stream == ALL_FORCE_ORDERS_STREAM => {
+            let event = serde_json::from_str::<ForceOrderEvent>(data)?;
+            if event.o.s != feed.symbol {
+                return Ok(None);
+            }
+            Ok(Some(Update::Liquidation(Liquidation::from_event(&event, feed.contract_size)?)))
+        }
+        stream if stream == MARK_PRICE_STREAM => {
+            let event = serde_json::from_str::<MarkPriceEvent>(data)?;
+            Ok(Some(Update::MarkPrice(MarkPrice::from_event(&event)?)))
+        }
+        _ => Err(DecodeError::Stream(combined.stream.to_string())),
+    }
+}
+
+// parses a raw frame and forwards the update to the owner, `recv_ts` is when the frame reached us
+// a frame that can't be decoded is only logged, returns false once the owner is gone
+async fn forward(feed: &Feed, text: &str, recv_ts: DateTime<Utc>, tx: &mpsc::Sender<Received>) -> bool {
+    let at = Instant::now();
+    let symbol = feed.symbol.as_str();
+    debug!("message received: {:?}", text);
+    match decode(feed, text, recv_ts) {
+        Ok(Some(update)) => {
+            debug!("forwarding update: {:?}", update);
+            // the owner is gone, nobody is interested anymore
+            tx.send(Received { update, at }).await.is_ok()
+        }
+        Ok(None) => true,
+        Err(e) => {
+            error!(symbol = symbol; "skipping frame for {}: {}", symbol, e);
+            true
+        }
+    }
+}
+
+// streams the symbol until cancelled, reconnecting with a backoff after every retryable failure
+pub async fn read_websocket(
+    feed: Feed,
+    tx: mpsc::Sender<Received>,
+    cancel: CancellationToken,
+    recorder: Option<Recorder>,
+) -> Result<(), TransportError> {
+    let symbol = feed.symbol.as_str();
+    let url = feed.url();
+    let mut delay = RECONNECT_MIN_DELAY;
+
+    loop {
+        let started = Instant::now();
+        let error = match stream(&feed, &url, &tx, &cancel, recorder.as_ref()).await {
+            // cancelled, or the owner is gone
+            Ok(()) => return Ok(()),
+            Err(e) if e.is_retryable() => e,
+            Err(e) => return Err(e),
+        };
+        if started.elapsed() >= STABLE_CONNECTION {
+            delay = RECONNECT_MIN_DELAY;
+        }
+        warn!(symbol = symbol; "stream for {} failed: {}, reconnecting in {:?}", symbol, error, delay);
+        if tx.send(Received { update: Update::Reconnecting, at: Instant::now() }).await.is_err() {
+            // the owner is gone
+            return Ok(());
+        }
+        tokio::select! {
+            _ = sleep(delay) => {},
+            _ = cancel.cancelled() => return Ok(()),
+        }
+        delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+    }
+}
+
+// a single connection, only returns Ok once there's no point in reconnecting
+async fn stream(
+    feed: &Feed,
+    url: &str,
+    tx: &mpsc::Sender<Received>,
+    cancel: &CancellationToken,
+    recorder: Option<&Recorder>,
+) -> Result<(), TransportError> {
+    let symbol = feed.symbol.as_str();
+    info!(symbol = symbol; "connecting to websocket at {}", url);
+
+    // init connection now, unless we're already shutting down
+    let (ws_stream, _) = tokio::select! {
+        connection = connect_async(url) => connection.map_err(|e| TransportError::Connect { url: url.to_string(), source: Box::new(e) })?,
+        _ = cancel.cancelled() => return Ok(()),
+    };
+    debug!("connection successful");
+
+    // split the stream, the sender is only needed to close the connection
+    let (mut write, mut read) = ws_stream.split();
+
+    loop {
+        let message = tokio::select! {
+            message = read.next() => message,
+            _ = cancel.cancelled() => {
+                debug!(symbol = symbol; "closing websocket for {}", symbol);
+                let frame = CloseFrame { code: CloseCode::Normal, reason: "shutting down".into() };
+                if let Err(e) = write.send(Message::Close(Some(frame))).await {
+                    error!(symbol = symbol; "failed to send close frame for {}: {:?}", symbol, e);
+                    return Ok(());
+                }
+                // wait for the close to be acknowledged, dropping whatever arrives meanwhile
+                let _ = timeout(CLOSE_HANDSHAKE_TIMEOUT, async {
+                    while let Some(Ok(_)) = read.next().await {}
+                }).await;
+                return Ok(());
+            }
+        };
+        match message {
+            Some(Ok(Message::Text(text))) => {
+                let recv_ts = Utc::now();
+                if let Some(recorder) = recorder {
+                    recorder.record(symbol, recv_ts, &text);
+                }
+                if !forward(feed, &text, recv_ts, tx).await {
+                    return Ok(());
+                }
+            }
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(TransportError::Stream(Box::new(e))),
+            // binance drops every connection after 24 hours
+            None => return Err(TransportError::Closed),
+        }
+    }
+}
+
+pub async fn read_recording(
+    feed: Feed,
+    path: PathBuf,
+    speed: Option<f64>,
+    tx: mpsc::Sender<Received>,
+    cancel: CancellationToken,
+) -> Result<(), TransportError> {
+    let symbol = feed.symbol.as_str();
+    info!(symbol = symbol; "replaying {} from {:?}", symbol, path);
+    let recording_error = |source| TransportError::Recording { path: path.clone(), source };
+    let file = File::open(&path).await.map_err(recording_error)?;
+    let mut lines = BufReader::new(file).lines();
+    let mut previous_ts: Option<i64> = None;
+    let mut frames = 0;
+
+    while let Some(line) = lines.next_line().await.map_err(recording_error)? {
+        if cancel.is_cancelled() {
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let frame: RecordedFrame = match serde_json::from_str(&line) {
+            Ok(frame) => frame,
+            Err(e) => {
+                error!("skipping malformed recorded frame: {}", DecodeError::from(e));
+                continue;
+            }
+        };
+        if frame.symbol != symbol {
+            continue;
+        }
+        // keep the recorded pace between the frames
+        if let (Some(speed), Some(previous_ts)) = (speed, previous_ts) {
+            let gap_ms = (frame.recv_ts - previous_ts).max(0) as f64 / speed;
+            tokio::select! {
+                _ = sleep(Duration::from_micros((gap_ms * 1000.) as u64)) => {},
+                _ = cancel.cancelled() => break,
+            }
+        }
+        previous_ts = Some(frame.recv_ts);
+        frames += 1;
+        // latency is measured against the recorded receive time
+        let Some(recv_ts) = DateTime::from_timestamp_millis(frame.recv_ts) else {
+            error!("skipping recorded frame: {}", DecodeError::Timestamp(frame.recv_ts));
+            continue;
+        };
+        if !forward(&feed, &frame.data, recv_ts, &tx).await {
+            break;
+        }
+    }
+
+    info!(symbol = symbol, frames = frames; "replay for {} finished after {} frames", symbol, frames);
+    Ok(())
+}
@@ -0,0 +1,389 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io;
+use chrono::{DateTime, Utc};
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures_util::StreamExt;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval, Duration, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use crate::stream_monitor::hub::Hub;
+use crate::stream_monitor::snapshot::{SignalState, SymbolSnapshot};
+
+// how often the table is redrawn from the hub
+const REFRESH: Duration = Duration::from_millis(250);
+// a symbol stays highlighted this long after its latest signal
+const SIGNAL_HIGHLIGHT_SECONDS: i64 = 10;
+// seconds of closes behind the price change column
+const CHANGE_SECONDS: usize = 30;
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// the columns of the symbol table, each one sorts the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Column {
+    #[default]
+    Symbol,
+    Atr,
+    AtrPct,
+    Volume,
+    Change,
+    State,
+    Age,
+    Reconnects,
+}
+
+const COLUMNS: [Column; 8] = [
+    Column::Symbol,
+    Column::Atr,
+    Column::AtrPct,
+    Column::Volume,
+    Column::Change,
+    Column::State,
+    Column::Age,
+    Column::Reconnects,
+];
+
+impl Column {
+    fn title(self) -> &'static str {
+        match self {
+            Column::Symbol => "symbol",
+            Column::Atr => "atr",
+            Column::AtrPct => "atr %",
+            Column::Volume => "volume",
+            Column::Change => "change",
+            Column::State => "state",
+            Column::Age => "age",
+            Column::Reconnects => "reconnects",
+        }
+    }
+
+    fn width(self) -> Constraint {
+        match self {
+            Column::Symbol => Constraint::Length(14),
+            Column::Change => Constraint::Length(CHANGE_SECONDS as u16 + 10),
+            Column::Reconnects => Constraint::Length(10),
+            _ => Constraint::Length(12),
+        }
+    }
+
+    fn next(self, step: isize) -> Column {
+        let index = COLUMNS.iter().position(|column| *column == self).unwrap_or_default() as isize;
+        COLUMNS[(index + step).rem_euclid(COLUMNS.len() as isize) as usize]
+    }
+}
+
+// closes of the latest CHANGE_SECONDS, oldest first
+fn recent_closes(snapshot: &SymbolSnapshot) -> Vec<f64> {
+    let skip = snapshot.bars.len().saturating_sub(CHANGE_SECONDS);
+    snapshot.bars[skip..].iter().map(|bar| bar.close).collect()
+}
+
+// price change over the closes of the sparkline
+fn change_pct(snapshot: &SymbolSnapshot) -> Option<f64> {
+    let closes = recent_closes(snapshot);
+    match (closes.first(), closes.last()) {
+        (Some(first), Some(last)) if *first > 0. => Some((last - first) / first * 100.),
+        _ => None,
+    }
+}
+
+// one block per value, scaled between the lowest and the highest one
+pub fn sparkline(values: &[f64]) -> String {
+    let low = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let high = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|value| match high - low {
+            range if range > 0. => SPARKS[((value - low) / range * (SPARKS.len() - 1) as f64).round() as usize],
+            _ => SPARKS[0],
+        })
+        .collect()
+}
+
+// since the latest kline arrived
+fn age(snapshot: &SymbolSnapshot, now: DateTime<Utc>) -> Option<chrono::Duration> {
+    snapshot.received_at.map(|at| now - at)
+}
+
+fn format_age(age: Option<chrono::Duration>) -> String {
+    match age.map(|age| age.num_milliseconds().max(0)) {
+        None => "-".to_string(),
+        Some(ms) if ms < 10_000 => format!("{:.1}s", ms as f64 / 1000.),
+        Some(ms) if ms < 120_000 => format!("{}s", ms / 1000),
+        Some(ms) => format!("{}m", ms / 60_000),
+    }
+}
+
+// what's on the screen, the snapshots come from the hub on every redraw
+#[derive(Debug, Default)]
+pub struct Dashboard {
+    pub sort: Column,
+    pub descending: bool,
+    // followed by name, so that resorting doesn't move the selection to another symbol
+    pub selected: Option<String>,
+    // the symbol whose bars are shown
+    pub detail: Option<String>,
+    // when each symbol signalled last
+    pub signalled: HashMap<String, DateTime<Utc>>,
+    pub quit: bool,
+}
+
+impl Dashboard {
+    // the snapshots in the order of the sort column, ties by symbol
+    pub fn rows(&self, mut snapshots: Vec<SymbolSnapshot>, now: DateTime<Utc>) -> Vec<SymbolSnapshot> {
+        let number = |a: Option<f64>, b: Option<f64>| a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+        snapshots.sort_by(|a, b| {
+            let ordering = match self.sort {
+                Column::Symbol => Ordering::Equal,
+                Column::Atr => number(Some(a.evaluation.atr), Some(b.evaluation.atr)),
+                Column::AtrPct => number(Some(a.evaluation.atr_pct), Some(b.evaluation.atr_pct)),
+                Column::Volume => number(Some(a.evaluation.vol_usdt), Some(b.evaluation.vol_usdt)),
+                Column::Change => number(change_pct(a), change_pct(b)),
+                Column::State => a.state.as_str().cmp(b.state.as_str()),
+                Column::Age => age(a, now).cmp(&age(b, now)),
+                Column::Reconnects => a.reconnects.cmp(&b.reconnects),
+            };
+            ordering.then_with(|| a.symbol.cmp(&b.symbol))
+        });
+        if self.descending {
+            snapshots.reverse();
+        }
+        snapshots
+    }
+
+    fn selected_index(&self, rows: &[SymbolSnapshot]) -> Option<usize> {
+        let selected = self.selected.as_ref()?;
+        rows.iter().position(|row| row.symbol == *selected)
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent, rows: &[SymbolSnapshot]) {
+        let step = |dashboard: &mut Dashboard, step: isize| {
+            if rows.is_empty() {
+                return;
+            }
+            let index = match dashboard.selected_index(rows) {
+                Some(index) => (index as isize + step).clamp(0, rows.len() as isize - 1) as usize,
+                None => 0,
+            };
+            dashboard.selected = Some(rows[index].symbol.clone());
+        };
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Esc => self.detail = None,
+            KeyCode::Up | KeyCode::Char('k') => step(self, -1),
+            KeyCode::Down | KeyCode::Char('j') => step(self, 1),
+            KeyCode::Left | KeyCode::BackTab => self.sort = self.sort.next(-1),
+            KeyCode::Right | KeyCode::Tab | KeyCode::Char('s') => self.sort = self.sort.next(1),
+            KeyCode::Char('r') => self.descending = !self.descending,
+            KeyCode::Enter => {
+                self.detail = match &self.detail {
+                    Some(shown) if self.selected.as_ref() == Some(shown) => None,
+                    _ => self.selected.clone(),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn row_style(&self, snapshot: &SymbolSnapshot, now: DateTime<Utc>) -> Style {
+        let recent = self.signalled.get(&snapshot.symbol).is_some_and(|at| (now - *at).num_seconds() < SIGNAL_HIGHLIGHT_SECONDS);
+        match snapshot.state {
+            SignalState::Triggered => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            _ if recent => Style::default().fg(Color::Red),
+            SignalState::Suppressed | SignalState::Paused => Style::default().fg(Color::Yellow),
+            SignalState::Idle => Style::default(),
+        }
+    }
+
+    pub fn draw(&self, frame: &mut Frame, rows: &[SymbolSnapshot], now: DateTime<Utc>) {
+        let detail = self.detail.as_ref().and_then(|symbol| rows.iter().find(|row| row.symbol == *symbol));
+        let detail_height = if detail.is_some() { Constraint::Percentage(50) } else { Constraint::Length(0) };
+        let [table_area, detail_area, help_area] =
+            Layout::vertical([Constraint::Min(3), detail_height, Constraint::Length(1)]).areas(frame.area());
+
+        let header = Row::new(COLUMNS.iter().map(|column| {
+            let arrow = match (*column == self.sort, self.descending) {
+                (false, _) => "",
+                (true, false) => " ▲",
+                (true, true) => " ▼",
+            };
+            Cell::from(format!("{}{}", column.title(), arrow))
+        }))
+        .style(Style::default().add_modifier(Modifier::BOLD));
+        let table_rows = rows.iter().map(|snapshot| {
+            let e = &snapshot.evaluation;
+            let change = match change_pct(snapshot) {
+                Some(change) => format!("{} {:+.2}%", sparkline(&recent_closes(snapshot)), change),
+                None => "-".to_string(),
+            };
+            Row::new([
+                snapshot.symbol.clone(),
+                format!("{:.4}", e.atr),
+                format!("{:.3}", e.atr_pct),
+                format!("{:.0}", e.vol_usdt),
+                change,
+                snapshot.state.as_str().to_string(),
+                format_age(age(snapshot, now)),
+                snapshot.reconnects.to_string(),
+            ])
+            .style(self.row_style(snapshot, now))
+        });
+        let table = Table::new(table_rows, COLUMNS.map(Column::width))
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(format!("whiplash, {} symbol(s)", rows.len())))
+            .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = TableState::default().with_selected(self.selected_index(rows));
+        frame.render_stateful_widget(table, table_area, &mut state);
+
+        if let Some(snapshot) = detail {
+            draw_bars(frame, snapshot, detail_area);
+        }
+
+        let help = "↑↓ select  ←→ sort  r reverse  enter bars  esc close  q quit";
+        frame.render_widget(Paragraph::new(help).style(Style::default().fg(Color::DarkGray)), help_area);
+    }
+}
+
+// the recent per-second bars of a symbol, newest first
+fn draw_bars(frame: &mut Frame, snapshot: &SymbolSnapshot, area: ratatui::layout::Rect) {
+    let header = Row::new(["time", "open", "high", "low", "close", "change %", "volume"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let bars = snapshot.bars.iter().rev().zip(snapshot.bars.iter().rev().skip(1).map(Some).chain([None]));
+    let rows = bars.map(|(bar, previous)| {
+        let change = previous.filter(|previous| previous.close > 0.).map(|previous| (bar.close - previous.close) / previous.close * 100.);
+        let style = match change {
+            Some(change) if change > 0. => Style::default().fg(Color::Green),
+            Some(change) if change < 0. => Style::default().fg(Color::Red),
+            _ => Style::default(),
+        };
+        Row::new([
+            bar.ts.format("%H:%M:%S").to_string(),
+            format!("{:.4}", bar.open),
+            format!("{:.4}", bar.high),
+            format!("{:.4}", bar.low),
+            format!("{:.4}", bar.close),
+            change.map(|change| format!("{:+.3}", change)).unwrap_or_default(),
+            format!("{:.0}", bar.volume),
+        ])
+        .style(style)
+    });
+    let table = Table::new(rows, [Constraint::Length(10); 7])
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(Line::from(format!("{}: {}", snapshot.symbol, snapshot.evaluation))));
+    frame.render_widget(table, area);
+}
+
+// draws the hub until `q` or cancelled, then cancels `cancel` so that the monitoring stops along with it
+pub async fn run(hub: Hub, cancel: CancellationToken) -> io::Result<()> {
+    let result = match ratatui::try_init() {
+        Ok(mut terminal) => {
+            let result = show(&mut terminal, &hub, &cancel).await;
+            ratatui::restore();
+            result
+        }
+        // e.g. not a terminal
+        Err(e) => Err(e),
+    };
+    cancel.cancel();
+    result
+}
+
+async fn show(terminal: &mut DefaultTerminal, hub: &Hub, cancel: &CancellationToken) -> io::Result<()> {
+    let mut dashboard = Dashboard::default();
+    // the sparklines and the detail pane are drawn from the bars
+    let _bars = hub.watch_bars();
+    let mut events = EventStream::new();
+    let mut signals = hub.subscribe_signals();
+    let mut refresh = interval(REFRESH);
+    refresh.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut rows = vec![];
+    loop {
+        tokio::select! {
+            _ = refresh.tick() => {}
+            _ = cancel.cancelled() => return Ok(()),
+            received = signals.recv() => match received {
+                Ok(signal) => {
+                    dashboard.signalled.insert(signal.symbol, Utc::now());
+                }
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            event = events.next() => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => dashboard.handle_key(key, &rows),
+                // a resize is redrawn below
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e),
+                None => return Ok(()),
+            },
+        }
+        if dashboard.quit {
+            return Ok(());
+        }
+        let now = Utc::now();
+        rows = dashboard.rows(hub.snapshots(), now);
+        if dashboard.selected.is_none() {
+            dashboard.selected = rows.first().map(|row| row.symbol.clone());
+        }
+        terminal.draw(|frame| dashboard.draw(frame, &rows, now))?;
+    }
+}
+
+// TESTS
+#[test]
+fn test_sparkline() {
+    assert_eq!(sparkline(&[1., 2., 3., 4., 5., 6., 7., 8.]), "▁▂▃▄▅▆▇█");
+    assert_eq!(sparkline(&[5., 5.]), "▁▁");
+    assert_eq!(sparkline(&[]), "");
+}
+
+#[test]
+fn test_dashboard_sorts_and_draws() {
+    use crate::stream_monitor::buffer::Bar;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    let now = Utc::now();
+    let bar = |close: f64| Bar { ts: now, open: close, high: close, low: close, close, volume: 1. };
+    let snapshot = |symbol: &str, atr: f64, state: SignalState, closes: &[f64]| {
+        let mut snapshot = SymbolSnapshot { symbol: symbol.to_string(), state, ..Default::default() };
+        snapshot.evaluation.atr = atr;
+        snapshot.bars = closes.iter().map(|close| bar(*close)).collect();
+        snapshot
+    };
+    let snapshots = vec![
+        snapshot("ETHUSDT", 2., SignalState::Triggered, &[100., 110.]),
+        snapshot("BTCUSDT", 1., SignalState::Idle, &[100., 90.]),
+        snapshot("SOLUSDT", 3., SignalState::Idle, &[]),
+    ];
+
+    let mut dashboard = Dashboard::default();
+    let symbols = |rows: &[SymbolSnapshot]| rows.iter().map(|row| row.symbol.clone()).collect::<Vec<_>>();
+    assert_eq!(symbols(&dashboard.rows(snapshots.clone(), now)), vec!["BTCUSDT", "ETHUSDT", "SOLUSDT"]);
+    dashboard.sort = Column::Atr;
+    dashboard.descending = true;
+    let rows = dashboard.rows(snapshots.clone(), now);
+    assert_eq!(symbols(&rows), vec!["SOLUSDT", "ETHUSDT", "BTCUSDT"]);
+    dashboard.sort = Column::Change;
+    assert_eq!(symbols(&dashboard.rows(snapshots, now)), vec!["ETHUSDT", "BTCUSDT", "SOLUSDT"]);
+
+    // the selection follows the symbol, enter opens its bars
+    dashboard.selected = Some("ETHUSDT".to_string());
+    dashboard.handle_key(KeyEvent::from(KeyCode::Down), &rows);
+    assert_eq!(dashboard.selected.as_deref(), Some("BTCUSDT"));
+    dashboard.handle_key(KeyEvent::from(KeyCode::Enter), &rows);
+    assert_eq!(dashboard.detail.as_deref(), Some("BTCUSDT"));
+
+    let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+    terminal.draw(|frame| dashboard.draw(frame, &rows, now)).unwrap();
+    let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+    assert!(screen.contains("triggered"));
+    assert!(screen.contains("▁█ +10.00%"));
+    assert!(screen.contains("█▁ -10.00%"));
+    assert!(screen.contains("BTCUSDT: "));
+}
@@ -1,14 +1,27 @@
+use log::info;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use crate::error::ServerError;
 
-use env_logger::Builder;
-use std::env;
-
-pub fn init_logger() {
-    let mut builder = Builder::from_default_env();
-
-    if env::var("RUST_LOG").is_err() {
-        builder.filter_level(log::LevelFilter::Info);
+// resolves on the first SIGINT or SIGTERM
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => info!("received SIGINT"),
+            _ = sigterm.recv() => info!("received SIGTERM"),
+        }
     }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        info!("received ctrl-c");
+    }
+}
 
-    builder.init();
-
-}
\ No newline at end of file
+// servers bind before the symbols start, so that a taken port fails the startup
+pub async fn bind(addr: SocketAddr) -> Result<TcpListener, ServerError> {
+    TcpListener::bind(addr).await.map_err(|source| ServerError::Bind { addr, source })
+}
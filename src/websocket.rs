@@ -0,0 +1,257 @@
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinSet;
+use tokio::time::{interval_at, Duration, Instant, Interval, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tokio_util::sync::CancellationToken;
+use crate::config::validate::normalize_symbol;
+use crate::stream_monitor::evaluation::Reason;
+use crate::stream_monitor::hub::Hub;
+use crate::stream_monitor::market::Market;
+use crate::stream_monitor::snapshot::{Signal, SignalState, SymbolSnapshot};
+
+// the `websocket` config section, the server is off unless enabled
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct WebSocketConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+    // how often a client gets the state updates unless it asks otherwise
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+    // the fastest a client may ask for
+    #[serde(default = "default_min_interval_ms")]
+    pub min_interval_ms: u64,
+}
+
+fn default_listen() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 8765))
+}
+
+fn default_interval_ms() -> u64 {
+    1000
+}
+
+fn default_min_interval_ms() -> u64 {
+    250
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig {
+            enabled: false,
+            listen: default_listen(),
+            interval_ms: default_interval_ms(),
+            min_interval_ms: default_min_interval_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    State,
+    Signals,
+}
+
+// what a client sends, e.g. {"type": "subscribe", "symbols": ["ETHUSDT"], "channels": ["signals"]}
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    // replaces the current subscription, anything left out is the default
+    Subscribe {
+        #[serde(default)]
+        symbols: Vec<String>,
+        #[serde(default)]
+        channels: Option<HashSet<Channel>>,
+        #[serde(default)]
+        interval_ms: Option<u64>,
+    },
+}
+
+// the atr, volume and state a symbol ended its latest tick with
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StateUpdate {
+    pub symbol: String,
+    pub market: Market,
+    pub ts: Option<DateTime<Utc>>,
+    pub close_price: f64,
+    pub atr: f64,
+    pub atr_pct: f64,
+    pub atr_threshold: f64,
+    pub vol_usdt: f64,
+    pub state: SignalState,
+    pub reason: Reason,
+}
+
+impl From<&SymbolSnapshot> for StateUpdate {
+    fn from(snapshot: &SymbolSnapshot) -> Self {
+        let e = &snapshot.evaluation;
+        StateUpdate {
+            symbol: snapshot.symbol.clone(),
+            market: snapshot.market,
+            ts: snapshot.ts,
+            close_price: snapshot.close_price,
+            atr: e.atr,
+            atr_pct: e.atr_pct,
+            atr_threshold: e.atr_threshold,
+            vol_usdt: e.vol_usdt,
+            state: snapshot.state,
+            reason: e.reason,
+        }
+    }
+}
+
+// what the server sends, tagged by `type`
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    // every symbol, right after connecting
+    Snapshot { symbols: &'a [SymbolSnapshot] },
+    Subscribed { symbols: &'a [String], channels: &'a HashSet<Channel>, interval_ms: u64 },
+    State(&'a StateUpdate),
+    Signal(&'a Signal),
+    Error { message: String },
+}
+
+// what a client gets, everything by default
+struct Subscription {
+    // empty for every symbol
+    symbols: Vec<String>,
+    channels: HashSet<Channel>,
+    interval_ms: u64,
+}
+
+impl Subscription {
+    fn wants(&self, symbol: &str) -> bool {
+        self.symbols.is_empty() || self.symbols.iter().any(|wanted| wanted == symbol)
+    }
+}
+
+// accepts dashboard clients until cancelled, then closes every connection
+pub async fn serve(listener: TcpListener, hub: Hub, config: WebSocketConfig, cancel: CancellationToken) {
+    if let Ok(addr) = listener.local_addr() {
+        info!("websocket server listening on {}", addr);
+    }
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    connections.spawn(connection(stream, peer, hub.clone(), config, cancel.clone()));
+                }
+                Err(e) => warn!("failed to accept a websocket client: {}", e),
+            },
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = cancel.cancelled() => break,
+        }
+    }
+    while connections.join_next().await.is_some() {}
+}
+
+async fn connection(stream: TcpStream, peer: SocketAddr, hub: Hub, config: WebSocketConfig, cancel: CancellationToken) {
+    let mut ws = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            debug!("websocket handshake with {} failed: {}", peer, e);
+            return;
+        }
+    };
+    debug!("websocket client {} connected", peer);
+    match stream_updates(&mut ws, &hub, config, &cancel).await {
+        Ok(()) => debug!("websocket client {} disconnected", peer),
+        Err(e) => debug!("websocket client {} dropped: {}", peer, e),
+    }
+    let _ = ws.close(None).await;
+}
+
+async fn send(ws: &mut WebSocketStream<TcpStream>, message: &ServerMessage<'_>) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+    // the messages are plain structs and maps, they always serialize
+    let text = serde_json::to_string(message).expect("unserializable websocket message");
+    ws.send(Message::Text(text)).await
+}
+
+// first ticks one interval from now
+fn throttle(interval_ms: u64) -> Interval {
+    let period = Duration::from_millis(interval_ms);
+    let mut ticker = interval_at(Instant::now() + period, period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    ticker
+}
+
+// signals go out as they come, state updates at most once per interval and only for the symbols that ticked since
+async fn stream_updates(
+    ws: &mut WebSocketStream<TcpStream>,
+    hub: &Hub,
+    config: WebSocketConfig,
+    cancel: &CancellationToken,
+) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+    let mut subscription = Subscription {
+        symbols: vec![],
+        channels: HashSet::from([Channel::State, Channel::Signals]),
+        interval_ms: config.interval_ms.max(config.min_interval_ms),
+    };
+    let mut signals = hub.subscribe_signals();
+    let snapshots = hub.snapshots();
+    send(ws, &ServerMessage::Snapshot { symbols: &snapshots }).await?;
+    let mut ticker = throttle(subscription.interval_ms);
+    // the latest update sent per symbol, the snapshot holds them to begin with
+    let mut sent: HashMap<String, StateUpdate> =
+        snapshots.iter().map(|snapshot| (snapshot.symbol.clone(), StateUpdate::from(snapshot))).collect();
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            received = ws.next() => match received {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(ClientMessage::Subscribe { symbols, channels, interval_ms }) => {
+                        subscription = Subscription {
+                            symbols: symbols.iter().map(|symbol| normalize_symbol(symbol)).collect(),
+                            channels: channels.unwrap_or_else(|| HashSet::from([Channel::State, Channel::Signals])),
+                            interval_ms: interval_ms.unwrap_or(config.interval_ms).max(config.min_interval_ms),
+                        };
+                        ticker = throttle(subscription.interval_ms);
+                        sent.clear();
+                        // nothing the client didn't ask for piles up
+                        signals = hub.subscribe_signals();
+                        let subscribed = ServerMessage::Subscribed {
+                            symbols: &subscription.symbols,
+                            channels: &subscription.channels,
+                            interval_ms: subscription.interval_ms,
+                        };
+                        send(ws, &subscribed).await?;
+                    }
+                    Err(e) => send(ws, &ServerMessage::Error { message: format!("invalid message: {}", e) }).await?,
+                },
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                // pings are answered by the library
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e),
+            },
+            received = signals.recv(), if subscription.channels.contains(&Channel::Signals) => match received {
+                Ok(signal) if subscription.wants(&signal.symbol) => send(ws, &ServerMessage::Signal(&signal)).await?,
+                Ok(_) => {}
+                // a slow client misses signals rather than holding up the others
+                Err(RecvError::Lagged(skipped)) => warn!("websocket client fell behind, skipped {} signal(s)", skipped),
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            _ = ticker.tick(), if subscription.channels.contains(&Channel::State) => {
+                for snapshot in hub.snapshots().iter().filter(|snapshot| subscription.wants(&snapshot.symbol)) {
+                    let update = StateUpdate::from(snapshot);
+                    if sent.get(&snapshot.symbol) == Some(&update) {
+                        continue;
+                    }
+                    send(ws, &ServerMessage::State(&update)).await?;
+                    sent.insert(update.symbol.clone(), update);
+                }
+            }
+        }
+    }
+}
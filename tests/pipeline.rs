@@ -0,0 +1,517 @@
+mod support;
+
+use futures_util::{SinkExt, StreamExt};
+use std::sync::atomic::Ordering;
+use support::{
+    book_ticker, calm, combined, depth, force_order, kline, klines, mark_price, run_until_messages, settings, spike, wait_for,
+    wrap_klines, MockExchange, MockRest, Step, START_MS,
+};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_util::sync::CancellationToken;
+use whiplash::config::layers::Layers;
+use whiplash::config::Config;
+use whiplash::grpc::{self, proto};
+use whiplash::http::{self, SymbolCommand};
+use whiplash::stream_monitor::evaluation::Reason;
+use whiplash::stream_monitor::hub::Hub;
+use whiplash::stream_monitor::snapshot::SignalState;
+use whiplash::stream_monitor::regime::{self, RegimeTag};
+use whiplash::stream_monitor::source::Source;
+use whiplash::stream_monitor::{run, SymbolData};
+use whiplash::util;
+use whiplash::websocket::{self, WebSocketConfig};
+
+const SYMBOL: &str = "ETHUSDT";
+
+#[tokio::test]
+async fn test_signal_from_mock_exchange() {
+    let mut script = klines(SYMBOL, START_MS, calm(15));
+    script.extend(klines(SYMBOL, START_MS + 15_000, spike(15)));
+    let exchange = MockExchange::start(vec![script]).await;
+
+    let hub = Hub::new();
+    let mut signals = hub.subscribe_signals();
+    let snapshot = run_until_messages(&hub, &[SYMBOL], &settings(&exchange.url), 120).await.remove(0);
+    assert_eq!(snapshot.evaluation.reason, Reason::Triggered);
+
+    let signal = signals.recv().await.unwrap();
+    assert_eq!(signal.symbol, SYMBOL);
+    assert_eq!(signal.evaluation.reason, Reason::Triggered);
+    // the calm part alone never triggers
+    assert!(signal.ts.timestamp_millis() >= START_MS + 15_000);
+
+    // shutting down closes the stream properly
+    assert_eq!(exchange.requests.lock().unwrap().as_slice(), ["/ws/ethusdt@kline_1m"]);
+    assert_eq!(exchange.client_closes.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_bars_only_while_watched() {
+    let exchange = MockExchange::start(vec![klines(SYMBOL, START_MS, calm(30)), klines(SYMBOL, START_MS, calm(30))]).await;
+    let settings = settings(&exchange.url);
+    let hub = Hub::new();
+
+    let bars = hub.watch_bars();
+    let snapshot = run_until_messages(&hub, &[SYMBOL], &settings, 120).await.remove(0);
+    assert_eq!(snapshot.bars.len(), 30);
+
+    // once nobody watches anymore they're left out
+    drop(bars);
+    let snapshot = run_until_messages(&hub, &[SYMBOL], &settings, 120).await.remove(0);
+    assert!(snapshot.bars.is_empty());
+    assert!(hub.snapshot(SYMBOL).unwrap().bars.is_empty());
+}
+
+#[tokio::test]
+async fn test_malformed_frames_are_skipped() {
+    let mut script = klines(SYMBOL, START_MS, calm(5));
+    script.push(Step::Send("not json".to_string()));
+    script.push(Step::Send(kline(SYMBOL, START_MS + 5_000, 2000., 10., false).replace("\"2000.00\"", "\"NaN-ish\"")));
+    script.push(Step::Send(r#"{"e":"kline","E":1700000005000}"#.to_string()));
+    script.extend(klines(SYMBOL, START_MS + 5_000, calm(5)));
+    let exchange = MockExchange::start(vec![script]).await;
+
+    // only the well formed frames reach the symbol, and the stream stays up
+    let snapshot = run_until_messages(&Hub::new(), &[SYMBOL], &settings(&exchange.url), 40).await.remove(0);
+    assert_eq!(snapshot.evaluation.reason, Reason::BelowThreshold);
+    assert_eq!(exchange.connections(), 1);
+}
+
+#[tokio::test]
+async fn test_reconnect_after_disconnect() {
+    let mut first = klines(SYMBOL, START_MS, calm(5));
+    first.push(Step::Disconnect);
+    // the reconnect replays the latest second, which has to be deduplicated
+    let second = klines(SYMBOL, START_MS + 4_000, calm(6));
+    let exchange = MockExchange::start(vec![first, second]).await;
+
+    let snapshot = run_until_messages(&Hub::new(), &[SYMBOL], &settings(&exchange.url), 44).await.remove(0);
+    assert_eq!(exchange.connections(), 2);
+    assert_eq!(snapshot.reconnects, 1);
+    assert_eq!(snapshot.anomalies.duplicates + snapshot.anomalies.merged, 4);
+    assert_eq!(snapshot.ts.unwrap().timestamp_millis(), START_MS + 9_750);
+}
+
+#[tokio::test]
+async fn test_state_survives_restart() {
+    let dir = std::env::temp_dir().join(format!("whiplash-state-{}", std::process::id()));
+    let hub = Hub::new();
+    for (round, start_ms) in [START_MS, START_MS + 5_000].into_iter().enumerate() {
+        let exchange = MockExchange::start(vec![klines(SYMBOL, start_ms, calm(5))]).await;
+        let mut settings = settings(&exchange.url);
+        settings.persistence.enabled = true;
+        settings.persistence.dir = dir.clone();
+
+        // the second run carries on with the buffer and the candles of the first one
+        let snapshot = run_until_messages(&hub, &[SYMBOL], &settings, 20).await.remove(0);
+        assert_eq!(snapshot.buffer.len, 20 * (round + 1));
+        assert_eq!(snapshot.evaluation.candles, 5 * (round + 1));
+        assert!(dir.join(format!("{}.json", SYMBOL)).exists());
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_order_book_confirmation() {
+    let mut script = vec![
+        Step::Send(combined(SYMBOL, "bookTicker", &book_ticker(SYMBOL, START_MS, 1990., 2010.))),
+        Step::Send(combined(SYMBOL, "depth20@100ms", &depth(SYMBOL, START_MS, &[(1999., 30.)], &[(2001., 10.)]))),
+    ];
+    script.extend(wrap_klines(SYMBOL, klines(SYMBOL, START_MS, calm(15))));
+    script.extend(wrap_klines(SYMBOL, klines(SYMBOL, START_MS + 15_000, spike(15))));
+    let exchange = MockExchange::start(vec![script]).await;
+
+    let mut settings = settings(&exchange.url);
+    settings.orderbook.depth = true;
+    settings.orderbook.book_ticker = true;
+    settings.orderbook.max_spread_bps = Some(5.);
+
+    // the spike passes atr and volume, the spread of the book ticker holds it back
+    let snapshot = run_until_messages(&Hub::new(), &[SYMBOL], &settings, 120).await.remove(0);
+    assert_eq!(snapshot.evaluation.reason, Reason::SpreadTooWide);
+    assert_eq!(snapshot.signals, 0);
+    let book = snapshot.evaluation.book.unwrap();
+    assert_eq!(book.spread_bps.map(|spread| spread.round()), Some(100.));
+    assert_eq!(book.imbalance.map(|imbalance| (imbalance * 100.).round()), Some(50.));
+
+    assert_eq!(
+        exchange.requests.lock().unwrap().as_slice(),
+        ["/stream?streams=ethusdt@kline_1m/ethusdt@depth20@100ms/ethusdt@bookTicker"]
+    );
+}
+
+#[tokio::test]
+async fn test_liquidations_from_all_market_stream() {
+    let liquidations_ms = START_MS + 25_000;
+    let mut script = vec![
+        // the all-market stream carries other symbols too
+        Step::Send(combined(SYMBOL, "!forceOrder@arr", &force_order("BTCUSDT", liquidations_ms, "SELL", 37_000., 30.))),
+        Step::Send(combined(SYMBOL, "!forceOrder@arr", &force_order(SYMBOL, liquidations_ms, "SELL", 2000., 20.))),
+        Step::Send(combined(SYMBOL, "!forceOrder@arr", &force_order(SYMBOL, liquidations_ms, "BUY", 2000., 10.))),
+    ];
+    script.extend(wrap_klines(SYMBOL, klines(SYMBOL, START_MS, calm(15))));
+    script.extend(wrap_klines(SYMBOL, klines(SYMBOL, START_MS + 15_000, spike(15))));
+    let exchange = MockExchange::start(vec![script]).await;
+
+    let mut settings = settings(&exchange.url);
+    settings.liquidations.enabled = true;
+    settings.liquidations.all_market = true;
+    settings.liquidations.min_notional_usdt = Some(100_000.);
+
+    let snapshot = run_until_messages(&Hub::new(), &[SYMBOL], &settings, 120).await.remove(0);
+    assert_eq!(snapshot.evaluation.reason, Reason::LiquidationsTooLow);
+    let liquidations = snapshot.evaluation.liquidations.unwrap();
+    assert_eq!((liquidations.sell_usdt, liquidations.buy_usdt, liquidations.count), (40_000., 20_000., 2));
+
+    assert_eq!(exchange.requests.lock().unwrap().as_slice(), ["/stream?streams=ethusdt@kline_1m/!forceOrder@arr"]);
+}
+
+#[tokio::test]
+async fn test_mark_price_filters_dislocation() {
+    // the last price runs off while the mark stays put
+    let mut script = vec![Step::Send(combined(SYMBOL, "markPrice@1s", &mark_price(SYMBOL, START_MS, 1950., 1951., 0.0001)))];
+    script.extend(wrap_klines(SYMBOL, klines(SYMBOL, START_MS, calm(15))));
+    script.extend(wrap_klines(SYMBOL, klines(SYMBOL, START_MS + 15_000, spike(15))));
+    let exchange = MockExchange::start(vec![script]).await;
+
+    let mut settings = settings(&exchange.url);
+    settings.mark_price.enabled = true;
+    settings.mark_price.max_basis_bps = Some(100.);
+
+    let snapshot = run_until_messages(&Hub::new(), &[SYMBOL], &settings, 120).await.remove(0);
+    assert_eq!(snapshot.evaluation.reason, Reason::BasisTooWide);
+    let mark = snapshot.evaluation.mark.unwrap();
+    assert_eq!((mark.mark_price, mark.index_price, mark.predicted_funding_rate), (1950., 1951., 0.0001));
+    assert_eq!(mark.basis_bps.round(), 256.);
+
+    assert_eq!(exchange.requests.lock().unwrap().as_slice(), ["/stream?streams=ethusdt@kline_1m/ethusdt@markPrice@1s"]);
+}
+
+#[tokio::test]
+async fn test_open_interest_from_mock_rest() {
+    // the history reaches back past both windows, every poll reads 10% more than 5 minutes ago
+    let now = chrono::Utc::now().timestamp_millis();
+    let rest = MockRest::start(move |target| match target.split('?').next().unwrap_or_default() {
+        "/futures/data/openInterestHist" => (
+            200,
+            serde_json::json!([
+                { "symbol": SYMBOL, "sumOpenInterest": "1000.000", "sumOpenInterestValue": "2000000", "timestamp": now - 600_000 },
+                { "symbol": SYMBOL, "sumOpenInterest": "1100.000", "sumOpenInterestValue": "2200000", "timestamp": now - 300_000 },
+            ])
+            .to_string(),
+        ),
+        "/fapi/v1/openInterest" => (
+            200,
+            serde_json::json!({ "symbol": SYMBOL, "openInterest": "1210.000", "time": chrono::Utc::now().timestamp_millis() })
+                .to_string(),
+        ),
+        _ => (404, "{}".to_string()),
+    })
+    .await;
+    let mut script = klines(SYMBOL, START_MS, calm(15));
+    // gives the poller time to read the history and the first sample
+    script.push(Step::Sleep(std::time::Duration::from_millis(300)));
+    script.extend(klines(SYMBOL, START_MS + 15_000, spike(15)));
+    let exchange = MockExchange::start(vec![script]).await;
+
+    let mut settings = settings(&exchange.url);
+    settings.rest_url = Some(rest.url.clone());
+    settings.open_interest.enabled = true;
+    settings.open_interest.poll_interval_ms = 50;
+    settings.open_interest.min_change_pct = Some(20.);
+
+    let snapshot = run_until_messages(&Hub::new(), &[SYMBOL], &settings, 120).await.remove(0);
+    assert_eq!(snapshot.evaluation.reason, Reason::OpenInterestChangeTooLow);
+    let open_interest = snapshot.evaluation.open_interest.unwrap();
+    assert_eq!(open_interest.open_interest, 1210.);
+    let changes: Vec<_> = open_interest.changes.iter().map(|change| change.change_pct.map(f64::round)).collect();
+    assert_eq!(changes, [Some(10.), Some(10.)]);
+
+    let requests = rest.requests.lock().unwrap();
+    assert_eq!(requests[0], format!("/futures/data/openInterestHist?symbol={}&period=5m&limit=2", SYMBOL));
+    assert!(requests[1..].iter().all(|request| *request == format!("/fapi/v1/openInterest?symbol={}", SYMBOL)));
+}
+
+#[tokio::test]
+async fn test_market_wide_moves_are_tagged() {
+    // every symbol spikes at once, the klines carry no symbol the reader would check so one script fits all
+    let symbols = ["BTCUSDT", "ETHUSDT", "SOLUSDT", "XRPUSDT"];
+    let script = || {
+        let mut script = klines(SYMBOL, START_MS, calm(15));
+        script.extend(klines(SYMBOL, START_MS + 15_000, spike(15)));
+        // lets every symbol catch up, so that the last second sees the others spike
+        script.insert(script.len() - 4, Step::Sleep(std::time::Duration::from_millis(300)));
+        script
+    };
+    let exchange = MockExchange::start(symbols.iter().map(|_| script()).collect()).await;
+
+    let hub = Hub::new();
+    let cancel = CancellationToken::new();
+    let mut settings = settings(&exchange.url);
+    settings.regime.suppress = Some(RegimeTag::MarketWide);
+    let aggregator = tokio::spawn(regime::aggregate(hub.clone(), settings.regime, cancel.clone()));
+
+    for snapshot in run_until_messages(&hub, &symbols, &settings, 120).await {
+        assert_eq!(snapshot.evaluation.reason, Reason::MarketWideMove);
+        assert_eq!(snapshot.evaluation.regime, Some(RegimeTag::MarketWide));
+        assert_eq!(snapshot.evaluation.breadth, Some(1.));
+    }
+    assert_eq!(hub.regime().breadth, 1.);
+
+    cancel.cancel();
+    aggregator.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_residual_atr_against_reference() {
+    // the follower moves exactly like the reference, so its atr is all beta
+    let symbols = ["BTCUSDT", "ETHUSDT"];
+    let script = || {
+        let mut script = klines(SYMBOL, START_MS, calm(15));
+        script.extend(klines(SYMBOL, START_MS + 15_000, spike(15)));
+        // lets the reference publish the spike before the follower's last seconds
+        script.insert(script.len() - 4, Step::Sleep(std::time::Duration::from_millis(300)));
+        script
+    };
+    let exchange = MockExchange::start(symbols.iter().map(|_| script()).collect()).await;
+
+    let mut settings = settings(&exchange.url);
+    settings.reference.enabled = true;
+    settings.reference.min_samples = 10;
+    settings.reference.use_residual = true;
+    let [reference, follower] = run_until_messages(&Hub::new(), &symbols, &settings, 120).await.try_into().unwrap();
+
+    // the reference is checked on its own atr
+    assert_eq!(reference.evaluation.reason, Reason::Triggered);
+    assert_eq!(reference.evaluation.reference, None);
+
+    let stats = follower.evaluation.reference.unwrap();
+    assert!((stats.beta - 1.).abs() < 1e-6, "beta {}", stats.beta);
+    assert!(stats.correlation > 0.99);
+    assert!(follower.evaluation.atr_pct > settings.atr_threshold);
+    assert_eq!(follower.evaluation.reason, Reason::BelowThreshold);
+    assert!(follower.evaluation.residual);
+}
+
+#[tokio::test]
+async fn test_reference_only_symbol_never_signals() {
+    let mut script = klines(SYMBOL, START_MS, calm(15));
+    script.extend(klines(SYMBOL, START_MS + 15_000, spike(15)));
+    let exchange = MockExchange::start(vec![script]).await;
+
+    let hub = Hub::new();
+    let mut signals = hub.subscribe_signals();
+    let mut settings = settings(&exchange.url);
+    settings.reference_only = true;
+
+    // the spike is still evaluated, for the other symbols and the dashboards
+    let snapshot = run_until_messages(&hub, &[SYMBOL], &settings, 120).await.remove(0);
+    assert_eq!(snapshot.evaluation.reason, Reason::Triggered);
+    assert_eq!(snapshot.state, SignalState::Idle);
+    assert_eq!(snapshot.signals, 0);
+    assert!(snapshot.reference_only);
+    assert!(signals.try_recv().is_err());
+    // nor does it count towards the breadth
+    assert!(regime::MarketRegime::from_snapshots(&hub.snapshots()).pulses.is_empty());
+}
+
+#[tokio::test]
+async fn test_grpc_signals_and_state() {
+    let mut script = klines(SYMBOL, START_MS, calm(15));
+    script.extend(klines(SYMBOL, START_MS + 15_000, spike(15)));
+    let exchange = MockExchange::start(vec![script]).await;
+
+    let hub = Hub::new();
+    let cancel = CancellationToken::new();
+    let listener = util::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(grpc::serve(listener, hub.clone(), cancel.clone()));
+
+    let mut client = proto::whiplash_client::WhiplashClient::connect(format!("http://{}", addr)).await.unwrap();
+    let request = proto::SubscribeSignalsRequest { symbols: vec!["ethusdt".to_string()] };
+    let mut signals = client.subscribe_signals(request).await.unwrap().into_inner();
+    // subscribed by now, so nothing is missed
+    run_until_messages(&hub, &[SYMBOL], &settings(&exchange.url), 120).await;
+
+    let signal = signals.message().await.unwrap().unwrap();
+    assert_eq!(signal.symbol, SYMBOL);
+    assert_eq!(signal.evaluation.unwrap().reason, "triggered");
+
+    // the state of a stopped symbol stays readable
+    let state = client.get_symbol_state(proto::GetSymbolStateRequest { symbol: SYMBOL.to_string() }).await.unwrap().into_inner();
+    assert_eq!(state.messages, 120);
+    assert!(state.buffer.unwrap().len > 0);
+    let missing = client.get_symbol_state(proto::GetSymbolStateRequest { symbol: "NOSUCHUSDT".to_string() }).await.unwrap_err();
+    assert_eq!(missing.code(), tonic::Code::NotFound);
+
+    // the stream ends along with the server
+    cancel.cancel();
+    server.await.unwrap().unwrap();
+    while signals.message().await.is_ok_and(|signal| signal.is_some()) {}
+}
+
+type WsClient = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+// the next text message, parsed
+async fn next_json(client: &mut WsClient) -> serde_json::Value {
+    loop {
+        if let Message::Text(text) = client.next().await.unwrap().unwrap() {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+// the next message that isn't a state update
+async fn next_reply(client: &mut WsClient) -> serde_json::Value {
+    loop {
+        let message = next_json(client).await;
+        if message["type"] != "state" {
+            return message;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_websocket_dashboard_feed() {
+    let mut script = klines(SYMBOL, START_MS, calm(15));
+    script.extend(klines(SYMBOL, START_MS + 15_000, spike(15)));
+    let exchange = MockExchange::start(vec![script]).await;
+
+    let hub = Hub::new();
+    let cancel = CancellationToken::new();
+    let listener = util::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = WebSocketConfig { enabled: true, min_interval_ms: 50, ..Default::default() };
+    let server = tokio::spawn(websocket::serve(listener, hub.clone(), config, cancel.clone()));
+    let publisher = hub.register(SYMBOL);
+
+    let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr)).await.unwrap();
+    let snapshot = next_json(&mut client).await;
+    assert_eq!(snapshot["type"], "snapshot");
+    assert_eq!(snapshot["symbols"][0]["symbol"], SYMBOL);
+
+    client.send(Message::Text("{\"type\": \"unsubscribe\"}".to_string())).await.unwrap();
+    client.send(Message::Text("{\"type\": \"subscribe\", \"symbols\": [\"ethusdt\"], \"interval_ms\": 10}".to_string())).await.unwrap();
+    assert_eq!(next_reply(&mut client).await["type"], "error");
+    let subscribed = next_reply(&mut client).await;
+    assert_eq!(subscribed["type"], "subscribed");
+    // faster than allowed is slowed down
+    assert_eq!(subscribed["interval_ms"], 50);
+
+    let task = tokio::spawn(run(SymbolData::new(SYMBOL, settings(&exchange.url)), publisher, cancel.clone(), Source::Live, None));
+    let (mut states, mut signal) = (vec![], None);
+    let collect = async {
+        while signal.is_none() || states.is_empty() {
+            let message = next_json(&mut client).await;
+            match message["type"].as_str() {
+                Some("state") => states.push(message),
+                Some("signal") => signal = Some(message),
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(10), collect).await.expect("timed out waiting for the updates");
+    assert_eq!(signal.unwrap()["evaluation"]["reason"], "triggered");
+    assert!(states.iter().all(|state| state["symbol"] == SYMBOL && state["atr"].is_number()));
+
+    // the server closes the connection on shutdown
+    cancel.cancel();
+    task.await.unwrap().unwrap();
+    server.await.unwrap();
+    while let Some(Ok(message)) = client.next().await {
+        if message.is_close() {
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_http_control_api() {
+    let mut script = klines(SYMBOL, START_MS, calm(15));
+    script.extend(klines(SYMBOL, START_MS + 15_000, spike(15)));
+    let exchange = MockExchange::start(vec![script]).await;
+
+    let raw = format!("min_vol_usdt: 1000\nsymbols: [ETHUSDT]\nhttp: {{enabled: true, token: secret}}\nstream_url: {}", exchange.url);
+    let path = std::env::temp_dir().join(format!("whiplash-http-{}.yaml", std::process::id()));
+    std::fs::write(&path, raw).unwrap();
+    let mut layers = Layers::new(Config::defaults());
+    layers.file(&path, true).unwrap();
+    let (config, report) = Config::check_value(layers.value);
+    let config = config.unwrap_or_else(|| panic!("{}", report));
+
+    let hub = Hub::new();
+    let cancel = CancellationToken::new();
+    let listener = util::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (commands_tx, mut commands) = tokio::sync::mpsc::channel(1);
+    let server = tokio::spawn(http::serve(listener, hub.clone(), config, path.clone(), commands_tx, cancel.clone()));
+    let publisher = hub.register(SYMBOL);
+    // stands in for main, which owns the symbol tasks
+    let tasks = tokio::spawn({
+        let hub = hub.clone();
+        async move {
+            while let Some(command) = commands.recv().await {
+                match command {
+                    SymbolCommand::Add { symbol, reply } => {
+                        hub.register(&symbol.symbol);
+                        reply.send(Ok(())).unwrap();
+                    }
+                    SymbolCommand::Remove { symbol, reply } => {
+                        hub.unregister(&symbol);
+                        reply.send(Ok(())).unwrap();
+                    }
+                }
+            }
+        }
+    });
+
+    let client = reqwest::Client::new();
+    let unauthorized = client.get(format!("{}/symbols", url)).send().await.unwrap();
+    assert_eq!(unauthorized.status(), 401);
+    let symbols: serde_json::Value = client.get(format!("{}/symbols", url)).bearer_auth("secret").send().await.unwrap().json().await.unwrap();
+    assert_eq!(symbols[0]["symbol"], SYMBOL);
+    assert_eq!(symbols[0]["paused"], false);
+
+    // a paused symbol is evaluated, but holds its signals back
+    let paused = client.post(format!("{}/symbols/ethusdt/pause", url)).bearer_auth("secret").send().await.unwrap();
+    assert_eq!(paused.json::<serde_json::Value>().await.unwrap()["paused"], true);
+    let task = tokio::spawn(run(SymbolData::new(SYMBOL, settings(&exchange.url)), publisher, cancel.clone(), Source::Live, None));
+    let snapshot = wait_for(&hub, SYMBOL, |snapshot| snapshot.state == SignalState::Paused).await;
+    assert_eq!(snapshot.signals, 0);
+
+    let out_of_range = client.patch(format!("{}/symbols/ETHUSDT/thresholds", url)).bearer_auth("secret").json(&serde_json::json!({"atr_threshold": 150})).send().await.unwrap();
+    assert_eq!(out_of_range.status(), 400);
+    let thresholds = client.patch(format!("{}/symbols/ETHUSDT/thresholds", url)).bearer_auth("secret").json(&serde_json::json!({"atr_threshold": 0.5})).send().await.unwrap();
+    assert_eq!(thresholds.json::<serde_json::Value>().await.unwrap()["atr_threshold"], 0.5);
+    assert_eq!(hub.controls(SYMBOL).unwrap().atr_threshold, Some(0.5));
+
+    let added = client.post(format!("{}/symbols", url)).bearer_auth("secret").json(&serde_json::json!({"symbol": "solusdt", "min_vol_usdt": 5000})).send().await.unwrap();
+    assert_eq!(added.status(), 201);
+    assert_eq!(added.json::<serde_json::Value>().await.unwrap()["symbol"], "SOLUSDT");
+    let again = client.post(format!("{}/symbols", url)).bearer_auth("secret").json(&"SOLUSDT").send().await.unwrap();
+    assert_eq!(again.status(), 409);
+    assert_eq!(hub.symbols(), vec!["ETHUSDT", "SOLUSDT"]);
+
+    let saved = client.post(format!("{}/config/save", url)).bearer_auth("secret").send().await.unwrap();
+    assert_eq!(saved.status(), 200);
+    let removed = client.delete(format!("{}/symbols/SOLUSDT", url)).bearer_auth("secret").send().await.unwrap();
+    assert_eq!(removed.status(), 204);
+    let missing = client.delete(format!("{}/symbols/SOLUSDT", url)).bearer_auth("secret").send().await.unwrap();
+    assert_eq!(missing.status(), 404);
+
+    // the saved config loads with the changes made up to then
+    let mut layers = Layers::new(Config::defaults());
+    layers.file(&path, true).unwrap();
+    let (saved, report) = Config::check_value(layers.value);
+    let saved = saved.unwrap_or_else(|| panic!("{}", report));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(saved.symbol_names(), vec!["ETHUSDT", "SOLUSDT"]);
+    assert_eq!(saved.symbols[0].atr_threshold, Some(0.5));
+    assert_eq!(saved.symbols[1].min_vol_usdt, Some(5000.));
+    assert_eq!(saved.http.token.as_deref(), Some("secret"));
+
+    cancel.cancel();
+    task.await.unwrap().unwrap();
+    server.await.unwrap().unwrap();
+    tasks.await.unwrap();
+}
@@ -0,0 +1,356 @@
+// in-process stand-in for the binance websocket, serving scripted frames
+#![allow(dead_code)]
+
+use futures_util::{SinkExt, StreamExt};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Duration};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_util::sync::CancellationToken;
+use whiplash::stream_monitor::atr::MovingAverage;
+use whiplash::stream_monitor::clock::{EvaluationConfig, EvaluationMode};
+use whiplash::stream_monitor::hub::Hub;
+use whiplash::stream_monitor::snapshot::SymbolSnapshot;
+use whiplash::stream_monitor::source::Source;
+use whiplash::stream_monitor::{run, SymbolData, SymbolSettings};
+
+// binance pushes kline updates every 250ms
+pub const MESSAGE_INTERVAL_MS: i64 = 250;
+// 2023-11-14 22:13:20 UTC, any fixed point works since the indicators run on the event time
+pub const START_MS: i64 = 1_700_000_000_000;
+
+pub enum Step {
+    // a raw text frame, fixtures and garbage alike
+    Send(String),
+    Sleep(Duration),
+    // drop the connection without a close frame
+    Disconnect,
+    // close the websocket properly
+    Close,
+}
+
+// serves one script per connection, in the order the connections come in
+// once its script runs out a connection stays open until the client leaves
+pub struct MockExchange {
+    // base url to configure as the `stream_url`
+    pub url: String,
+    // requested paths along with the query, e.g. `/ws/ethusdt@kline_1m`
+    pub requests: Arc<Mutex<Vec<String>>>,
+    // close frames sent by the client
+    pub client_closes: Arc<AtomicUsize>,
+    server: JoinHandle<()>,
+}
+
+impl MockExchange {
+    pub async fn start(scripts: Vec<Vec<Step>>) -> MockExchange {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let client_closes = Arc::new(AtomicUsize::new(0));
+
+        let server = tokio::spawn({
+            let requests = requests.clone();
+            let client_closes = client_closes.clone();
+            async move {
+                let mut scripts = scripts.into_iter();
+                while let Ok((tcp, _)) = listener.accept().await {
+                    let script = scripts.next().unwrap_or_default();
+                    let requests = requests.clone();
+                    let client_closes = client_closes.clone();
+                    tokio::spawn(async move {
+                        // the signature is tungstenite's
+                        #[allow(clippy::result_large_err)]
+                        let callback = |request: &Request, response: Response| {
+                            requests.lock().unwrap().push(request.uri().to_string());
+                            Ok(response)
+                        };
+                        let Ok(mut ws) = tokio_tungstenite::accept_hdr_async(tcp, callback).await else {
+                            return;
+                        };
+                        for step in script {
+                            match step {
+                                Step::Send(text) => {
+                                    if ws.send(Message::Text(text)).await.is_err() {
+                                        return;
+                                    }
+                                }
+                                Step::Sleep(duration) => sleep(duration).await,
+                                Step::Disconnect => return,
+                                Step::Close => {
+                                    let _ = ws.close(None).await;
+                                    break;
+                                }
+                            }
+                        }
+                        // reading answers the close handshake of the client
+                        while let Some(Ok(message)) = ws.next().await {
+                            if message.is_close() {
+                                client_closes.fetch_add(1, Ordering::SeqCst);
+                            }
+                        }
+                    });
+                }
+            }
+        });
+
+        MockExchange { url, requests, client_closes, server }
+    }
+
+    pub fn connections(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+}
+
+impl Drop for MockExchange {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+// in-process stand-in for the binance rest api, answering every request with `respond(path and query)`
+// as a (status, json body) pair, one request per connection
+pub struct MockRest {
+    // base url to configure as the `rest_url`
+    pub url: String,
+    // requested paths along with the query, e.g. `/fapi/v1/openInterest?symbol=ETHUSDT`
+    pub requests: Arc<Mutex<Vec<String>>>,
+    server: JoinHandle<()>,
+}
+
+impl MockRest {
+    pub async fn start(respond: impl Fn(&str) -> (u16, String) + Send + Sync + 'static) -> MockRest {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let respond = Arc::new(respond);
+
+        let server = tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                while let Ok((mut tcp, _)) = listener.accept().await {
+                    let requests = requests.clone();
+                    let respond = respond.clone();
+                    tokio::spawn(async move {
+                        // a get has no body, the head is all there is
+                        let mut head = vec![];
+                        let mut buffer = [0; 1024];
+                        while !head.ends_with(b"\r\n\r\n") {
+                            match tcp.read(&mut buffer).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(read) => head.extend_from_slice(&buffer[..read]),
+                            }
+                        }
+                        let head = String::from_utf8_lossy(&head);
+                        let target = head.split_whitespace().nth(1).unwrap_or_default().to_string();
+                        requests.lock().unwrap().push(target.clone());
+                        let (status, body) = respond(&target);
+                        let response = format!(
+                            "HTTP/1.1 {} MOCK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                            status,
+                            body.len(),
+                            body
+                        );
+                        let _ = tcp.write_all(response.as_bytes()).await;
+                    });
+                }
+            }
+        });
+
+        MockRest { url, requests, server }
+    }
+}
+
+impl Drop for MockRest {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+// a kline update, the high and low are left at the close since only the close feeds the atr
+pub fn kline(symbol: &str, event_ms: i64, close: f64, volume: f64, closed: bool) -> String {
+    serde_json::json!({
+        "e": "kline",
+        "E": event_ms,
+        "s": symbol,
+        "k": {
+            "s": symbol,
+            "i": "1m",
+            "o": format!("{:.2}", close),
+            "c": format!("{:.2}", close),
+            "h": format!("{:.2}", close),
+            "l": format!("{:.2}", close),
+            "v": format!("{:.3}", volume),
+            "x": closed,
+        },
+    })
+    .to_string()
+}
+
+// a frame of a combined stream connection, all-market streams like `!forceOrder@arr` go by their name alone
+pub fn combined(symbol: &str, stream: &str, data: &str) -> String {
+    let stream = match stream.starts_with('!') {
+        true => stream.to_string(),
+        false => format!("{}@{}", symbol.to_lowercase(), stream),
+    };
+    format!(r#"{{"stream":"{}","data":{}}}"#, stream, data)
+}
+
+// wraps the kline frames of a script for a combined stream connection
+pub fn wrap_klines(symbol: &str, steps: Vec<Step>) -> Vec<Step> {
+    steps
+        .into_iter()
+        .map(|step| match step {
+            Step::Send(text) => Step::Send(combined(symbol, "kline_1m", &text)),
+            step => step,
+        })
+        .collect()
+}
+
+pub fn book_ticker(symbol: &str, event_ms: i64, bid: f64, ask: f64) -> String {
+    serde_json::json!({
+        "e": "bookTicker",
+        "E": event_ms,
+        "s": symbol,
+        "b": format!("{:.2}", bid),
+        "B": "1.000",
+        "a": format!("{:.2}", ask),
+        "A": "1.000",
+    })
+    .to_string()
+}
+
+// a filled liquidation order, `side` is BUY or SELL
+pub fn force_order(symbol: &str, event_ms: i64, side: &str, price: f64, quantity: f64) -> String {
+    serde_json::json!({
+        "e": "forceOrder",
+        "E": event_ms,
+        "o": {
+            "s": symbol,
+            "S": side,
+            "o": "LIMIT",
+            "q": format!("{:.3}", quantity),
+            "p": format!("{:.2}", price),
+            "ap": format!("{:.2}", price),
+            "X": "FILLED",
+            "z": format!("{:.3}", quantity),
+            "T": event_ms,
+        },
+    })
+    .to_string()
+}
+
+// funding every 8 hours from START_MS
+pub fn mark_price(symbol: &str, event_ms: i64, mark: f64, index: f64, funding_rate: f64) -> String {
+    serde_json::json!({
+        "e": "markPriceUpdate",
+        "E": event_ms,
+        "s": symbol,
+        "p": format!("{:.2}", mark),
+        "i": format!("{:.2}", index),
+        "P": format!("{:.2}", mark),
+        "r": format!("{:.8}", funding_rate),
+        "T": START_MS + 8 * 3_600_000,
+    })
+    .to_string()
+}
+
+// levels as (price, quantity), best first
+pub fn depth(symbol: &str, event_ms: i64, bids: &[(f64, f64)], asks: &[(f64, f64)]) -> String {
+    let levels = |levels: &[(f64, f64)]| {
+        levels.iter().map(|(price, qty)| [format!("{:.2}", price), format!("{:.3}", qty)]).collect::<Vec<_>>()
+    };
+    serde_json::json!({
+        "e": "depthUpdate",
+        "E": event_ms,
+        "s": symbol,
+        "b": levels(bids),
+        "a": levels(asks),
+    })
+    .to_string()
+}
+
+// one update every MESSAGE_INTERVAL_MS from `start_ms`, with the volume growing by 10 per update
+pub fn klines(symbol: &str, start_ms: i64, prices: impl IntoIterator<Item = f64>) -> Vec<Step> {
+    prices
+        .into_iter()
+        .enumerate()
+        .map(|(i, price)| {
+            let i = i as i64;
+            Step::Send(kline(symbol, start_ms + i * MESSAGE_INTERVAL_MS, price, (i + 1) as f64 * 10., false))
+        })
+        .collect()
+}
+
+// `seconds` of prices within a 0.02% range
+pub fn calm(seconds: i64) -> Vec<f64> {
+    (0..seconds * 4).map(|i| 2000. + (i % 3) as f64 * 0.2).collect()
+}
+
+// `seconds` of prices swinging by 2% every second
+pub fn spike(seconds: i64) -> Vec<f64> {
+    (0..seconds * 4).map(|i| if (i / 4) % 2 == 0 { 2000. } else { 2040. }).collect()
+}
+
+// evaluated after every message with no warmup, so a test only waits for the frames
+pub fn settings(stream_url: &str) -> SymbolSettings {
+    SymbolSettings {
+        atr_moving_average: MovingAverage::Ema,
+        atr_window_seconds: 10,
+        atr_threshold: 0.2,
+        atr_min_candles_percent: 0.8,
+        min_vol_usdt: 1000.,
+        evaluation: EvaluationConfig { mode: EvaluationMode::Event, warmup_seconds: 0, ..Default::default() },
+        latency: Default::default(),
+        ingestion: Default::default(),
+        orderbook: Default::default(),
+        liquidations: Default::default(),
+        mark_price: Default::default(),
+        open_interest: Default::default(),
+        regime: Default::default(),
+        reference: Default::default(),
+        reference_only: false,
+        persistence: Default::default(),
+        rest_url: None,
+        market: Default::default(),
+        stream_url: stream_url.to_string(),
+        contract_size: None,
+    }
+}
+
+// waits for the first snapshot of the symbol matching `done`
+pub async fn wait_for(hub: &Hub, symbol: &str, done: impl FnMut(&SymbolSnapshot) -> bool) -> SymbolSnapshot {
+    let mut snapshots = hub.watch(symbol).expect("symbol isn't registered");
+    let snapshot = timeout(Duration::from_secs(10), snapshots.wait_for(done))
+        .await
+        .expect("timed out waiting for the snapshot")
+        .expect("the symbol task is gone");
+    snapshot.clone()
+}
+
+// runs the symbols live until each one handled `messages` frames, then shuts them down
+// returns the snapshots at that point, in the order of `symbols`
+pub async fn run_until_messages(hub: &Hub, symbols: &[&str], settings: &SymbolSettings, messages: u64) -> Vec<SymbolSnapshot> {
+    let cancel = CancellationToken::new();
+    let tasks: Vec<_> = symbols
+        .iter()
+        .map(|symbol| {
+            let task = run(SymbolData::new(symbol, settings.clone()), hub.register(symbol), cancel.clone(), Source::Live, None);
+            tokio::spawn(task)
+        })
+        .collect();
+
+    let mut snapshots = vec![];
+    for symbol in symbols {
+        snapshots.push(wait_for(hub, symbol, |snapshot| snapshot.messages == messages).await);
+    }
+
+    cancel.cancel();
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+    snapshots
+}